@@ -0,0 +1,37 @@
+-- migrate:up
+CREATE TABLE IF NOT EXISTS blobs (
+    hash TEXT PRIMARY KEY,
+    key TEXT NOT NULL,
+    size BIGINT NOT NULL DEFAULT 0,
+    etag TEXT,
+    ref_count BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE IF EXISTS files
+ADD COLUMN IF NOT EXISTS blob_hash TEXT REFERENCES blobs (hash);
+
+CREATE INDEX IF NOT EXISTS files_blob_hash_index ON files (blob_hash);
+
+CREATE OR REPLACE FUNCTION update_blob_ref_count() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND OLD.blob_hash IS NOT NULL THEN
+        UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.blob_hash;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND NEW.blob_hash IS NOT NULL THEN
+        UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.blob_hash;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER files_blob_ref_count
+AFTER INSERT OR DELETE OR UPDATE OF blob_hash ON files
+FOR EACH ROW EXECUTE FUNCTION update_blob_ref_count();
+
+-- migrate:down
+DROP TRIGGER IF EXISTS files_blob_ref_count ON files;
+DROP FUNCTION IF EXISTS update_blob_ref_count();
+ALTER TABLE IF EXISTS files
+DROP COLUMN IF EXISTS blob_hash;
+DROP TABLE IF EXISTS blobs;
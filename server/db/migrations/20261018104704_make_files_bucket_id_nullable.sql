@@ -0,0 +1,7 @@
+-- migrate:up
+ALTER TABLE IF EXISTS files
+ALTER COLUMN bucket_id DROP NOT NULL;
+
+-- migrate:down
+ALTER TABLE IF EXISTS files
+ALTER COLUMN bucket_id SET NOT NULL;
\ No newline at end of file
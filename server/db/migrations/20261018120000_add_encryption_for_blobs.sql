@@ -0,0 +1,21 @@
+-- migrate:up
+ALTER TABLE IF EXISTS users
+ADD COLUMN IF NOT EXISTS encrypt_files BOOLEAN NOT NULL DEFAULT false;
+
+ALTER TABLE IF EXISTS blobs
+ADD COLUMN IF NOT EXISTS key_id TEXT,
+ADD COLUMN IF NOT EXISTS data_key BYTEA,
+ADD COLUMN IF NOT EXISTS nonce BYTEA;
+
+CREATE INDEX IF NOT EXISTS blobs_key_id_index ON blobs (key_id) WHERE key_id IS NOT NULL;
+
+-- migrate:down
+DROP INDEX IF EXISTS blobs_key_id_index;
+
+ALTER TABLE IF EXISTS blobs
+DROP COLUMN IF EXISTS key_id,
+DROP COLUMN IF EXISTS data_key,
+DROP COLUMN IF EXISTS nonce;
+
+ALTER TABLE IF EXISTS users
+DROP COLUMN IF EXISTS encrypt_files;
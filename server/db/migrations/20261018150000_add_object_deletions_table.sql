@@ -0,0 +1,8 @@
+-- migrate:up
+CREATE TABLE IF NOT EXISTS object_deletions (
+    key TEXT PRIMARY KEY,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+-- migrate:down
+DROP TABLE IF EXISTS object_deletions;
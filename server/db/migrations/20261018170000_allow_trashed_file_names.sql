@@ -0,0 +1,16 @@
+-- migrate:up
+ALTER TABLE IF EXISTS files
+DROP CONSTRAINT IF EXISTS unique_file_per_user_constraint;
+
+CREATE UNIQUE INDEX IF NOT EXISTS unique_file_per_user_index
+ON files (path, title, owner_id) WHERE deleted_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS files_deleted_at_index
+ON files (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- migrate:down
+DROP INDEX IF EXISTS files_deleted_at_index;
+DROP INDEX IF EXISTS unique_file_per_user_index;
+
+ALTER TABLE IF EXISTS files
+ADD CONSTRAINT unique_file_per_user_constraint UNIQUE (path, title, owner_id);
@@ -0,0 +1,33 @@
+-- migrate:up
+ALTER TABLE IF EXISTS files
+ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ;
+
+ALTER TABLE IF EXISTS users
+ADD COLUMN IF NOT EXISTS max_file_versions INTEGER NOT NULL DEFAULT 10;
+
+CREATE TABLE IF NOT EXISTS file_versions (
+    file_id UUID NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    size BIGINT NOT NULL DEFAULT 0,
+    hash TEXT,
+    etag TEXT,
+    blob_hash TEXT NOT NULL REFERENCES blobs (hash),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (file_id, version)
+);
+
+CREATE INDEX IF NOT EXISTS file_versions_blob_hash_index ON file_versions (blob_hash);
+
+CREATE TRIGGER file_versions_blob_ref_count
+AFTER INSERT OR DELETE OR UPDATE OF blob_hash ON file_versions
+FOR EACH ROW EXECUTE FUNCTION update_blob_ref_count();
+
+-- migrate:down
+DROP TRIGGER IF EXISTS file_versions_blob_ref_count ON file_versions;
+DROP TABLE IF EXISTS file_versions;
+ALTER TABLE IF EXISTS users
+DROP COLUMN IF EXISTS max_file_versions;
+ALTER TABLE IF EXISTS files
+DROP COLUMN IF EXISTS updated_at,
+DROP COLUMN IF EXISTS version;
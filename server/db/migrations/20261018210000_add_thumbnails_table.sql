@@ -0,0 +1,17 @@
+-- migrate:up
+CREATE TABLE IF NOT EXISTS thumbnails (
+    file_id UUID NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    size TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    type TEXT NOT NULL,
+    byte_size BIGINT NOT NULL DEFAULT 0,
+    key_id TEXT,
+    data_key BYTEA,
+    nonce BYTEA,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (file_id, size)
+);
+
+-- migrate:down
+DROP TABLE IF EXISTS thumbnails;
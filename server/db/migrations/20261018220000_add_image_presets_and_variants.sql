@@ -0,0 +1,30 @@
+-- migrate:up
+CREATE TABLE IF NOT EXISTS image_presets (
+    owner_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    fit TEXT NOT NULL,
+    format TEXT NOT NULL,
+    quality INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (owner_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS image_variants (
+    file_id UUID NOT NULL REFERENCES files (id) ON DELETE CASCADE,
+    variant TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    type TEXT NOT NULL,
+    byte_size BIGINT NOT NULL DEFAULT 0,
+    key_id TEXT,
+    data_key BYTEA,
+    nonce BYTEA,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (file_id, variant)
+);
+
+-- migrate:down
+DROP TABLE IF EXISTS image_variants;
+DROP TABLE IF EXISTS image_presets;
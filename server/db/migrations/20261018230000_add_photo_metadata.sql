@@ -0,0 +1,35 @@
+-- migrate:up
+ALTER TABLE IF EXISTS files
+ADD COLUMN IF NOT EXISTS camera_make TEXT,
+ADD COLUMN IF NOT EXISTS camera_model TEXT,
+ADD COLUMN IF NOT EXISTS taken_at TIMESTAMPTZ,
+ADD COLUMN IF NOT EXISTS image_width INTEGER,
+ADD COLUMN IF NOT EXISTS image_height INTEGER,
+ADD COLUMN IF NOT EXISTS orientation INTEGER,
+ADD COLUMN IF NOT EXISTS gps_latitude DOUBLE PRECISION,
+ADD COLUMN IF NOT EXISTS gps_longitude DOUBLE PRECISION;
+
+CREATE INDEX IF NOT EXISTS files_taken_at_index ON files (owner_id, taken_at)
+WHERE taken_at IS NOT NULL;
+
+ALTER TABLE IF EXISTS buckets
+ADD COLUMN IF NOT EXISTS strip_metadata BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE IF EXISTS users
+ADD COLUMN IF NOT EXISTS strip_metadata BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- migrate:down
+ALTER TABLE IF EXISTS users
+DROP COLUMN IF EXISTS strip_metadata;
+ALTER TABLE IF EXISTS buckets
+DROP COLUMN IF EXISTS strip_metadata;
+DROP INDEX IF EXISTS files_taken_at_index;
+ALTER TABLE IF EXISTS files
+DROP COLUMN IF EXISTS camera_make,
+DROP COLUMN IF EXISTS camera_model,
+DROP COLUMN IF EXISTS taken_at,
+DROP COLUMN IF EXISTS image_width,
+DROP COLUMN IF EXISTS image_height,
+DROP COLUMN IF EXISTS orientation,
+DROP COLUMN IF EXISTS gps_latitude,
+DROP COLUMN IF EXISTS gps_longitude;
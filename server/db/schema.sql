@@ -29,10 +29,46 @@ CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;
 COMMENT ON EXTENSION pg_trgm IS 'text similarity measurement and index searching based on trigrams';
 
 
+--
+-- Name: update_blob_ref_count(); Type: FUNCTION; Schema: public; Owner: -
+--
+
+CREATE FUNCTION public.update_blob_ref_count() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') AND OLD.blob_hash IS NOT NULL THEN
+        UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.blob_hash;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND NEW.blob_hash IS NOT NULL THEN
+        UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.blob_hash;
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+
 SET default_tablespace = '';
 
 SET default_table_access_method = heap;
 
+--
+-- Name: blobs; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.blobs (
+    hash text NOT NULL,
+    key text NOT NULL,
+    size bigint DEFAULT 0 NOT NULL,
+    etag text,
+    ref_count bigint DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
+    key_id text,
+    data_key bytea,
+    nonce bytea
+);
+
+
 --
 -- Name: buckets; Type: TABLE; Schema: public; Owner: -
 --
@@ -42,7 +78,8 @@ CREATE TABLE public.buckets (
     title text NOT NULL,
     created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
     updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
-    owner_id uuid NOT NULL
+    owner_id uuid NOT NULL,
+    strip_metadata boolean DEFAULT false NOT NULL
 );
 
 
@@ -61,8 +98,78 @@ CREATE TABLE public.files (
     size bigint DEFAULT 0 NOT NULL,
     path text NOT NULL,
     hash text,
-    bucket_id uuid NOT NULL,
-    etag text
+    bucket_id uuid,
+    etag text,
+    blob_hash text,
+    version integer DEFAULT 1 NOT NULL,
+    updated_at timestamp with time zone,
+    camera_make text,
+    camera_model text,
+    taken_at timestamp with time zone,
+    image_width integer,
+    image_height integer,
+    orientation integer,
+    gps_latitude double precision,
+    gps_longitude double precision
+);
+
+
+--
+-- Name: file_versions; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.file_versions (
+    file_id uuid NOT NULL,
+    version integer NOT NULL,
+    size bigint DEFAULT 0 NOT NULL,
+    hash text,
+    etag text,
+    blob_hash text NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+
+--
+-- Name: image_presets; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.image_presets (
+    owner_id uuid NOT NULL,
+    name text NOT NULL,
+    width integer,
+    height integer,
+    fit text NOT NULL,
+    format text NOT NULL,
+    quality integer NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+
+--
+-- Name: image_variants; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.image_variants (
+    file_id uuid NOT NULL,
+    variant text NOT NULL,
+    version integer NOT NULL,
+    key text NOT NULL,
+    type text NOT NULL,
+    byte_size bigint DEFAULT 0 NOT NULL,
+    key_id text,
+    data_key bytea,
+    nonce bytea,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+
+--
+-- Name: object_deletions; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.object_deletions (
+    key text NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
 );
 
 
@@ -86,6 +193,24 @@ CREATE TABLE public.tags (
 );
 
 
+--
+-- Name: thumbnails; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.thumbnails (
+    file_id uuid NOT NULL,
+    size text NOT NULL,
+    version integer NOT NULL,
+    key text NOT NULL,
+    type text NOT NULL,
+    byte_size bigint DEFAULT 0 NOT NULL,
+    key_id text,
+    data_key bytea,
+    nonce bytea,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+
 --
 -- Name: users; Type: TABLE; Schema: public; Owner: -
 --
@@ -97,10 +222,21 @@ CREATE TABLE public.users (
     is_verified boolean DEFAULT false NOT NULL,
     username text NOT NULL,
     email text NOT NULL,
-    pw_hsh text NOT NULL
+    pw_hsh text NOT NULL,
+    encrypt_files boolean DEFAULT false NOT NULL,
+    max_file_versions integer DEFAULT 10 NOT NULL,
+    strip_metadata boolean DEFAULT false NOT NULL
 );
 
 
+--
+-- Name: blobs blobs_pkey; Type: CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.blobs
+    ADD CONSTRAINT blobs_pkey PRIMARY KEY (hash);
+
+
 --
 -- Name: buckets buckets_pkey; Type: CONSTRAINT; Schema: public; Owner: -
 --
@@ -117,6 +253,38 @@ ALTER TABLE ONLY public.files
     ADD CONSTRAINT files_pkey PRIMARY KEY (id);
 
 
+--
+-- Name: file_versions file_versions_pkey; Type: CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.file_versions
+    ADD CONSTRAINT file_versions_pkey PRIMARY KEY (file_id, version);
+
+
+--
+-- Name: image_presets image_presets_pkey; Type: CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.image_presets
+    ADD CONSTRAINT image_presets_pkey PRIMARY KEY (owner_id, name);
+
+
+--
+-- Name: image_variants image_variants_pkey; Type: CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.image_variants
+    ADD CONSTRAINT image_variants_pkey PRIMARY KEY (file_id, variant);
+
+
+--
+-- Name: object_deletions object_deletions_pkey; Type: CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.object_deletions
+    ADD CONSTRAINT object_deletions_pkey PRIMARY KEY (key);
+
+
 --
 -- Name: schema_migrations schema_migrations_pkey; Type: CONSTRAINT; Schema: public; Owner: -
 --
@@ -142,11 +310,11 @@ ALTER TABLE ONLY public.tags
 
 
 --
--- Name: files unique_file_per_user_constraint; Type: CONSTRAINT; Schema: public; Owner: -
+-- Name: thumbnails thumbnails_pkey; Type: CONSTRAINT; Schema: public; Owner: -
 --
 
-ALTER TABLE ONLY public.files
-    ADD CONSTRAINT unique_file_per_user_constraint UNIQUE (path, title, owner_id);
+ALTER TABLE ONLY public.thumbnails
+    ADD CONSTRAINT thumbnails_pkey PRIMARY KEY (file_id, size);
 
 
 --
@@ -173,6 +341,41 @@ ALTER TABLE ONLY public.users
     ADD CONSTRAINT users_username_key UNIQUE (username);
 
 
+--
+-- Name: blobs_key_id_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE INDEX blobs_key_id_index ON public.blobs USING btree (key_id) WHERE (key_id IS NOT NULL);
+
+
+--
+-- Name: file_versions_blob_hash_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE INDEX file_versions_blob_hash_index ON public.file_versions USING btree (blob_hash);
+
+
+--
+-- Name: files_blob_hash_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE INDEX files_blob_hash_index ON public.files USING btree (blob_hash);
+
+
+--
+-- Name: files_deleted_at_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE INDEX files_deleted_at_index ON public.files USING btree (deleted_at) WHERE (deleted_at IS NOT NULL);
+
+
+--
+-- Name: files_taken_at_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE INDEX files_taken_at_index ON public.files USING btree (owner_id, taken_at) WHERE (taken_at IS NOT NULL);
+
+
 --
 -- Name: files_title_trgm_index; Type: INDEX; Schema: public; Owner: -
 --
@@ -180,6 +383,27 @@ ALTER TABLE ONLY public.users
 CREATE INDEX files_title_trgm_index ON public.files USING gin (title public.gin_trgm_ops);
 
 
+--
+-- Name: unique_file_per_user_index; Type: INDEX; Schema: public; Owner: -
+--
+
+CREATE UNIQUE INDEX unique_file_per_user_index ON public.files USING btree (path, title, owner_id) WHERE (deleted_at IS NULL);
+
+
+--
+-- Name: file_versions file_versions_blob_ref_count; Type: TRIGGER; Schema: public; Owner: -
+--
+
+CREATE TRIGGER file_versions_blob_ref_count AFTER INSERT OR DELETE OR UPDATE OF blob_hash ON public.file_versions FOR EACH ROW EXECUTE FUNCTION public.update_blob_ref_count();
+
+
+--
+-- Name: files files_blob_ref_count; Type: TRIGGER; Schema: public; Owner: -
+--
+
+CREATE TRIGGER files_blob_ref_count AFTER INSERT OR DELETE OR UPDATE OF blob_hash ON public.files FOR EACH ROW EXECUTE FUNCTION public.update_blob_ref_count();
+
+
 --
 -- Name: buckets buckets_owner_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
 --
@@ -188,6 +412,30 @@ ALTER TABLE ONLY public.buckets
     ADD CONSTRAINT buckets_owner_id_fkey FOREIGN KEY (owner_id) REFERENCES public.users(id) ON DELETE SET NULL;
 
 
+--
+-- Name: file_versions file_versions_blob_hash_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.file_versions
+    ADD CONSTRAINT file_versions_blob_hash_fkey FOREIGN KEY (blob_hash) REFERENCES public.blobs(hash);
+
+
+--
+-- Name: file_versions file_versions_file_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.file_versions
+    ADD CONSTRAINT file_versions_file_id_fkey FOREIGN KEY (file_id) REFERENCES public.files(id) ON DELETE CASCADE;
+
+
+--
+-- Name: files files_blob_hash_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.files
+    ADD CONSTRAINT files_blob_hash_fkey FOREIGN KEY (blob_hash) REFERENCES public.blobs(hash);
+
+
 --
 -- Name: files files_bucket_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
 --
@@ -204,6 +452,22 @@ ALTER TABLE ONLY public.files
     ADD CONSTRAINT files_owner_id_fkey FOREIGN KEY (owner_id) REFERENCES public.users(id) ON DELETE CASCADE;
 
 
+--
+-- Name: image_presets image_presets_owner_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.image_presets
+    ADD CONSTRAINT image_presets_owner_id_fkey FOREIGN KEY (owner_id) REFERENCES public.users(id) ON DELETE CASCADE;
+
+
+--
+-- Name: image_variants image_variants_file_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.image_variants
+    ADD CONSTRAINT image_variants_file_id_fkey FOREIGN KEY (file_id) REFERENCES public.files(id) ON DELETE CASCADE;
+
+
 --
 -- Name: tags tags_owner_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
 --
@@ -212,6 +476,14 @@ ALTER TABLE ONLY public.tags
     ADD CONSTRAINT tags_owner_id_fkey FOREIGN KEY (owner_id) REFERENCES public.users(id) ON DELETE CASCADE;
 
 
+--
+-- Name: thumbnails thumbnails_file_id_fkey; Type: FK CONSTRAINT; Schema: public; Owner: -
+--
+
+ALTER TABLE ONLY public.thumbnails
+    ADD CONSTRAINT thumbnails_file_id_fkey FOREIGN KEY (file_id) REFERENCES public.files(id) ON DELETE CASCADE;
+
+
 --
 -- PostgreSQL database dump complete
 --
@@ -233,4 +505,13 @@ INSERT INTO public.schema_migrations (version) VALUES
     ('20260110103844'),
     ('20260112075724'),
     ('20260112080233'),
-    ('20260112080337');
+    ('20260112080337'),
+    ('20261018090000'),
+    ('20261018104704'),
+    ('20261018120000'),
+    ('20261018150000'),
+    ('20261018170000'),
+    ('20261018190000'),
+    ('20261018210000'),
+    ('20261018220000'),
+    ('20261018230000');
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use deadpool_redis::{
+    Connection, Pool as DflyPool,
+    redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions},
+};
+
+use crate::{enums::errors::CacheError, traits::cache_traits::CacheStore};
+
+pub struct DflyCache {
+    pool: DflyPool,
+}
+
+impl DflyCache {
+    pub fn new(pool: DflyPool) -> Self {
+        Self { pool }
+    }
+
+    async fn conn(&self) -> Result<Connection, CacheError> {
+        tracing::debug!("ESTABLISHING DRAGONFLY CONNECTION");
+        self.pool
+            .get()
+            .await
+            .map_err(|err| CacheError::Connection(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl CacheStore for DflyCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let value: Option<String> = self.conn().await?.get(key).await?;
+        Ok(value)
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, seconds: u64) -> Result<(), CacheError> {
+        let _: () = self.conn().await?.set_ex(key, value, seconds).await?;
+        Ok(())
+    }
+
+    async fn set_nx_ex(&self, key: &str, value: &str, seconds: u64) -> Result<bool, CacheError> {
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(seconds));
+        let written: Option<String> = self.conn().await?.set_options(key, value, options).await?;
+        Ok(written.is_some())
+    }
+
+    async fn del(&self, key: &str) -> Result<(), CacheError> {
+        let _: () = self.conn().await?.del(key).await?;
+        Ok(())
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> Result<(), CacheError> {
+        let _: () = self.conn().await?.sadd(key, member).await?;
+        Ok(())
+    }
+
+    async fn smembers(&self, key: &str) -> Result<Vec<String>, CacheError> {
+        let members: Vec<String> = self.conn().await?.smembers(key).await?;
+        Ok(members)
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{enums::errors::CacheError, traits::cache_traits::CacheStore};
+
+/// In-process cache for tests and single-node development setups.
+#[derive(Default)]
+pub struct MemoryCache {
+    values: Mutex<HashMap<String, (String, Instant)>>,
+    sets: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        let mut values = self.values.lock().unwrap();
+        match values.get(key) {
+            Some((_, expires_at)) if *expires_at <= Instant::now() => {
+                values.remove(key);
+                Ok(None)
+            }
+            Some((value, _)) => Ok(Some(value.clone())),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, seconds: u64) -> Result<(), CacheError> {
+        let expires_at = Instant::now() + Duration::from_secs(seconds);
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (value.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn set_nx_ex(&self, key: &str, value: &str, seconds: u64) -> Result<bool, CacheError> {
+        let now = Instant::now();
+        let mut values = self.values.lock().unwrap();
+        if values
+            .get(key)
+            .is_some_and(|(_, expires_at)| *expires_at > now)
+        {
+            return Ok(false);
+        }
+
+        values.insert(
+            key.to_string(),
+            (value.to_string(), now + Duration::from_secs(seconds)),
+        );
+        Ok(true)
+    }
+
+    async fn del(&self, key: &str) -> Result<(), CacheError> {
+        self.values.lock().unwrap().remove(key);
+        self.sets.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> Result<(), CacheError> {
+        self.sets
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .insert(member.to_string());
+        Ok(())
+    }
+
+    async fn smembers(&self, key: &str) -> Result<Vec<String>, CacheError> {
+        Ok(self
+            .sets
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,2 @@
+pub mod dfly_cache;
+pub mod memory_cache;
@@ -1,13 +1,71 @@
 use fancy_regex::Regex;
 use once_cell::sync::Lazy;
 
+use crate::enums::file_enums::MismatchPolicy;
+
 pub const DUMMY_PASSWORD: &str = "DUmMY_P@sSW0d579_0";
 pub const AUTH_SESSION_TIME: i32 = 29700; // 8 hours 15 mins
 pub static PASSWORD_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(?=.*[a-z])(?=.*[A-Z])(?=.*\d)(?=.*[^A-Za-z0-9]).{12,}$").unwrap());
+pub const UPLOAD_PART_SIZE: usize = 8 * 1024 * 1024; // S3 requires at least 5 MiB per part
+pub const MAX_RANGES: usize = 16; // larger multi-range requests get the whole file
+pub const MAX_UPLOAD_PARTS: usize = 10_000; // S3 limit for a multipart upload
+pub const MAX_FILE_VERSIONS: i32 = 100; // highest per-user version limit
+pub const MAX_INSERT_ATTEMPTS: usize = 5; // tries to claim a file name that keeps changing hands
+pub const DIRECT_UPLOAD_URL_TTL: u64 = 60 * 60; // presigned URLs stay valid for an hour
+pub const DIRECT_UPLOAD_TTL: u64 = 60 * 60 * 24; // pending direct uploads are forgotten after a day
+pub const ENCRYPTION_CHUNK_SIZE: usize = 64 * 1024; // plaintext per sealed chunk, divides UPLOAD_PART_SIZE
+pub const ENCRYPTION_TAG_SIZE: usize = 16; // Poly1305 tag appended to every chunk
+pub const KEY_ROTATION_BATCH: i64 = 500; // blobs re-wrapped per query by `rotate-keys`
+pub const KEY_MIGRATION_BATCH: i64 = 500; // files moved per query by `migrate-keys`
+pub const ARCHIVE_PIPE_SIZE: usize = 64 * 1024; // archive bytes buffered between storage reads and the response
+pub const MAX_ARCHIVE_ENTRIES: usize = 10_000; // entries one extraction may create
+pub const MAX_EXTRACTED_SIZE: u64 = 4 * 1024 * 1024 * 1024; // uncompressed bytes one extraction may write
+pub const MAX_COMPRESSION_RATIO: u64 = 100; // per entry, counting at least 1 KiB compressed
+pub const MAX_ZIP_DIRECTORY_SIZE: u64 = 16 * 1024 * 1024; // central directories read into memory
+pub const MAX_RENDER_SOURCE_SIZE: i64 = 64 * 1024 * 1024; // larger images get no thumbnails or transforms
+pub const MAX_RENDER_ALLOC: u64 = 512 * 1024 * 1024; // decoded pixels of one source image
+pub const RENDER_WORKERS: usize = 2; // images rendered at the same time
+pub const THUMBNAIL_QUALITY: u8 = 80; // JPEG quality of opaque thumbnails
+pub const DEFAULT_IMAGE_QUALITY: u8 = 80; // JPEG quality of transforms that name none
+pub const MAX_IMAGE_DIMENSION: u32 = 4096; // widest or tallest transformed image
+pub const MAX_IMAGE_PRESETS: i64 = 32; // transform presets per user
+pub const SNIFF_SIZE: usize = 1024; // leading bytes of an upload checked against its claimed type
+pub const DELETE_BATCH_SIZE: usize = 1000; // S3 limit for a DeleteObjects request
+pub const TRASH_PURGE_BATCH: i64 = 500; // trashed files removed per query by the purge
+pub const TRASH_PURGE_INTERVAL: u64 = 60 * 60; // the purge runs hourly
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination";
+pub const TUS_UPLOAD_TTL: u64 = 60 * 60 * 24; // 24 hours since the last PATCH
+pub const TUS_LOCK_TTL: u64 = 60 * 5; // refreshed after every uploaded part
+pub const TUS_RESUMABLE: &str = "tus-resumable";
+pub const TUS_VERSION_HEADER: &str = "tus-version";
+pub const TUS_EXTENSION_HEADER: &str = "tus-extension";
+pub const TUS_MAX_SIZE: &str = "tus-max-size";
+pub const UPLOAD_OFFSET: &str = "upload-offset";
+pub const UPLOAD_LENGTH: &str = "upload-length";
+pub const UPLOAD_METADATA: &str = "upload-metadata";
 pub static MAX_FILE_SIZE: Lazy<usize> = Lazy::new(|| {
     std::env::var("MAX_FILE_SIZE")
         .expect("Env var `MAX_FILE_SIZE` not found")
         .parse::<usize>()
         .expect("COULD NOT PARSE MAX_FILE_SIZE ENV VAR AS NUMBER")
 });
+pub static TYPE_MISMATCH_POLICY: Lazy<MismatchPolicy> = Lazy::new(|| {
+    std::env::var("TYPE_MISMATCH_POLICY")
+        .map(|value| {
+            value
+                .parse::<MismatchPolicy>()
+                .expect("COULD NOT PARSE TYPE_MISMATCH_POLICY ENV VAR AS flag OR reject")
+        })
+        .unwrap_or_default()
+});
+pub static TRASH_RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .map(|value| {
+            value
+                .parse::<i64>()
+                .expect("COULD NOT PARSE TRASH_RETENTION_DAYS ENV VAR AS NUMBER")
+        })
+        .unwrap_or(30)
+});
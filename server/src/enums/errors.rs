@@ -1,7 +1,8 @@
 use axum::http::StatusCode;
 use deadpool_redis::redis::RedisError;
+use image::ImageError;
 use thiserror::Error;
-use tokio_postgres::Error as DBError;
+use tokio_postgres::{Error as DBError, error::SqlState};
 use tracing::{error, warn};
 use uuid::Uuid;
 
@@ -14,9 +15,70 @@ pub enum AppError {
     #[error("Unauthorized.")]
     Unauthorized,
     #[error("You are not permitted to perform this action.")]
+    #[allow(dead_code)]
     Forbidden,
     #[error("There was an error registering your account.")]
     RegistrationError,
+    #[error("The requested resource was not found.")]
+    NotFound,
+    #[error("The resource was modified by another request.")]
+    Conflict,
+    #[error("The resource already exists.")]
+    AlreadyExists,
+    #[error("The request body is too large.")]
+    PayloadTooLarge,
+    #[error("This operation is not supported.")]
+    NotSupported,
+}
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Object not found - {0}")]
+    NotFound(String),
+    #[error("Invalid object key - {0}")]
+    InvalidKey(String),
+    #[error("Operation not supported by the storage backend - {0}")]
+    Unsupported(&'static str),
+    #[error("Encryption failed - {0}")]
+    Encryption(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Backend(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Invalid archive - {0}")]
+    Invalid(String),
+    #[error("Unsupported archive feature - {0}")]
+    Unsupported(String),
+    #[error("Archive exceeds the extraction limits - {0}")]
+    LimitExceeded(String),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Error, Debug)]
+pub enum RenderError {
+    #[error("Image cannot be rendered - {0}")]
+    Image(#[from] ImageError),
+    #[error("Image of {0} bytes is too large to render")]
+    TooLarge(i64),
+    #[error("Metadata cannot be stripped - {0}")]
+    Strip(#[from] img_parts::Error),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Database(#[from] DBError),
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("{0}")]
+    Connection(String),
+    #[error(transparent)]
+    Command(#[from] RedisError),
 }
 
 impl AppError {
@@ -53,6 +115,7 @@ impl AppError {
         }
     }
     #[track_caller]
+    #[allow(dead_code)]
     pub fn forbidden_response(err: impl ToString) -> AppErrorResponse {
         let location = std::panic::Location::caller();
 
@@ -69,6 +132,70 @@ impl AppError {
         }
     }
     #[track_caller]
+    pub fn bad_request_response(err: impl ToString) -> AppErrorResponse {
+        let location = std::panic::Location::caller();
+
+        warn!(
+            message = err.to_string(),
+            kind = "BAD REQUEST RESPONSE",
+            call_path = format!("{} -> {}", location.file(), location.line()),
+            log_id = Uuid::new_v4().to_string()
+        );
+        AppErrorResponse {
+            status_code: StatusCode::BAD_REQUEST,
+            ok: false,
+            message: AppError::ErrorWithRequest.to_string(),
+        }
+    }
+    #[track_caller]
+    pub fn not_found_response(err: impl ToString) -> AppErrorResponse {
+        let location = std::panic::Location::caller();
+
+        warn!(
+            message = err.to_string(),
+            kind = "NOT FOUND RESPONSE",
+            call_path = format!("{} -> {}", location.file(), location.line()),
+            log_id = Uuid::new_v4().to_string()
+        );
+        AppErrorResponse {
+            status_code: StatusCode::NOT_FOUND,
+            ok: false,
+            message: AppError::NotFound.to_string(),
+        }
+    }
+    #[track_caller]
+    pub fn conflict_response(err: impl ToString) -> AppErrorResponse {
+        let location = std::panic::Location::caller();
+
+        warn!(
+            message = err.to_string(),
+            kind = "CONFLICT RESPONSE",
+            call_path = format!("{} -> {}", location.file(), location.line()),
+            log_id = Uuid::new_v4().to_string()
+        );
+        AppErrorResponse {
+            status_code: StatusCode::CONFLICT,
+            ok: false,
+            message: AppError::Conflict.to_string(),
+        }
+    }
+    #[track_caller]
+    pub fn payload_too_large_response(err: impl ToString) -> AppErrorResponse {
+        let location = std::panic::Location::caller();
+
+        warn!(
+            message = err.to_string(),
+            kind = "PAYLOAD TOO LARGE RESPONSE",
+            call_path = format!("{} -> {}", location.file(), location.line()),
+            log_id = Uuid::new_v4().to_string()
+        );
+        AppErrorResponse {
+            status_code: StatusCode::PAYLOAD_TOO_LARGE,
+            ok: false,
+            message: AppError::PayloadTooLarge.to_string(),
+        }
+    }
+    #[track_caller]
     pub fn registration_response(err: impl ToString) -> AppErrorResponse {
         let location = std::panic::Location::caller();
 
@@ -98,30 +225,66 @@ impl AppError {
             call_path = format!("{} -> {}", location.file(), location.line()),
             log_id = Uuid::new_v4().to_string()
         );
-        AppErrorResponse {
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            ok: false,
-            message: AppError::ErrorWithRequest.to_string(),
+        match err.code() {
+            Some(&SqlState::UNIQUE_VIOLATION) => AppErrorResponse {
+                status_code: StatusCode::CONFLICT,
+                ok: false,
+                message: AppError::AlreadyExists.to_string(),
+            },
+            _ => AppErrorResponse {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                ok: false,
+                message: AppError::ErrorWithRequest.to_string(),
+            },
         }
     }
     #[track_caller]
-    pub fn s3_error(err: impl ToString) -> AppErrorResponse {
-        let location: &'static std::panic::Location<'static> = std::panic::Location::caller();
-        let err_str = err.to_string();
+    pub fn storage_error(err: StorageError) -> AppErrorResponse {
+        let location = std::panic::Location::caller();
         error!(
-            message = err_str,
-            kind = "S3 ERROR",
+            message = err.to_string(),
+            kind = "STORAGE ERROR",
             call_path = format!("{} -> {}", location.file(), location.line()),
             log_id = Uuid::new_v4().to_string()
         );
-        AppErrorResponse {
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            ok: false,
-            message: AppError::ErrorWithRequest.to_string(),
+        match err {
+            StorageError::NotFound(_) => AppErrorResponse {
+                status_code: StatusCode::NOT_FOUND,
+                ok: false,
+                message: AppError::NotFound.to_string(),
+            },
+            StorageError::Unsupported(_) => AppErrorResponse {
+                status_code: StatusCode::NOT_IMPLEMENTED,
+                ok: false,
+                message: AppError::NotSupported.to_string(),
+            },
+            _ => AppErrorResponse {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                ok: false,
+                message: AppError::ErrorWithRequest.to_string(),
+            },
+        }
+    }
+    #[track_caller]
+    pub fn archive_error(err: ArchiveError) -> AppErrorResponse {
+        match err {
+            ArchiveError::Storage(err) => AppError::storage_error(err),
+            ArchiveError::LimitExceeded(_) => AppError::payload_too_large_response(err),
+            _ => AppError::bad_request_response(err),
+        }
+    }
+    /// Images that cannot be rendered have no thumbnail or transform, which clients show as no
+    /// preview.
+    #[track_caller]
+    pub fn render_error(err: RenderError) -> AppErrorResponse {
+        match err {
+            RenderError::Storage(err) => AppError::storage_error(err),
+            RenderError::Database(err) => AppError::db_error(err),
+            _ => AppError::not_found_response(err),
         }
     }
     #[track_caller]
-    pub fn dfly_error(err: RedisError) -> AppErrorResponse {
+    pub fn dfly_error(err: CacheError) -> AppErrorResponse {
         let location = std::panic::Location::caller();
         error!(
             message = err.to_string(),
@@ -136,6 +299,7 @@ impl AppError {
         }
     }
     #[track_caller]
+    #[allow(dead_code)]
     pub fn queue_error(err: impl ToString) -> AppErrorResponse {
         let location = std::panic::Location::caller();
 
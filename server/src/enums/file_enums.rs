@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString};
 use tokio_postgres::types::FromSql;
 
+use crate::utils::file_type_utils::FILE_TYPES;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, EnumString, Display)]
 #[serde(rename_all = "lowercase")]
 #[strum(serialize_all = "lowercase")]
@@ -61,131 +63,84 @@ pub enum FileTypes {
     Otf,
     Woff,
     Exe,
+    /// A type added through `FILE_TYPES_CONFIG`, by its name.
+    #[strum(default)]
+    Custom(String),
     Other(String),
 }
 
 impl FileTypes {
+    /// MIME type the file is served with.
     pub fn content_type(&self) -> &'static str {
-        match self {
-            FileTypes::Png => "image/png",
-            FileTypes::Jpg | FileTypes::Jpeg => "image/jpeg",
-            FileTypes::Webp => "image/webp",
-            FileTypes::Gif => "image/gif",
-            FileTypes::Svg => "image/svg+xml",
-            FileTypes::Pdf => "application/pdf",
-            FileTypes::Doc => "application/msword",
-            FileTypes::Docx => {
-                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
-            }
-            FileTypes::Txt => "text/plain",
-            FileTypes::Xls => "application/vnd.ms-excel",
-            FileTypes::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
-            FileTypes::Mp3 => "audio/mpeg",
-            FileTypes::Wav => "audio/wav",
-            FileTypes::Ogg => "audio/ogg",
-            FileTypes::Flac => "audio/flac",
-            FileTypes::Mp4 => "video/mp4",
-            FileTypes::Mov => "video/quicktime",
-            FileTypes::Avi => "video/x-msvideo",
-            FileTypes::Webm => "video/webm",
-            FileTypes::Zip => "application/zip",
-            FileTypes::Rar => "application/vnd.rar",
-            FileTypes::Json => "application/json",
-            FileTypes::Csv => "text/csv",
-            FileTypes::Md => "text/markdown",
-            FileTypes::Mdx => "text/markdown",
-            FileTypes::Xml => "application/xml",
-            FileTypes::Yml => "application/x-yaml",
-            FileTypes::Sql => "application/sql",
-            FileTypes::Bmp => "image/bmp",
-            FileTypes::Heic => "image/heic",
-            FileTypes::Raw => "image/x-raw",
-            FileTypes::Tiff => "image/tiff",
-            FileTypes::Psd => "image/vnd.adobe.photoshop",
-            FileTypes::Aac => "audio/aac",
-            FileTypes::M4p => "audio/mp4",
-            FileTypes::Opus => "audio/opus",
-            FileTypes::Js => "text/javascript",
-            FileTypes::Ts => "text/typescript",
-            FileTypes::Jsx => "text/jsx",
-            FileTypes::Tsx => "text/tsx",
-            FileTypes::Css => "text/css",
-            FileTypes::Scss => "text/x-scss",
-            FileTypes::Sass => "text/x-sass",
-            FileTypes::Py => "text/x-python",
-            FileTypes::Rb => "text/x-ruby",
-            FileTypes::Php => "application/x-httpd-php",
-            FileTypes::Sh => "application/x-sh",
-            FileTypes::Java => "text/x-java-source",
-            FileTypes::Cs => "text/x-csharp",
-            FileTypes::Html => "text/html",
-            FileTypes::Ttf => "font/ttf",
-            FileTypes::Otf => "font/otf",
-            FileTypes::Woff => "font/woff",
-            FileTypes::Exe => "application/vnd.microsoft.portable-executable",
-            FileTypes::Other(_) => "application/octet-stream",
-        }
+        FILE_TYPES
+            .by_name(&self.to_string())
+            .map_or("application/octet-stream", |entry| entry.content_type())
     }
 
     pub fn from_mime(value: &str) -> Self {
-        match value {
-            "image/png" => FileTypes::Png,
-            "image/jpeg" => FileTypes::Jpg,
-            "image/webp" => FileTypes::Webp,
-            "image/gif" => FileTypes::Gif,
-            "image/svg+xml" => FileTypes::Svg,
-            "application/pdf" => FileTypes::Pdf,
-            "application/msword" => FileTypes::Doc,
-            "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
-                FileTypes::Docx
-            }
-            "text/plain" => FileTypes::Txt,
-            "application/vnd.ms-excel" => FileTypes::Xls,
-            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => FileTypes::Xlsx,
-            "audio/mpeg" => FileTypes::Mp3,
-            "audio/wav" => FileTypes::Wav,
-            "audio/ogg" => FileTypes::Ogg,
-            "audio/opus" => FileTypes::Opus,
-            "audio/flac" => FileTypes::Flac,
-            "video/mp4" => FileTypes::Mp4,
-            "video/quicktime" => FileTypes::Mov,
-            "video/x-msvideo" => FileTypes::Avi,
-            "video/webm" => FileTypes::Webm,
-            "application/zip" => FileTypes::Zip,
-            "application/vnd.rar" => FileTypes::Rar,
-            "application/json" => FileTypes::Json,
-            "text/csv" => FileTypes::Csv,
-            "text/markdown" => FileTypes::Md,
-            "application/xml" => FileTypes::Xml,
-            "application/x-yaml" => FileTypes::Yml,
-            "application/sql" => FileTypes::Sql,
-            "image/bmp" => FileTypes::Bmp,
-            "image/heic" => FileTypes::Heic,
-            "image/x-raw" => FileTypes::Raw,
-            "image/tiff" => FileTypes::Tiff,
-            "image/vnd.adobe.photoshop" => FileTypes::Psd,
-            "audio/aac" => FileTypes::Aac,
-            "audio/mp4" => FileTypes::M4p,
-            "application/javascript" => FileTypes::Js,
-            "text/javascript" => FileTypes::Js,
-            "text/typescript" => FileTypes::Ts,
-            "text/jsx" => FileTypes::Jsx,
-            "text/tsx" => FileTypes::Tsx,
-            "text/css" => FileTypes::Css,
-            "text/x-scss" => FileTypes::Scss,
-            "text/x-sass" => FileTypes::Sass,
-            "text/x-python" => FileTypes::Py,
-            "text/x-ruby" => FileTypes::Rb,
-            "application/x-httpd-php" => FileTypes::Php,
-            "application/x-sh" => FileTypes::Sh,
-            "text/x-java-source" => FileTypes::Java,
-            "text/x-csharp" => FileTypes::Cs,
-            "text/html" => FileTypes::Html,
-            "font/ttf" => FileTypes::Ttf,
-            "font/otf" => FileTypes::Otf,
-            "font/woff" => FileTypes::Woff,
-            "application/vnd.microsoft.portable-executable" => FileTypes::Exe,
-            _ => FileTypes::Other(value.to_string()),
+        FILE_TYPES.by_mime(value).map_or_else(
+            || FileTypes::Other(value.to_string()),
+            |entry| entry.file_type(),
+        )
+    }
+
+    /// Type for a file extension.
+    pub fn from_extension(value: &str) -> Self {
+        FILE_TYPES.by_extension(value).map_or_else(
+            || FileTypes::Other(value.to_lowercase()),
+            |entry| entry.file_type(),
+        )
+    }
+
+    /// Type for its name in the `type` column.
+    pub fn from_name(value: &str) -> Self {
+        FILE_TYPES.by_name(value).map_or_else(
+            || FileTypes::Other(value.to_string()),
+            |entry| entry.file_type(),
+        )
+    }
+
+    /// Image types the server decodes, for thumbnails and transforms.
+    pub fn is_renderable(&self) -> bool {
+        matches!(
+            self,
+            FileTypes::Png
+                | FileTypes::Jpg
+                | FileTypes::Jpeg
+                | FileTypes::Webp
+                | FileTypes::Gif
+                | FileTypes::Bmp
+                | FileTypes::Tiff
+        )
+    }
+
+    /// Image types photo metadata is read from.
+    pub fn has_exif(&self) -> bool {
+        matches!(
+            self,
+            FileTypes::Jpg
+                | FileTypes::Jpeg
+                | FileTypes::Tiff
+                | FileTypes::Heic
+                | FileTypes::Png
+                | FileTypes::Webp
+        )
+    }
+
+    /// Types with EXIF the server can strip. HEIC images can only be served as they are.
+    pub fn is_strippable(&self) -> bool {
+        self.has_exif() && *self != FileTypes::Heic
+    }
+
+    /// Type of a file going by the extension of its name. Names without a known extension
+    /// get the generic binary type, as uploads without a known content type do.
+    pub fn from_file_name(name: &str) -> Self {
+        match name
+            .rsplit_once('.')
+            .map(|(_, extension)| Self::from_extension(extension))
+        {
+            Some(FileTypes::Other(_)) | None => Self::from_mime("application/octet-stream"),
+            Some(file_type) => file_type,
         }
     }
 }
@@ -195,67 +150,163 @@ impl<'a> FromSql<'a> for FileTypes {
         _ty: &tokio_postgres::types::Type,
         raw: &'a [u8],
     ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        match std::str::from_utf8(raw)?.to_lowercase().as_str() {
-            "png" => Ok(FileTypes::Png),
-            "jpg" => Ok(FileTypes::Jpg),
-            "jpeg" => Ok(FileTypes::Jpeg),
-            "webp" => Ok(FileTypes::Webp),
-            "gif" => Ok(FileTypes::Gif),
-            "svg" => Ok(FileTypes::Svg),
-            "pdf" => Ok(FileTypes::Pdf),
-            "doc" => Ok(FileTypes::Doc),
-            "docx" => Ok(FileTypes::Docx),
-            "txt" => Ok(FileTypes::Txt),
-            "xls" => Ok(FileTypes::Xls),
-            "xlsx" => Ok(FileTypes::Xlsx),
-            "mp3" => Ok(FileTypes::Mp3),
-            "wav" => Ok(FileTypes::Wav),
-            "ogg" => Ok(FileTypes::Ogg),
-            "opus" => Ok(FileTypes::Opus),
-            "flac" => Ok(FileTypes::Flac),
-            "mp4" => Ok(FileTypes::Mp4),
-            "mov" => Ok(FileTypes::Mov),
-            "avi" => Ok(FileTypes::Avi),
-            "webm" => Ok(FileTypes::Webm),
-            "zip" => Ok(FileTypes::Zip),
-            "rar" => Ok(FileTypes::Rar),
-            "json" => Ok(FileTypes::Json),
-            "csv" => Ok(FileTypes::Csv),
-            "md" => Ok(FileTypes::Md),
-            "mdx" => Ok(FileTypes::Mdx),
-            "xml" => Ok(FileTypes::Xml),
-            "yml" | "yaml" => Ok(FileTypes::Yml),
-            "sql" => Ok(FileTypes::Sql),
-            "bmp" => Ok(FileTypes::Bmp),
-            "heic" => Ok(FileTypes::Heic),
-            "raw" => Ok(FileTypes::Raw),
-            "tiff" | "tif" => Ok(FileTypes::Tiff),
-            "psd" => Ok(FileTypes::Psd),
-            "aac" => Ok(FileTypes::Aac),
-            "m4p" => Ok(FileTypes::M4p),
-            "js" => Ok(FileTypes::Js),
-            "ts" => Ok(FileTypes::Ts),
-            "jsx" => Ok(FileTypes::Jsx),
-            "tsx" => Ok(FileTypes::Tsx),
-            "css" => Ok(FileTypes::Css),
-            "scss" => Ok(FileTypes::Scss),
-            "sass" => Ok(FileTypes::Sass),
-            "py" => Ok(FileTypes::Py),
-            "rb" => Ok(FileTypes::Rb),
-            "php" => Ok(FileTypes::Php),
-            "sh" => Ok(FileTypes::Sh),
-            "java" => Ok(FileTypes::Java),
-            "cs" => Ok(FileTypes::Cs),
-            "html" => Ok(FileTypes::Html),
-            "ttf" => Ok(FileTypes::Ttf),
-            "otf" => Ok(FileTypes::Otf),
-            "woff" => Ok(FileTypes::Woff),
-            "exe" => Ok(FileTypes::Exe),
-            other => Ok(FileTypes::Other(other.to_string())),
-        }
+        Ok(FileTypes::from_name(std::str::from_utf8(raw)?))
     }
 
     fn accepts(ty: &tokio_postgres::types::Type) -> bool {
         ty == &tokio_postgres::types::Type::TEXT
     }
 }
+
+/// What kind of content a type holds, for filtering listings.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FileCategory {
+    Image,
+    Audio,
+    Video,
+    Document,
+    Code,
+    Archive,
+    Font,
+    Executable,
+}
+
+/// Outcome for one item of `POST /files/{id}/copy`.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyStatus {
+    Copied,
+    /// The destination already has an item with this name.
+    Exists,
+    /// The source has no stored content to share.
+    Missing,
+}
+
+/// Rendered thumbnail sizes, named by the longest edge they fit in.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    Display,
+    EnumString,
+    EnumIter,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ThumbnailSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl ThumbnailSize {
+    /// Longest edge in pixels.
+    pub fn pixels(&self) -> u32 {
+        match self {
+            ThumbnailSize::Small => 128,
+            ThumbnailSize::Medium => 256,
+            ThumbnailSize::Large => 512,
+        }
+    }
+}
+
+/// Outcome for one file of an upload.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadOutcome {
+    Created,
+    /// The upload became a new version of the file with its name.
+    Replaced,
+    /// The upload was stored under a new name.
+    Renamed,
+    /// The name was taken and nothing changed.
+    Skipped,
+    /// Nothing was stored, `error` says why.
+    Failed,
+}
+
+/// Why one field of an upload failed.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadErrorReason {
+    /// The file name is missing or reserved, or its path has `.` or `..` segments.
+    InvalidName,
+    /// The request body ended or broke off while reading the file.
+    ReadFailed,
+    /// The file's data key could not be opened.
+    EncryptionFailed,
+    /// Storage did not accept the file.
+    StorageFailed,
+    /// A file holds the name of a folder in the file's path.
+    PathConflict,
+    /// A tags field that is not a JSON array of strings.
+    InvalidTags,
+    /// An archive to extract, or one of its entries, is corrupt or uses an unsupported feature.
+    InvalidArchive,
+    /// An archive to extract exceeds the entry count, size or compression ratio limits.
+    ArchiveTooLarge,
+    /// The content is not what the file's extension or content type says, and the server
+    /// rejects such uploads.
+    TypeMismatch,
+}
+
+/// What happens to uploads whose content contradicts their extension or content type, set by
+/// `TYPE_MISMATCH_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum MismatchPolicy {
+    /// Stored with the type of its content, the contradicted claim is reported.
+    #[default]
+    Flag,
+    /// Not stored.
+    Reject,
+}
+
+/// How a transformed image fills the requested width and height.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Display, EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ImageFit {
+    /// Scaled to fit inside the box, keeping its aspect ratio.
+    #[default]
+    Contain,
+    /// Scaled to cover the box and cropped around the center.
+    Cover,
+    /// Stretched to the box.
+    Fill,
+}
+
+/// Encodings a transformed image can be served in.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Display, EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ImageOutput {
+    /// Lossless, keeping transparency.
+    #[default]
+    Webp,
+    Png,
+    /// Lossy at the requested quality, transparency is dropped.
+    Jpeg,
+}
+
+impl ImageOutput {
+    pub fn file_type(&self) -> FileTypes {
+        match self {
+            ImageOutput::Webp => FileTypes::Webp,
+            ImageOutput::Png => FileTypes::Png,
+            ImageOutput::Jpeg => FileTypes::Jpeg,
+        }
+    }
+}
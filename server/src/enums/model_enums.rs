@@ -1,6 +1,7 @@
 #[derive(strum::Display, Debug)]
 #[strum(serialize_all = "snake_case")]
 pub enum Models {
+    #[allow(dead_code)]
     Users,
     Files,
     Buckets,
@@ -19,3 +19,17 @@ impl From<String> for SortType {
         }
     }
 }
+
+/// What an upload does when its name is already taken, from the `conflict` query parameter.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Display, Default)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Leave the existing file as it is.
+    Skip,
+    /// Make the upload the existing file's current version.
+    #[default]
+    Replace,
+    /// Store the upload under a free name, like `report (1).pdf`.
+    Rename,
+}
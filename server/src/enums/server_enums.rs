@@ -7,3 +7,10 @@ pub enum Environment {
     Development,
     Production,
 }
+
+#[derive(Debug, Clone, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum CacheBackends {
+    Dragonfly,
+    Memory,
+}
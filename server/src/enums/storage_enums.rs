@@ -1,7 +1,16 @@
 use strum::EnumString;
 
-#[derive(EnumString)]
+#[derive(EnumString, Debug, Clone, PartialEq)]
 pub enum S3Providers {
-    AWS,
+    #[strum(serialize = "AWS")]
+    Aws,
     DigitalOcean,
 }
+
+#[derive(EnumString, Debug, Clone, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum StorageBackends {
+    S3,
+    Local,
+    Memory,
+}
@@ -1,10 +1,10 @@
-use std::{env::var, str::FromStr};
+use std::{env::var, str::FromStr, sync::Arc, time::Duration};
 
 use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
 use axum::{
     Router,
     extract::{MatchedPath, Request},
-    http::HeaderValue,
+    http::{HeaderName, HeaderValue},
     middleware::from_fn_with_state,
 };
 use axum_extra::extract::cookie::Key;
@@ -14,197 +14,86 @@ use deadpool_redis::{Config as DflyConfig, Runtime};
 use reqwest::{
     Client, Method,
     header::{
-        ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL, CONNECTION,
-        CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY, CONTENT_TYPE,
-        SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SET_COOKIE, UPGRADE,
+        ACCEPT_ENCODING, ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL,
+        CONNECTION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_SECURITY_POLICY,
+        CONTENT_SECURITY_POLICY_REPORT_ONLY, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        IF_RANGE, LAST_MODIFIED, LOCATION, RANGE, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_PROTOCOL, SET_COOKIE, UPGRADE,
     },
 };
 use tokio_postgres::NoTls;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
-use uuid::Uuid;
 
 use crate::{
-    enums::{errors::AppError, file_enums::FileTypes, server_enums::Environment},
+    cache::{dfly_cache::DflyCache, memory_cache::MemoryCache},
+    consts::{
+        TRASH_PURGE_INTERVAL, TUS_EXTENSION_HEADER, TUS_MAX_SIZE, TUS_RESUMABLE,
+        TUS_VERSION_HEADER, UPLOAD_LENGTH, UPLOAD_METADATA, UPLOAD_OFFSET,
+    },
+    enums::{
+        server_enums::{CacheBackends, Environment},
+        storage_enums::{S3Providers, StorageBackends},
+    },
     middleware::session_middleware::session_middleware,
-    models::{response::AppErrorResponse, state::AppState},
-    routes::{auth_routes::auth_routes, bucket_routes::bucket_routes, file_routes::file_routes},
-    utils::db_utils::db_init_setup,
+    models::state::AppState,
+    routes::{
+        auth_routes::auth_routes, bucket_routes::bucket_routes,
+        direct_upload_routes::direct_upload_routes, file_routes::file_routes,
+        tus_routes::tus_routes, user_routes::user_routes,
+    },
+    storage::{local_storage::LocalStorage, memory_storage::MemoryStorage, s3_storage::S3Storage},
+    traits::{cache_traits::CacheStore, storage_traits::StorageBackend},
+    utils::{
+        blob_utils::{flush_object_deletions, migrate_legacy_objects},
+        crypto_utils::{Keyring, rotate_data_keys},
+        db_utils::db_init_setup,
+        trash_utils::purge_trash,
+    },
 };
+mod cache;
 mod consts;
 mod enums;
 mod middleware;
 mod models;
 mod routes;
+mod storage;
+#[cfg(test)]
+mod tests;
 mod traits;
 mod utils;
 
-async fn import_s3_objects_to_db(
-    state: &AppState,
-    owner_id: Uuid,
-    prefix: Option<&str>,
-) -> Result<usize, AppErrorResponse> {
-    let conn = state.get_db_conn().await?;
-    let statement = conn
-        .prepare(
-            "INSERT INTO files (title, owner_id, size, type, path, is_public)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            ON CONFLICT (path, title, owner_id) DO NOTHING;",
-        )
-        .await
-        .map_err(|err| AppError::db_error(err))?;
-
-    let normalized_prefix = prefix
-        .map(|value| value.trim_matches('/').to_string())
-        .filter(|value| !value.is_empty());
-    let list_prefix = normalized_prefix
-        .as_ref()
-        .map(|value| format!("{}/", value));
-    let mut continuation_token: Option<String> = None;
-    let mut inserted = 0usize;
-
-    loop {
-        let mut request = state.s3_client.list_objects_v2().bucket(&state.s3_name);
-        if let Some(value) = list_prefix.as_ref() {
-            request = request.prefix(value);
-        }
-        if let Some(token) = continuation_token.as_ref() {
-            request = request.continuation_token(token);
-        }
-
-        let response = request
-            .send()
-            .await
-            .map_err(|err| AppError::s3_error(err))?;
-
-        for object in response.contents() {
-            let Some(full_key) = object.key() else {
-                continue;
-            };
-            if full_key.ends_with('/') {
-                continue;
-            }
-
-            let head = state
-                .s3_client
-                .head_object()
-                .bucket(&state.s3_name)
-                .key(full_key)
-                .send()
-                .await
-                .map_err(|err| AppError::s3_error(err))?;
-
-            let key = match list_prefix.as_ref() {
-                Some(value) => full_key.strip_prefix(value).unwrap_or(full_key),
-                None => full_key,
-            };
-            let (path, title) = split_key(key);
-            if title.is_empty() {
-                continue;
-            }
+pub fn app_router(state: AppState) -> Router {
+    let base_router = Router::new()
+        .merge(bucket_routes())
+        .merge(file_routes())
+        .merge(direct_upload_routes())
+        .merge(tus_routes())
+        .merge(user_routes())
+        .layer(from_fn_with_state(state.clone(), session_middleware));
 
-            let size = object.size().unwrap_or(0);
-            let file_type = infer_file_type(&title, head.content_type());
+    Router::new()
+        .merge(auth_routes())
+        .nest("/api/v1", base_router)
+        .with_state(state)
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
+                let matched_path = request
+                    .extensions()
+                    .get::<MatchedPath>()
+                    .map(MatchedPath::as_str);
 
-            let rows = conn
-                .execute(
-                    &statement,
-                    &[&title, &owner_id, &size, &file_type, &path, &false],
+                tracing::debug_span!(
+                    "REQUEST SPAN",
+                    method = ?request.method(),
+                    matched_path,
+                    stage = tracing::field::Empty,
+                    kind = tracing::field::Empty,
+                    message = tracing::field::Empty,
+                    call_path = tracing::field::Empty
                 )
-                .await
-                .map_err(|err| AppError::db_error(err))?;
-            inserted += rows as usize;
-        }
-        if response.is_truncated().unwrap_or(false) {
-            continuation_token = response
-                .next_continuation_token()
-                .map(|value| value.to_string());
-            if continuation_token.is_none() {
-                break;
-            }
-        } else {
-            break;
-        }
-    }
-
-    Ok(inserted)
-}
-
-fn split_key(key: &str) -> (String, String) {
-    match key.rfind('/') {
-        Some(idx) => (key[..idx].to_string(), key[idx + 1..].to_string()),
-        None => (String::from(""), key.to_string()),
-    }
-}
-
-fn infer_file_type(title: &str, content_type: Option<&str>) -> String {
-    if let Some(value) = content_type.filter(|value| !value.is_empty()) {
-        return FileTypes::from_mime(value).to_string();
-    }
-
-    if let Some(ext) = title.rsplit('.').next()
-        && ext != title
-    {
-        let ext = ext.to_lowercase();
-        return FileTypes::from_str(&ext)
-            .unwrap_or(FileTypes::Other(ext))
-            .to_string();
-    }
-
-    FileTypes::Other(String::from("other")).to_string()
-}
-
-async fn create_folders_from_paths(
-    state: &AppState,
-    owner_id: Uuid,
-    is_public: bool,
-) -> Result<usize, AppErrorResponse> {
-    let conn = state.get_db_conn().await?;
-    let rows = conn
-        .query(
-            "SELECT DISTINCT path FROM files
-            WHERE owner_id = $1 AND path IS NOT NULL AND path <> '';",
-            &[&owner_id],
-        )
-        .await
-        .map_err(|err| AppError::db_error(err))?;
-
-    let statement = conn
-        .prepare(
-            "INSERT INTO files (id, title, owner_id, size, type, path, is_public)
-            VALUES ($1, $2, $3, 0, 'folder', $4, $5)
-            ON CONFLICT (path, title, owner_id) DO NOTHING;",
+            }),
         )
-        .await
-        .map_err(|err| AppError::db_error(err))?;
-
-    let mut inserted = 0usize;
-
-    for row in rows {
-        let path: String = row.get("path");
-        let mut parent = String::from("");
-        for segment in path.split('/').filter(|value| !value.is_empty()) {
-            let folder_path = parent.clone();
-            let title = segment.to_string();
-
-            let row_count = conn
-                .execute(
-                    &statement,
-                    &[&Uuid::new_v4(), &title, &owner_id, &folder_path, &is_public],
-                )
-                .await
-                .map_err(|err| AppError::db_error(err))?;
-            inserted += row_count as usize;
-
-            parent = if parent.is_empty() {
-                title
-            } else {
-                format!("{}/{}", parent, title)
-            };
-        }
-    }
-
-    Ok(inserted)
 }
 
 #[tokio::main]
@@ -226,18 +115,18 @@ async fn main() {
     let db_url = var("DATABASE_URL").expect("Env var `DATABASE_URL` not set");
     let client_url = var("CLIENT_URL").expect("Env var `CLIENT_URL` not set");
 
-    let dfly_url = var("DFLY_URL").expect("Env var `DFLY_URL` not set");
-
     let cookie_encryption_key =
         var("COOKIE_ENCRYPTION_KEY").expect("Env var `COOKIE_ENCRYPTION_KEY` not set");
 
-    let s3_endpoint = var("S3_ENDPOINT").expect("Env var `S3_ENDPOINT` not set");
-    let s3_access_key = var("S3_ACCESS_KEY").expect("Env var `S3_ACCESS_KEY` not set");
-    let s3_secret = var("S3_SECRET").expect("Env var `S3_SECRET` not set");
-    let s3_name = var("S3_NAME").expect("Env var `S3_SECRET` not set");
-    let s3_region = var("S3_REGION").expect("Env var `S3_REGION` not set");
-    let s3_provider = var("S3_PROVIDER").expect("Env var `S3_PROVIDER` not set");
     let cdn_endpoint = var("CDN_ENDPOINT").unwrap_or(String::from(""));
+    let storage_backend = var("STORAGE_BACKEND")
+        .map(|value| {
+            StorageBackends::from_str(&value).expect("Env var `STORAGE_BACKEND` is not valid")
+        })
+        .unwrap_or(StorageBackends::S3);
+    let cache_backend = var("CACHE_BACKEND")
+        .map(|value| CacheBackends::from_str(&value).expect("Env var `CACHE_BACKEND` is not valid"))
+        .unwrap_or(CacheBackends::Dragonfly);
     //* PG DB Config
     let mut cfg = deadpool_postgres::Config::new();
     cfg.url = Some(db_url.to_string());
@@ -249,23 +138,50 @@ async fn main() {
         .unwrap();
 
     //* DFLY DB Config
-    let dfly_config = DflyConfig::from_url(dfly_url);
-    let dfly = dfly_config.create_pool(Some(Runtime::Tokio1)).unwrap();
+    let cache: Arc<dyn CacheStore> = match cache_backend {
+        CacheBackends::Dragonfly => {
+            let dfly_url = var("DFLY_URL").expect("Env var `DFLY_URL` not set");
+            let dfly_config = DflyConfig::from_url(dfly_url);
+            let dfly = dfly_config.create_pool(Some(Runtime::Tokio1)).unwrap();
+            Arc::new(DflyCache::new(dfly))
+        }
+        CacheBackends::Memory => Arc::new(MemoryCache::new()),
+    };
 
     //* Auth Config
     let cookie_key = Key::from(cookie_encryption_key.as_bytes());
 
-    //* S3 Config
-    let s3_credentials = Credentials::new(s3_access_key, s3_secret, None, None, "");
-    let config = aws_sdk_s3::config::Builder::new()
-        .behavior_version(BehaviorVersion::latest())
-        .force_path_style(true)
-        .region(Region::new(s3_region.clone()))
-        .endpoint_url(s3_endpoint)
-        .credentials_provider(s3_credentials)
-        .build();
-
-    let s3_client = aws_sdk_s3::Client::from_conf(config);
+    //* Storage Config
+    let storage: Arc<dyn StorageBackend> = match storage_backend {
+        StorageBackends::S3 => {
+            let s3_endpoint = var("S3_ENDPOINT").expect("Env var `S3_ENDPOINT` not set");
+            let s3_access_key = var("S3_ACCESS_KEY").expect("Env var `S3_ACCESS_KEY` not set");
+            let s3_secret = var("S3_SECRET").expect("Env var `S3_SECRET` not set");
+            let s3_name = var("S3_NAME").expect("Env var `S3_NAME` not set");
+            let s3_region = var("S3_REGION").expect("Env var `S3_REGION` not set");
+            let s3_provider = var("S3_PROVIDER").expect("Env var `S3_PROVIDER` not set");
+            let s3_provider =
+                S3Providers::from_str(&s3_provider).expect("Env var `S3_PROVIDER` is not valid");
+
+            let s3_credentials = Credentials::new(s3_access_key, s3_secret, None, None, "");
+            let config = aws_sdk_s3::config::Builder::new()
+                .behavior_version(BehaviorVersion::latest())
+                .force_path_style(true)
+                .region(Region::new(s3_region.clone()))
+                .endpoint_url(s3_endpoint)
+                .credentials_provider(s3_credentials)
+                .build();
+
+            let s3_client = aws_sdk_s3::Client::from_conf(config);
+            Arc::new(S3Storage::new(s3_client, s3_name, s3_region, s3_provider))
+        }
+        StorageBackends::Local => {
+            let local_path =
+                var("LOCAL_STORAGE_PATH").expect("Env var `LOCAL_STORAGE_PATH` not set");
+            Arc::new(LocalStorage::new(local_path))
+        }
+        StorageBackends::Memory => Arc::new(MemoryStorage::new()),
+    };
 
     //* Server Config
 
@@ -282,6 +198,14 @@ async fn main() {
             SEC_WEBSOCKET_KEY,
             SEC_WEBSOCKET_PROTOCOL,
             SEC_WEBSOCKET_EXTENSIONS,
+            RANGE,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
+            IF_RANGE,
+            HeaderName::from_static(TUS_RESUMABLE),
+            HeaderName::from_static(UPLOAD_LENGTH),
+            HeaderName::from_static(UPLOAD_METADATA),
+            HeaderName::from_static(UPLOAD_OFFSET),
         ])
         .expose_headers([
             ACCESS_CONTROL_ALLOW_ORIGIN,
@@ -289,10 +213,25 @@ async fn main() {
             CONTENT_SECURITY_POLICY,
             CONTENT_SECURITY_POLICY_REPORT_ONLY,
             CACHE_CONTROL,
+            ACCEPT_RANGES,
+            CONTENT_LENGTH,
+            CONTENT_RANGE,
+            ETAG,
+            LAST_MODIFIED,
+            LOCATION,
+            HeaderName::from_static(TUS_RESUMABLE),
+            HeaderName::from_static(TUS_VERSION_HEADER),
+            HeaderName::from_static(TUS_EXTENSION_HEADER),
+            HeaderName::from_static(TUS_MAX_SIZE),
+            HeaderName::from_static(UPLOAD_LENGTH),
+            HeaderName::from_static(UPLOAD_METADATA),
+            HeaderName::from_static(UPLOAD_OFFSET),
         ])
         .allow_methods([
             Method::GET,
+            Method::HEAD,
             Method::POST,
+            Method::PUT,
             Method::OPTIONS,
             Method::DELETE,
             Method::PATCH,
@@ -315,12 +254,10 @@ async fn main() {
         server_url,
         db_pool,
         rqw_client,
-        dfly,
+        cache,
         cookie_key,
-        s3_client,
-        s3_name,
-        s3_region,
-        s3_provider,
+        storage,
+        keyring: Keyring::from_env().map(Arc::new),
         cdn_endpoint,
         environment,
         root,
@@ -328,34 +265,58 @@ async fn main() {
 
     let _ = db_init_setup(&state).await;
 
-    let base_router = Router::new()
-        .merge(bucket_routes())
-        .merge(file_routes())
-        .layer(from_fn_with_state(state.clone(), session_middleware));
+    //* `filestore rotate-keys` re-wraps data keys with ENCRYPTION_MASTER_KEY and exits,
+    //* `filestore migrate-keys` moves path keyed objects to blob keys and exits,
+    //* `filestore purge-trash` permanently deletes expired trash and exits
+    let task = match std::env::args().nth(1).as_deref() {
+        Some("rotate-keys") => Some(
+            rotate_data_keys(&state)
+                .await
+                .map(|rotated| format!("Re-wrapped {} data keys", rotated)),
+        ),
+        Some("migrate-keys") => Some(
+            migrate_legacy_objects(&state)
+                .await
+                .map(|migrated| format!("Moved {} files to blob keys", migrated)),
+        ),
+        Some("purge-trash") => Some(
+            async {
+                let mut conn = state.get_db_conn().await?;
+                let purged = purge_trash(&mut conn).await?;
+                flush_object_deletions(&state, &mut conn).await?;
+                Ok(format!("Purged {} trashed files", purged))
+            }
+            .await,
+        ),
+        _ => None,
+    };
+    if let Some(result) = task {
+        match result {
+            Ok(summary) => println!("{}", summary),
+            Err(err) => {
+                eprintln!("TASK FAILED - {}", err.message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let app = Router::new()
-        .merge(auth_routes())
-        .nest("/api/v1", base_router)
-        .with_state(state)
-        .layer(
-            TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
-                let matched_path = request
-                    .extensions()
-                    .get::<MatchedPath>()
-                    .map(MatchedPath::as_str);
+    //* Purge expired trash and retry object deletions left queued by earlier failures
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(TRASH_PURGE_INTERVAL));
+            loop {
+                interval.tick().await;
+                if let Ok(mut conn) = state.get_db_conn().await {
+                    let _ = purge_trash(&mut conn).await;
+                    let _ = flush_object_deletions(&state, &mut conn).await;
+                }
+            }
+        }
+    });
 
-                tracing::debug_span!(
-                    "REQUEST SPAN",
-                    method = ?request.method(),
-                    matched_path,
-                    stage = tracing::field::Empty,
-                    kind = tracing::field::Empty,
-                    message = tracing::field::Empty,
-                    call_path = tracing::field::Empty
-                )
-            }),
-        )
-        .layer(cors);
+    let app = app_router(state).layer(cors);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
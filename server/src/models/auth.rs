@@ -98,3 +98,25 @@ pub struct AuthUserVerifyQuery {
     pub user_id: Uuid,
     pub code: String,
 }
+
+/// Per-user preferences, read and updated through `/users/settings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSettings {
+    /// New uploads are encrypted at rest. Files stored before the change keep their form.
+    pub encrypt_files: bool,
+    /// Versions kept per file, the current one included. Older ones go on the next upload.
+    pub max_file_versions: i32,
+    /// Images are served to others and linked without their EXIF data. Buckets can ask for
+    /// it on their own.
+    pub strip_metadata: bool,
+}
+
+/// Settings to change, the rest keep their value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateUserSettings {
+    pub encrypt_files: Option<bool>,
+    pub max_file_versions: Option<i32>,
+    pub strip_metadata: Option<bool>,
+}
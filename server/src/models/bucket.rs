@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+/// Bucket settings to change, the rest keep their value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateBucket {
+    /// Images in the bucket are served to others and linked without their EXIF data, whatever
+    /// their owner's setting says.
+    pub strip_metadata: Option<bool>,
+}
@@ -0,0 +1,258 @@
+use blake3::Hash;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    consts::{DEFAULT_IMAGE_QUALITY, MAX_IMAGE_DIMENSION},
+    enums::file_enums::{
+        CopyStatus, FileTypes, ImageFit, ImageOutput, UploadErrorReason, UploadOutcome,
+    },
+};
+
+/// An object that finished uploading and is about to get its `files` row.
+#[derive(Debug, Clone)]
+pub struct NewFile {
+    pub id: Uuid,
+    pub title: String,
+    pub owner_id: Uuid,
+    pub size: i64,
+    pub file_type: FileTypes,
+    /// Value for the `path` column.
+    pub path: String,
+    pub is_public: bool,
+    pub hash: Hash,
+    /// Where the upload was written; becomes the blob key unless the content is already stored.
+    pub key: String,
+    pub etag: Option<String>,
+    pub encryption: Option<BlobEncryption>,
+}
+
+/// Where `insert_file` put an upload.
+#[derive(Debug, Clone)]
+pub struct InsertedFile {
+    /// The file holding the upload, or the one it was skipped for.
+    pub id: Uuid,
+    /// Final name, which differs from the uploaded one when renamed.
+    pub title: String,
+    pub outcome: UploadOutcome,
+    /// The object at `NewFile::key` became a blob and has to be kept.
+    pub is_stored: bool,
+}
+
+/// Result for one multipart field of `POST /files/upload`. Only stored files carry the
+/// details of what was stored.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    pub field: String,
+    /// Name the file was uploaded with.
+    pub file_name: Option<String>,
+    pub outcome: UploadOutcome,
+    pub error: Option<UploadErrorReason>,
+    /// The file holding the upload, or the one it was skipped for.
+    pub id: Option<Uuid>,
+    /// Final location, in the same form as the `path` query parameter followed by the title.
+    pub path: Option<String>,
+    pub title: Option<String>,
+    pub size: Option<i64>,
+    pub hash: Option<String>,
+    #[serde(rename = "type")]
+    pub file_type: Option<String>,
+    /// What the name or declared content type claimed, when the content turned out to be
+    /// something else. `type` is what the content is.
+    pub declared_type: Option<String>,
+}
+
+impl UploadedFile {
+    /// Result for `file` once `insert_file` placed it, with `folder` in the same form as the
+    /// `path` query parameter, empty or ending in `/`.
+    pub fn stored(
+        field: String,
+        file_name: String,
+        folder: &str,
+        inserted: InsertedFile,
+        file: &NewFile,
+    ) -> Self {
+        Self {
+            field,
+            file_name: Some(file_name),
+            outcome: inserted.outcome,
+            error: None,
+            id: Some(inserted.id),
+            path: Some(format!("{}{}", folder, inserted.title)),
+            title: Some(inserted.title),
+            size: Some(file.size),
+            hash: Some(file.hash.to_string()),
+            file_type: Some(file.file_type.to_string()),
+            declared_type: None,
+        }
+    }
+
+    /// Reports the claim the content of the upload contradicted.
+    pub fn flagged(mut self, declared_type: Option<String>) -> Self {
+        self.declared_type = declared_type;
+        self
+    }
+
+    pub fn failed(field: String, file_name: Option<String>, reason: UploadErrorReason) -> Self {
+        Self {
+            field,
+            file_name,
+            outcome: UploadOutcome::Failed,
+            error: Some(reason),
+            id: None,
+            path: None,
+            title: None,
+            size: None,
+            hash: None,
+            file_type: None,
+            declared_type: None,
+        }
+    }
+}
+
+/// Envelope for an encrypted blob: its data key wrapped by the master key `key_id`, and the
+/// nonce prefix its chunks are sealed with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobEncryption {
+    pub key_id: String,
+    pub data_key: Vec<u8>,
+    pub nonce: Vec<u8>,
+}
+
+/// Where a file's content is stored. `size` is the plaintext size.
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: i64,
+    pub encryption: Option<BlobEncryption>,
+}
+
+/// Body of `PATCH /files/{id}`. Omitted fields keep their current value.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveFile {
+    pub title: Option<String>,
+    /// Destination folder, in the same form as the `path` query parameter.
+    pub path: Option<String>,
+}
+
+/// Body of `POST /files/{id}/copy`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyFile {
+    /// Destination folder, in the same form as the `path` query parameter.
+    pub path: String,
+    /// Name of the copy. Defaults to the source's title.
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopiedFile {
+    pub source_id: Uuid,
+    /// Id of the new row, when one was created.
+    pub id: Option<Uuid>,
+    pub path: String,
+    pub title: String,
+    pub status: CopyStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DedupSavings {
+    /// Total size of the user's files.
+    pub logical_bytes: i64,
+    /// Size of the distinct blobs behind those files.
+    pub stored_bytes: i64,
+    pub saved_bytes: i64,
+}
+
+/// What `GET /files/{id}/image` does to an image. A missing width or height follows the
+/// aspect ratio; with neither the image is only converted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageTransform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub fit: ImageFit,
+    #[serde(default)]
+    pub format: ImageOutput,
+    /// JPEG quality from 1 to 100, ignored by the lossless formats.
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+}
+
+fn default_quality() -> u8 {
+    DEFAULT_IMAGE_QUALITY
+}
+
+impl Default for ImageTransform {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            fit: ImageFit::default(),
+            format: ImageOutput::default(),
+            quality: DEFAULT_IMAGE_QUALITY,
+        }
+    }
+}
+
+impl ImageTransform {
+    /// Name of the cached rendering, the same for every equal transform.
+    pub fn variant(&self) -> String {
+        let edge = |edge: Option<u32>| edge.map_or(String::from("auto"), |edge| edge.to_string());
+        format!(
+            "{}x{}-{}-q{}.{}",
+            edge(self.width),
+            edge(self.height),
+            self.fit,
+            self.quality,
+            self.format
+        )
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for edge in [self.width, self.height].into_iter().flatten() {
+            if !(1..=MAX_IMAGE_DIMENSION).contains(&edge) {
+                return Err(format!(
+                    "Width and height must be between 1 and {}",
+                    MAX_IMAGE_DIMENSION
+                ));
+            }
+        }
+        if !(1..=100).contains(&self.quality) {
+            return Err(String::from("Quality must be between 1 and 100"));
+        }
+        Ok(())
+    }
+}
+
+/// A named transform, managed through `/users/image-presets`. Only transforms matching one of
+/// the owner's presets are served.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePreset {
+    pub name: String,
+    #[serde(flatten)]
+    pub transform: ImageTransform,
+}
+
+/// What the EXIF data of a photo says about it, kept in the photo columns of `files`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhotoMetadata {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    /// Capture time. Cameras that record no offset are taken to be on UTC.
+    pub taken_at: Option<Timestamp>,
+    pub image_width: Option<i32>,
+    pub image_height: Option<i32>,
+    /// EXIF orientation from 1 to 8, 1 being upright.
+    pub orientation: Option<i32>,
+    pub gps_latitude: Option<f64>,
+    pub gps_longitude: Option<f64>,
+}
@@ -1,4 +1,8 @@
 pub mod auth;
+pub mod bucket;
+pub mod file;
 pub mod request;
 pub mod response;
 pub mod state;
+pub mod storage;
+pub mod upload;
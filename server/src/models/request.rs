@@ -2,7 +2,14 @@ use convert_case::Casing;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::enums::{model_enums::Models, request_enums::SortType};
+use crate::{
+    enums::{
+        file_enums::{FileCategory, ImageFit, ImageOutput, ThumbnailSize},
+        model_enums::Models,
+        request_enums::{ConflictPolicy, SortType},
+    },
+    models::file::ImageTransform,
+};
 
 use std::{
     collections::{HashMap, HashSet},
@@ -11,6 +18,55 @@ use std::{
 
 use serde::Serialize;
 
+/// Query parameters of `POST /files/upload` besides the target folder.
+#[derive(Deserialize, Debug, Default)]
+pub struct UploadQuery {
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+    /// Expand ZIP archives into a folder named after them instead of storing them.
+    #[serde(default)]
+    pub extract: bool,
+}
+
+/// Query parameters of `GET /files/{id}/thumbnail`.
+#[derive(Deserialize, Debug, Default)]
+pub struct ThumbnailQuery {
+    #[serde(default)]
+    pub size: ThumbnailSize,
+}
+
+/// Query parameters of `GET /files/{id}/image`: a preset by name, or a transform that has to
+/// match one of the owner's presets.
+#[derive(Deserialize, Debug, Default)]
+pub struct ImageQuery {
+    pub preset: Option<String>,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub fit: Option<ImageFit>,
+    pub format: Option<ImageOutput>,
+    pub quality: Option<u8>,
+}
+
+impl ImageQuery {
+    /// The transform asked for, defaults filled in.
+    pub fn transform(&self) -> ImageTransform {
+        let defaults = ImageTransform::default();
+        ImageTransform {
+            width: self.w,
+            height: self.h,
+            fit: self.fit.unwrap_or(defaults.fit),
+            format: self.format.unwrap_or(defaults.format),
+            quality: self.quality.unwrap_or(defaults.quality),
+        }
+    }
+}
+
+/// Query parameters of `GET /files/archive`: comma separated ids of files and folders.
+#[derive(Deserialize, Debug)]
+pub struct ArchiveQuery {
+    pub ids: String,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum FilterOperators {
@@ -88,10 +144,13 @@ pub struct Conditions {
 #[serde(rename_all = "camelCase")]
 pub struct QueryParams {
     #[serde(default = "default_page")]
+    #[allow(dead_code)]
     pub page: Option<i64>,
     #[serde(default = "default_limit")]
+    #[allow(dead_code)]
     pub limit: Option<i64>,
     #[serde(default = "default_none")]
+    #[allow(dead_code)]
     pub fields: Option<String>,
     #[serde(default = "default_none")]
     pub filters: Option<String>,
@@ -100,9 +159,12 @@ pub struct QueryParams {
     #[serde(default = "default_sort_type")]
     pub sort_type: Option<SortType>,
     #[serde(default = "default_none")]
+    #[allow(dead_code)]
     pub relations: Option<String>,
     #[serde(default = "default_path")]
     pub path: String,
+    #[serde(default)]
+    pub category: Option<FileCategory>,
 }
 
 fn default_path() -> String {
@@ -126,8 +188,7 @@ fn default_sort_type() -> Option<SortType> {
 
 impl QueryParams {
     pub fn to_query_sort(&self, model: &Models) -> String {
-        if self.sort_field.is_some() {
-            let sort_field = self.sort_field.as_ref().unwrap();
+        if let Some(sort_field) = &self.sort_field {
             if sort_field.contains(".") {
                 return format!(
                     "ORDER BY {} {}",
@@ -161,6 +222,7 @@ impl QueryParams {
         }
         None
     }
+    #[allow(dead_code)]
     pub fn relations(&self) -> HashMap<String, HashSet<String>> {
         if self.relations.is_none() {
             return HashMap::new();
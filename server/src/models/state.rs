@@ -1,11 +1,10 @@
+use std::sync::Arc;
+
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use aws_sdk_s3::Client as S3Client;
 use axum::extract::FromRef;
 use axum_extra::extract::cookie::Key;
 
 use deadpool_postgres::{Object, Pool};
-use deadpool_redis::redis::AsyncCommands;
-use deadpool_redis::{Connection, Pool as DflyPool};
 use rand::Rng;
 use rand::distr::Alphanumeric;
 use reqwest::Client as ReqwestClient;
@@ -17,35 +16,26 @@ use crate::enums::errors::AppError;
 use crate::enums::server_enums::Environment;
 use crate::models::auth::{AuthSession, AuthUser, Credentials};
 use crate::models::response::AppErrorResponse;
+use crate::traits::cache_traits::CacheStore;
+use crate::traits::storage_traits::StorageBackend;
+use crate::utils::crypto_utils::Keyring;
 
 #[derive(Clone)]
 pub struct AppState {
     pub server_url: String,
     pub db_pool: Pool,
     pub rqw_client: ReqwestClient,
-    pub dfly: DflyPool,
+    pub cache: Arc<dyn CacheStore>,
     pub cookie_key: Key,
-    pub s3_client: S3Client,
-    pub s3_name: String,
-    pub s3_region: String,
-    pub s3_provider: String,
+    pub storage: Arc<dyn StorageBackend>,
+    /// Master keys for envelope encryption, `None` when no master key is configured.
+    pub keyring: Option<Arc<Keyring>>,
     pub cdn_endpoint: String,
     pub root: String,
     pub environment: Environment,
 }
 
 impl AppState {
-    pub async fn get_dfly_conn(&self) -> Result<Connection, AppErrorResponse> {
-        tracing::debug!("ESTABLISHING DRAGONFLY CONNECTION");
-        let conn = self
-            .dfly
-            .get()
-            .await
-            .map_err(|e| AppError::critical_error(e))?;
-
-        Ok(conn)
-    }
-
     pub async fn get_db_conn(&self) -> Result<Object, AppErrorResponse> {
         tracing::debug!("ESTABLISH POSTGRES DB CONNECTION");
         let connection = self
@@ -61,8 +51,6 @@ impl AppState {
         &self,
         creds: Credentials,
     ) -> Result<Option<(String, AuthUser)>, AppErrorResponse> {
-        let mut v_conn = self.get_dfly_conn().await?;
-
         let pg_conn = self.get_db_conn().await?;
 
         debug!("QUERYING USER BY USERNAME");
@@ -112,18 +100,18 @@ impl AppState {
                     .map(char::from)
                     .collect();
 
-                let _: () = v_conn
+                self.cache
                     .set_ex(
                         &session_id,
-                        serde_json::json!({"user":user}).to_string(),
+                        &serde_json::json!({"user":user}).to_string(),
                         AUTH_SESSION_TIME as u64,
                     )
                     .await
                     .map_err(|err| AppError::unauthorized_response(err))?;
 
                 let user_key = format!("user_sessions:{}", user.id);
-                let _: () = v_conn
-                    .sadd(user_key, &session_id)
+                self.cache
+                    .sadd(&user_key, &session_id)
                     .await
                     .map_err(|err| AppError::dfly_error(err))?;
 
@@ -147,24 +135,24 @@ impl AppState {
     }
 
     pub async fn invalidate_user_sessions(&self, user_id: Uuid) -> Result<(), AppErrorResponse> {
-        let mut conn = self.get_dfly_conn().await?;
         let user_key = format!("user_sessions:{}", user_id);
 
-        let session_ids: Vec<String> = conn
+        let session_ids: Vec<String> = self
+            .cache
             .smembers(&user_key)
             .await
             .map_err(|err| AppError::dfly_error(err))?;
 
         for session_id in session_ids {
             let key = format!("sessions:{}", session_id);
-            let _: () = conn
-                .del(key)
+            self.cache
+                .del(&key)
                 .await
                 .map_err(|err| AppError::dfly_error(err))?;
         }
 
-        let _: () = conn
-            .del(user_key)
+        self.cache
+            .del(&user_key)
             .await
             .map_err(|err| AppError::dfly_error(err))?;
 
@@ -175,9 +163,8 @@ impl AppState {
         &self,
         id: &str,
     ) -> Result<Option<AuthSession>, AppErrorResponse> {
-        let mut v_conn = self.get_dfly_conn().await?;
-
-        let data: Option<String> = v_conn
+        let data: Option<String> = self
+            .cache
             .get(id)
             .await
             .map_err(|err| AppError::unauthorized_response(err))?;
@@ -193,10 +180,8 @@ impl AppState {
     }
 
     pub async fn delete_session_data(&self, id: String) -> Result<bool, AppErrorResponse> {
-        let mut v_conn = self.get_dfly_conn().await?;
-
-        let _: () = v_conn
-            .del(id)
+        self.cache
+            .del(&id)
             .await
             .map_err(AppError::unauthorized_response)?;
 
@@ -0,0 +1,41 @@
+use std::{collections::BTreeMap, pin::Pin};
+
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
+
+pub type ObjectBody = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    pub content_type: Option<String>,
+    pub is_public: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: i64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+pub struct StorageObject {
+    pub meta: ObjectMeta,
+    pub body: ObjectBody,
+}
+
+/// A request a client can send straight to storage. `headers` were signed and must be sent as-is.
+#[derive(Debug, Clone, Serialize)]
+pub struct PresignedRequest {
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    consts::UPLOAD_PART_SIZE,
+    models::{
+        file::BlobEncryption,
+        storage::{PresignedRequest, UploadedPart},
+    },
+};
+
+/// State of a tus upload, stored in the cache between requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TusUpload {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub title: String,
+    /// Value for the `path` column of the finished file row.
+    pub path: String,
+    pub key: String,
+    pub content_type: String,
+    pub is_public: bool,
+    pub metadata: Option<String>,
+    pub length: i64,
+    pub offset: i64,
+    pub upload_id: Option<String>,
+    pub parts: Vec<UploadedPart>,
+    /// Bytes that did not fill a whole part yet live under this prefix, keyed by offset.
+    pub pending_prefix: String,
+    pub completed: bool,
+    /// Envelope for the owner's encrypted uploads. Pending bytes are sealed with it as well.
+    #[serde(default)]
+    pub encryption: Option<BlobEncryption>,
+}
+
+impl TusUpload {
+    pub fn cache_key(id: &Uuid) -> String {
+        format!("tus:{}", id)
+    }
+
+    pub fn lock_key(id: &Uuid) -> String {
+        format!("tus:{}:lock", id)
+    }
+
+    pub fn pending_len(&self) -> i64 {
+        self.offset - (self.parts.len() * UPLOAD_PART_SIZE) as i64
+    }
+
+    pub fn pending_key(&self) -> String {
+        format!("{}{}", self.pending_prefix, self.offset)
+    }
+}
+
+/// A presigned upload waiting for its completion request, stored in the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectUpload {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub title: String,
+    pub path: String,
+    pub key: String,
+    pub content_type: String,
+    pub is_public: bool,
+    pub size: i64,
+    pub upload_id: Option<String>,
+}
+
+impl DirectUpload {
+    pub fn cache_key(id: &Uuid) -> String {
+        format!("direct:{}", id)
+    }
+
+    pub fn lock_key(id: &Uuid) -> String {
+        format!("direct:{}:lock", id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitiateDirectUpload {
+    pub title: String,
+    pub content_type: Option<String>,
+    pub size: i64,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub is_public: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignedPart {
+    pub part_number: i32,
+    #[serde(flatten)]
+    pub request: PresignedRequest,
+}
+
+/// Either a single `put` or one presigned request per part of a multipart upload.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectUploadTarget {
+    pub id: Uuid,
+    pub upload_id: Option<String>,
+    pub part_size: usize,
+    pub put: Option<PresignedRequest>,
+    pub parts: Vec<PresignedPart>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteDirectUpload {
+    #[serde(default)]
+    pub parts: Vec<UploadedPart>,
+}
@@ -15,7 +15,6 @@ use axum_extra::extract::{
 
 use base64::Engine;
 use base64::prelude::BASE64_URL_SAFE_NO_PAD;
-use deadpool_redis::redis::AsyncCommands;
 use headers::Origin;
 
 use reqwest::StatusCode;
@@ -98,7 +97,6 @@ async fn login(
     let code_challenge = generate_code_challenge(&code_verifier);
     let state_param = Uuid::new_v4().to_string();
     let client_url = var("CLIENT_URL").expect("No ENV var `CLIENT_URL` set");
-    let mut v_conn = state.get_dfly_conn().await?;
 
     let redirect_uri = match state.environment {
         Environment::Development => origin.to_string(),
@@ -110,7 +108,8 @@ async fn login(
         }
     };
 
-    let _: () = v_conn
+    state
+        .cache
         .set_ex(&state_param, &code_verifier, 120)
         .await
         .map_err(|err| AppError::dfly_error(err))?;
@@ -137,8 +136,8 @@ async fn callback(
     query: axum::extract::Query<CallbackQuery>,
 ) -> Result<(PrivateCookieJar, AppResponse<AuthSession>), AppErrorResponse> {
     let domain = var("DOMAIN").expect("Env var `DOMAIN` not set");
-    let mut dfly_conn = state.get_dfly_conn().await?;
-    let code_verifier: Option<String> = dfly_conn
+    let code_verifier: Option<String> = state
+        .cache
         .get(&query.state)
         .await
         .map_err(|err| AppError::dfly_error(err))?;
@@ -149,7 +148,8 @@ async fn callback(
         ));
     };
 
-    let _: () = dfly_conn
+    state
+        .cache
         .del(&query.state)
         .await
         .map_err(|err| AppError::dfly_error(err))?;
@@ -218,10 +218,9 @@ async fn verify(
 ) -> Result<Redirect, AppErrorResponse> {
     let client_url = var("CLIENT_URL").expect("No ENV var `CLIENT_URL` set");
 
-    let mut conn = state.get_dfly_conn().await?;
-
-    let saved_code: String = conn
-        .get(query.user_id.to_string())
+    let saved_code: Option<String> = state
+        .cache
+        .get(&query.user_id.to_string())
         .await
         .map_err(|err| AppError::dfly_error(err))?;
 
@@ -233,9 +232,11 @@ async fn verify(
             ));
         }
     };
-    if query.code == saved_code {
-        let _: () = conn
-            .del(query.user_id.to_string())
+    // * A missing or expired code never verifies, even against an empty one
+    if saved_code.is_some_and(|saved_code| saved_code == query.code) {
+        state
+            .cache
+            .del(&query.user_id.to_string())
             .await
             .map_err(|err| AppError::dfly_error(err))?;
         Ok(Redirect::permanent(&format!("{}/auth/login", redirect_uri)))
@@ -1,7 +1,7 @@
 use axum::{
-    Extension, Router,
+    Extension, Json, Router,
     extract::{Path, Query, State},
-    routing::{delete, get},
+    routing::{delete, get, patch},
 };
 
 use serde_json::Value;
@@ -12,12 +12,16 @@ use crate::{
     enums::{errors::AppError, model_enums::Models},
     models::{
         auth::AuthSession,
+        bucket::UpdateBucket,
         request::QueryParams,
         response::{AppResponse, RouteResponse},
         state::AppState,
     },
-    traits::db_traits::SerializeList,
-    utils::db_utils::{WhereBuilder, convert_filter_type},
+    traits::db_traits::{SerializeList, SerializeToJson},
+    utils::{
+        blob_utils::{DELETED_FILE_COLUMNS, flush_object_deletions, release_deleted_files},
+        db_utils::{WhereBuilder, convert_filter_type},
+    },
 };
 
 async fn list_buckets(
@@ -46,7 +50,7 @@ async fn list_buckets(
 
     let stmt = format!(
         "
-        SELECT id, title
+        SELECT id, title, strip_metadata
         FROM buckets
         WHERE
             owner_id = $1
@@ -67,6 +71,28 @@ async fn list_buckets(
     Ok(AppResponse::default_response(rows.serialize_list()))
 }
 
+async fn update_bucket(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateBucket>,
+) -> RouteResponse<Value> {
+    let conn = state.get_db_conn().await?;
+    let row = conn
+        .query_opt(
+            "UPDATE buckets
+            SET strip_metadata = COALESCE($1, strip_metadata), updated_at = CURRENT_TIMESTAMP
+            WHERE id = $2 AND owner_id = $3
+            RETURNING id, title, strip_metadata;",
+            &[&payload.strip_metadata, &id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No bucket {}", id)))?;
+
+    Ok(AppResponse::default_response(row.serialize_row_to_json()))
+}
+
 async fn delete_bucket(
     Extension(session): Extension<AuthSession>,
     State(state): State<AppState>,
@@ -78,6 +104,19 @@ async fn delete_bucket(
         .await
         .map_err(|err| AppError::db_error(err))?;
 
+    let rows = tx
+        .query(
+            &format!(
+                "DELETE FROM files
+                WHERE bucket_id = $1 AND owner_id = $2 AND type <> 'folder'
+                RETURNING {};",
+                DELETED_FILE_COLUMNS
+            ),
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
     tx.execute(
         "DELETE FROM buckets WHERE id = $1 AND owner_id = $2;",
         &[&id, &session.user.id],
@@ -85,15 +124,13 @@ async fn delete_bucket(
     .await
     .map_err(|err| AppError::db_error(err))?;
 
-    state
-        .s3_client
-        .delete_bucket()
-        .bucket(&state.s3_name)
-        .send()
+    release_deleted_files(&tx, &rows)
         .await
-        .map_err(|err| AppError::s3_error(err))?;
+        .map_err(|err| AppError::db_error(err))?;
 
     tx.commit().await.map_err(|err| AppError::db_error(err))?;
+    // * Objects that fail to delete stay queued for the next flush
+    let _ = flush_object_deletions(&state, &mut conn).await;
 
     Ok(AppResponse::default_response(id))
 }
@@ -107,6 +144,7 @@ pub fn bucket_routes() -> Router<AppState> {
             // .route("/read/{id}/link", get(generate_link))
             // .route("/download/{id}", get(download_file))
             .route("/list", get(list_buckets))
+            .route("/{id}", patch(update_bucket))
             .route("/delete/{id}", delete(delete_bucket)),
     )
 }
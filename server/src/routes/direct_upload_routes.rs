@@ -0,0 +1,301 @@
+use std::time::Duration;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, State},
+    routing::post,
+};
+use uuid::Uuid;
+
+use crate::{
+    consts::{
+        DIRECT_UPLOAD_TTL, DIRECT_UPLOAD_URL_TTL, MAX_FILE_SIZE, MAX_UPLOAD_PARTS, UPLOAD_PART_SIZE,
+    },
+    enums::errors::{AppError, StorageError},
+    models::{
+        auth::AuthSession,
+        file::{NewFile, StoredObject},
+        response::{AppErrorResponse, AppResponse, RouteResponse},
+        state::AppState,
+        storage::PutOptions,
+        upload::{
+            CompleteDirectUpload, DirectUpload, DirectUploadTarget, InitiateDirectUpload,
+            PresignedPart,
+        },
+    },
+    routes::file_routes::FileQuery,
+    utils::{
+        blob_utils::{new_blob_key, record_file},
+        crypto_utils::owner_keyring,
+        file_utils::{hash_object, refuses_type, verify_object},
+        tree_utils::split_relative_path,
+    },
+};
+
+async fn get_direct_upload(
+    state: &AppState,
+    session: &AuthSession,
+    id: &Uuid,
+) -> Result<DirectUpload, AppErrorResponse> {
+    let upload = state
+        .cache
+        .get(&DirectUpload::cache_key(id))
+        .await
+        .map_err(|err| AppError::dfly_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No direct upload {}", id)))?;
+    let upload: DirectUpload =
+        serde_json::from_str(&upload).map_err(|err| AppError::critical_error(err))?;
+
+    match upload.owner_id == session.user.id {
+        true => Ok(upload),
+        false => Err(AppError::not_found_response(format!(
+            "Direct upload {} belongs to another user",
+            id
+        ))),
+    }
+}
+
+async fn initiate_direct_upload(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Json(payload): Json<InitiateDirectUpload>,
+) -> RouteResponse<DirectUploadTarget> {
+    if payload.title.is_empty() || payload.size < 0 {
+        return Err(AppError::bad_request_response(
+            "Direct upload needs a title and a size",
+        ));
+    }
+    let part_count = (payload.size as usize).div_ceil(UPLOAD_PART_SIZE);
+    if payload.size as usize > *MAX_FILE_SIZE || part_count > MAX_UPLOAD_PARTS {
+        return Err(AppError::payload_too_large_response(format!(
+            "Direct upload of {} bytes exceeds the maximum file size",
+            payload.size
+        )));
+    }
+
+    // * Clients write presigned objects themselves, so the server never sees the plaintext
+    if owner_keyring(&state, &session.user.id).await?.is_some() {
+        return Err(AppError::storage_error(StorageError::Unsupported(
+            "direct uploads of encrypted files",
+        )));
+    }
+
+    // * Checked like the relative names of multipart uploads; `record_file` creates the
+    // * folders once the upload completes
+    let (folder, title) = split_relative_path(&format!("{}/{}", payload.path, payload.title))
+        .ok_or_else(|| {
+            AppError::bad_request_response(format!(
+                "Invalid title {:?} in {:?}",
+                payload.title, payload.path
+            ))
+        })?;
+    let query = FileQuery {
+        path: folder,
+        is_public: payload.is_public,
+        is_folder: false,
+    };
+    let path = query.format_path(&state.root);
+    let content_type = payload
+        .content_type
+        .filter(|content_type| !content_type.is_empty())
+        .unwrap_or(String::from("application/octet-stream"));
+    let options = PutOptions {
+        content_type: Some(content_type.clone()),
+        is_public: payload.is_public,
+    };
+    let expires_in = Duration::from_secs(DIRECT_UPLOAD_URL_TTL);
+
+    let id = Uuid::new_v4();
+    let mut upload = DirectUpload {
+        id,
+        owner_id: session.user.id,
+        key: new_blob_key(&state.root, &id),
+        title,
+        path,
+        content_type,
+        is_public: payload.is_public,
+        size: payload.size,
+        upload_id: None,
+    };
+    let mut target = DirectUploadTarget {
+        id: upload.id,
+        upload_id: None,
+        part_size: UPLOAD_PART_SIZE,
+        put: None,
+        parts: vec![],
+    };
+
+    // * Anything that fits in one part is a single PUT
+    if part_count <= 1 {
+        let request = state
+            .storage
+            .presign_put(&upload.key, &options, expires_in)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        target.put = Some(request);
+    } else {
+        let upload_id = state
+            .storage
+            .create_multipart_upload(&upload.key, &options)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        for part_number in 1..=part_count as i32 {
+            let request = state
+                .storage
+                .presign_upload_part(&upload.key, &upload_id, part_number, expires_in)
+                .await
+                .map_err(|err| AppError::storage_error(err))?;
+            target.parts.push(PresignedPart {
+                part_number,
+                request,
+            });
+        }
+        upload.upload_id = Some(upload_id.clone());
+        target.upload_id = Some(upload_id);
+    }
+
+    let value = serde_json::to_string(&upload).map_err(|err| AppError::critical_error(err))?;
+    state
+        .cache
+        .set_ex(
+            &DirectUpload::cache_key(&upload.id),
+            &value,
+            DIRECT_UPLOAD_TTL,
+        )
+        .await
+        .map_err(|err| AppError::dfly_error(err))?;
+
+    Ok(AppResponse::default_response(target))
+}
+
+/// Records a direct upload once its object is in storage. The object is read back in full to
+/// hash it: storage can only check SHA-256 checksums, while blobs are keyed by their BLAKE3
+/// hash, so the client cannot declare one the server could trust without reading. Completing
+/// takes about as long as downloading the file.
+async fn finish_direct_upload(
+    state: &AppState,
+    upload: &DirectUpload,
+    payload: CompleteDirectUpload,
+) -> Result<Uuid, AppErrorResponse> {
+    if let Some(upload_id) = &upload.upload_id {
+        if payload.parts.is_empty() {
+            return Err(AppError::bad_request_response(format!(
+                "Direct upload {} is multipart and needs its parts",
+                upload.id
+            )));
+        }
+        state
+            .storage
+            .complete_multipart_upload(&upload.key, upload_id, &payload.parts)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+    }
+
+    let meta = match state.storage.head_object(&upload.key).await {
+        Ok(meta) => meta,
+        Err(StorageError::NotFound(key)) => {
+            return Err(AppError::bad_request_response(format!(
+                "Direct upload {} never arrived at {}",
+                upload.id, key
+            )));
+        }
+        Err(err) => return Err(AppError::storage_error(err)),
+    };
+    if meta.size != upload.size {
+        if let Err(err) = state.storage.delete_object(&upload.key).await {
+            AppError::storage_error(err);
+        }
+        return Err(AppError::bad_request_response(format!(
+            "Direct upload {} has {} bytes, expected {}",
+            upload.id, meta.size, upload.size
+        )));
+    }
+
+    let object = StoredObject {
+        key: upload.key.clone(),
+        size: meta.size,
+        encryption: None,
+    };
+    let verified = verify_object(state, &object, &upload.title, Some(&upload.content_type))
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+    if refuses_type(&upload.title, &verified) {
+        if let Err(err) = state.storage.delete_object(&upload.key).await {
+            AppError::storage_error(err);
+        }
+        state
+            .cache
+            .del(&DirectUpload::cache_key(&upload.id))
+            .await
+            .map_err(|err| AppError::dfly_error(err))?;
+        return Err(AppError::bad_request_response(format!(
+            "Content of direct upload {} is {}, not {}",
+            upload.id,
+            verified.file_type,
+            verified.mismatch.unwrap_or_default()
+        )));
+    }
+
+    let hash = hash_object(state, &object)
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+
+    let file = NewFile {
+        id: upload.id,
+        title: upload.title.clone(),
+        owner_id: upload.owner_id,
+        size: meta.size,
+        file_type: verified.file_type,
+        path: upload.path.clone(),
+        is_public: upload.is_public,
+        hash,
+        key: upload.key.clone(),
+        etag: meta.etag,
+        encryption: None,
+    };
+    record_file(state, file).await?;
+
+    state
+        .cache
+        .del(&DirectUpload::cache_key(&upload.id))
+        .await
+        .map_err(|err| AppError::dfly_error(err))?;
+
+    Ok(upload.id)
+}
+
+async fn complete_direct_upload(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CompleteDirectUpload>,
+) -> RouteResponse<Uuid> {
+    let locked = state
+        .cache
+        .set_nx_ex(&DirectUpload::lock_key(&id), "1", DIRECT_UPLOAD_URL_TTL)
+        .await
+        .map_err(|err| AppError::dfly_error(err))?;
+    if !locked {
+        return Err(AppError::conflict_response(format!(
+            "Direct upload {} is already being completed",
+            id
+        )));
+    }
+
+    let completed = async {
+        let upload = get_direct_upload(&state, &session, &id).await?;
+        finish_direct_upload(&state, &upload, payload).await
+    }
+    .await;
+    if let Err(err) = state.cache.del(&DirectUpload::lock_key(&id)).await {
+        AppError::dfly_error(err);
+    }
+
+    Ok(AppResponse::default_response(completed?))
+}
+
+pub fn direct_upload_routes() -> Router<AppState> {
+    Router::new()
+        .route("/files/direct", post(initiate_direct_upload))
+        .route("/files/direct/{id}/complete", post(complete_direct_upload))
+}
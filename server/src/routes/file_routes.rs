@@ -1,38 +1,79 @@
-use std::{ops::Not, str::FromStr, time::Duration};
+use std::{
+    collections::HashSet,
+    io::Cursor,
+    ops::Not,
+    time::{Duration, UNIX_EPOCH},
+};
 
-use aws_sdk_s3::presigning::PresigningConfig;
 use axum::{
     Extension, Json, Router,
     body::Body,
     extract::{DefaultBodyLimit, Multipart, Path, Query, State},
-    http::Response,
-    routing::{delete, get, post},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+    routing::{delete, get, patch, post},
 };
 
+use deadpool_postgres::{GenericClient, Object};
+use headers::{
+    AcceptRanges, ContentRange, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, IfRange,
+    LastModified, Range,
+};
+use jiff::Timestamp;
 use reqwest::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio_postgres::types::ToSql;
+use tokio::io::AsyncReadExt;
+use tokio_postgres::{Row, types::ToSql};
 use tokio_util::io::ReaderStream;
 use tracing::debug;
 use uuid::Uuid;
 
 use crate::{
-    consts::MAX_FILE_SIZE,
+    consts::{MAX_FILE_SIZE, MAX_RANGES},
     enums::{
-        errors::AppError, file_enums::FileTypes, model_enums::Models, storage_enums::S3Providers,
+        errors::{AppError, StorageError},
+        file_enums::{FileTypes, UploadErrorReason, UploadOutcome},
+        model_enums::Models,
     },
     models::{
         auth::AuthSession,
-        request::QueryParams,
+        file::{
+            CopiedFile, CopyFile, DedupSavings, MoveFile, NewFile, PhotoMetadata, StoredObject,
+            UploadedFile,
+        },
+        request::{ArchiveQuery, ImageQuery, QueryParams, ThumbnailQuery, UploadQuery},
         response::{AppErrorResponse, AppResponse, RouteResponse},
         state::AppState,
+        storage::ObjectBody,
     },
     traits::db_traits::SerializeList,
     utils::{
+        blob_utils::{
+            add_version, adopt_legacy_object, blob_encryption, flush_object_deletions, legacy_key,
+            new_blob_key, release_deleted_files,
+        },
+        crypto_utils::{open_object, open_object_range, owner_keyring},
         db_utils::{WhereBuilder, convert_filter_type},
-        file_utils::upload_file,
+        exif_utils::{photo_metadata_of, stripped_copy, strips_metadata},
+        file_type_utils::FILE_TYPES,
+        file_utils::{UploadBatch, extract_archive, upload_file},
+        format_utils::numbered_title,
+        image_utils::{
+            IMAGE_SOURCE_QUERY, Renderings, STRIPPED_VARIANT, image_presets, render_image,
+            transform_image,
+        },
+        range_utils::{byteranges_end, byteranges_part_header, resolve_ranges},
+        thumbnail_utils::{generate_thumbnails, spawn_image_processing},
+        trash_utils::{restore_tree, trash_tree},
+        tree_utils::{
+            copy_tree, create_parent_folders, delete_tree, folder_prefix, legacy_files_in_tree,
+            move_tree, split_relative_path,
+        },
+        zip_utils::{
+            ArchiveEntry, ZipEntry, ZipMember, archive_size, open_zip_member, read_zip_directory,
+            stream_archive,
+        },
     },
 };
 
@@ -45,19 +86,24 @@ fn default_public() -> bool {
 }
 
 #[derive(Deserialize, Serialize)]
-struct FileQuery {
+pub(crate) struct FileQuery {
     #[serde(default = "default_path")]
-    path: String,
+    pub(crate) path: String,
     #[serde(default = "default_public")]
-    is_public: bool,
+    pub(crate) is_public: bool,
     #[serde(default = "default_public")]
-    is_folder: bool,
+    pub(crate) is_folder: bool,
 }
 
 impl FileQuery {
-    fn format_path(&self, root: &str) -> String {
+    /// Value of the `path` column for files in this folder. It is empty or ends in `/`, so a
+    /// folder's children live under `path || title || '/'`.
+    pub(crate) fn format_path(&self, root: &str) -> String {
         if self.path.is_empty() {
-            root.to_string()
+            match root.is_empty() {
+                true => String::new(),
+                false => format!("{}/", root),
+            }
         } else if root.is_empty().not() {
             format!("{}/{}/", root, self.path)
         } else {
@@ -75,21 +121,30 @@ async fn upload_file_route(
     State(state): State<AppState>,
     Extension(session): Extension<AuthSession>,
     Query(query): Query<FileQuery>,
+    Query(upload_query): Query<UploadQuery>,
     mut payload: Multipart,
-) -> RouteResponse<Vec<Uuid>> {
+) -> RouteResponse<Vec<UploadedFile>> {
+    let keyring = owner_keyring(&state, &session.user.id).await?;
     let mut conn = state.get_db_conn().await?;
     let tx = conn
         .transaction()
         .await
         .map_err(|err| AppError::db_error(err))?;
 
-    let statement = tx
-        .prepare(
-            "INSERT INTO files (id, title, owner_id, size, type, path, is_public, hash)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (path, title, owner_id) DO NOTHING;",
-        )
-        .await
-        .map_err(|err| AppError::db_error(err))?;
+    let mut uploaded = vec![];
+    // * Folders are created as files reach them, each folder only once per request
+    let root_path = FileQuery {
+        path: String::new(),
+        is_public: false,
+        is_folder: true,
+    }
+    .format_path(&state.root);
+    let mut batch = UploadBatch::new(
+        session.user.id,
+        root_path,
+        query.is_public,
+        upload_query.conflict,
+    );
 
     debug!("WHILE LOOP FOR FIELDS");
 
@@ -99,111 +154,207 @@ async fn upload_file_route(
         .map_err(|err| AppError::default_response(err))?
     {
         debug!("ENTERED WHILE LOOP FOR FIELDS");
-        let field_name = field.name().unwrap_or_default();
+        let field_name = field.name().unwrap_or_default().to_string();
         if field_name.is_empty() {
             continue;
         }
         let file_id = Uuid::new_v4();
-        let title = field
+        let file_name = field
             .file_name()
             .map(|s| s.to_string())
             .unwrap_or(file_id.to_string());
 
         if field_name.ends_with(".tags") {
-            let mut tag_statement = String::from("INSERT INTO tags (owner_id, title) VALUES ");
-            let tags = field.text().await;
-
-            if let Ok(tags) = tags {
-                if tags.is_empty() {
-                    continue;
+            let tags = match field.text().await {
+                Ok(tags) if tags.is_empty() => continue,
+                Ok(tags) => {
+                    serde_json::from_str::<Vec<String>>(&tags).map_err(|err| err.to_string())
                 }
-                let tags: Result<Vec<String>, serde_json::Error> = serde_json::from_str(&tags);
-
-                if tags.is_err() {
-                    tracing::error!("ERROR PARSING TAGS - {}", tags.err().unwrap());
+                Err(err) => Err(err.to_string()),
+            };
+            let tags = match tags {
+                Ok(tags) => tags,
+                Err(err) => {
+                    tracing::error!("ERROR PARSING TAGS - {}", err);
+                    uploaded.push(UploadedFile::failed(
+                        field_name,
+                        None,
+                        UploadErrorReason::InvalidTags,
+                    ));
                     continue;
                 }
+            };
+            if tags.is_empty() {
+                continue;
+            }
 
-                let tags = tags.unwrap();
-                let tag_count = tags.len();
-                let mut inputs = vec![session.user.id.to_string()];
-                for (idx, tag) in tags.into_iter().enumerate() {
-                    tag_statement.push_str(&format!("($1, ${})", idx + 2));
-                    if idx < tag_count - 1 {
-                        tag_statement.push_str(", ");
-                    }
-                    if idx == tag_count - 1 {
-                        tag_statement.push_str(" ON CONFLICT (title, owner_id) DO NOTHING;");
-                    }
-                    inputs.push(String::from(tag.trim()));
+            let mut tag_statement = String::from("INSERT INTO tags (owner_id, title) VALUES ");
+            let tag_count = tags.len();
+            let mut inputs = vec![session.user.id.to_string()];
+            for (idx, tag) in tags.into_iter().enumerate() {
+                tag_statement.push_str(&format!("($1, ${})", idx + 2));
+                if idx < tag_count - 1 {
+                    tag_statement.push_str(", ");
                 }
+                if idx == tag_count - 1 {
+                    tag_statement.push_str(" ON CONFLICT (title, owner_id) DO NOTHING;");
+                }
+                inputs.push(String::from(tag.trim()));
+            }
 
-                let inputs_dyn: Vec<Box<dyn ToSql + Sync + Send>> = inputs
-                    .iter()
-                    .filter_map(convert_filter_type)
-                    .collect::<Vec<_>>();
+            let inputs_dyn: Vec<Box<dyn ToSql + Sync + Send>> = inputs
+                .iter()
+                .filter_map(convert_filter_type)
+                .collect::<Vec<_>>();
 
-                let inputs_dyn = inputs_dyn
-                    .iter()
-                    .map(|input| input.as_ref() as &(dyn ToSql + Sync))
-                    .collect::<Vec<_>>();
+            let inputs_dyn = inputs_dyn
+                .iter()
+                .map(|input| input.as_ref() as &(dyn ToSql + Sync))
+                .collect::<Vec<_>>();
 
-                tx.execute(&tag_statement, &inputs_dyn)
-                    .await
-                    .map_err(|err| AppError::db_error(err))?;
-                continue;
-            } else if tags.is_err() {
-                tracing::error!("ERROR PARSING TAGS - {}", tags.err().unwrap());
-                continue;
-            }
+            tx.execute(&tag_statement, &inputs_dyn)
+                .await
+                .map_err(|err| AppError::db_error(err))?;
             continue;
         }
 
-        let file_path = format!("{}{}", &query.format_path(&state.root), &title);
+        // * Names may carry a path relative to the target folder, as dropped folders do
+        let Some((relative_folder, title)) = split_relative_path(&file_name) else {
+            uploaded.push(UploadedFile::failed(
+                field_name,
+                Some(file_name),
+                UploadErrorReason::InvalidName,
+            ));
+            continue;
+        };
+        let folder = FileQuery {
+            path: [query.path.trim_matches('/'), &relative_folder]
+                .into_iter()
+                .filter(|path| !path.is_empty())
+                .collect::<Vec<_>>()
+                .join("/"),
+            is_public: query.is_public,
+            is_folder: true,
+        };
+
+        let file_path = new_blob_key(&state.root, &file_id);
+        // * Every blob gets its own data key
+        let encryption = keyring
+            .map(|keyring| keyring.new_encryption())
+            .transpose()
+            .map_err(|err| AppError::storage_error(err))?;
 
         debug!("BEGIN FILE UPLOAD");
-        let upload_result = upload_file(&state, field, &title, &file_path, &query.is_public).await;
+        let upload_result = upload_file(
+            &state,
+            field,
+            &title,
+            &file_path,
+            &query.is_public,
+            encryption.as_ref(),
+        )
+        .await;
         debug!("END FILE UPLOAD");
-        if let Ok((file_type, size, hash)) = upload_result {
-            //TODO: Optimize by using batch insert
-            let db_result = tx
-                .execute(
-                    &statement,
-                    &[
-                        &file_id,
-                        &title,
-                        &session.user.id,
-                        &size,
-                        &file_type.to_string(),
-                        &query.format_path(&state.root),
-                        &query.is_public,
-                        &hash.to_string(),
-                    ],
-                )
-                .await;
-
-            if db_result.is_err() {
-                AppError::db_error(db_result.err().unwrap());
-
-                let upload = state
-                    .s3_client
-                    .delete_object()
-                    .bucket(&state.s3_name)
-                    .key(file_path)
-                    .send()
-                    .await
-                    .map_err(|err| AppError::s3_error(err));
-                if upload.is_err() {
-                    continue;
+        let (verified, size, hash, etag) = match upload_result {
+            Ok(uploaded_file) => uploaded_file,
+            Err(reason) => {
+                uploaded.push(UploadedFile::failed(field_name, Some(file_name), reason));
+                continue;
+            }
+        };
+
+        // * Archives to extract were stored like any upload and are read back from storage
+        let file_type = verified.file_type;
+        if upload_query.extract
+            && (file_type == FileTypes::Zip || title.to_lowercase().ends_with(".zip"))
+        {
+            let archive = StoredObject {
+                key: file_path,
+                size,
+                encryption,
+            };
+            let extracted = extract_archive(
+                &state,
+                &tx,
+                &mut batch,
+                keyring,
+                archive,
+                &folder.path,
+                &title,
+                &field_name,
+            )
+            .await;
+            match extracted {
+                Ok(mut extracted) => uploaded.append(&mut extracted),
+                Err(err) => {
+                    batch.rolled_back(&state).await;
+                    return Err(AppError::db_error(err));
                 }
             }
-        } else {
             continue;
         }
+
+        let file = NewFile {
+            id: file_id,
+            title,
+            owner_id: session.user.id,
+            size,
+            file_type,
+            path: folder.format_path(&state.root),
+            is_public: query.is_public,
+            hash,
+            key: file_path,
+            etag,
+            encryption,
+        };
+        //TODO: Optimize by using batch insert
+        match batch.place(&tx, &file).await {
+            // * Relative to the storage root, like the `path` parameter
+            Ok(Some(inserted)) => uploaded.push(
+                UploadedFile::stored(
+                    field_name,
+                    file_name,
+                    &folder.format_path(""),
+                    inserted,
+                    &file,
+                )
+                .flagged(verified.mismatch),
+            ),
+            // * A file holds the name of a folder on the way
+            Ok(None) => uploaded.push(UploadedFile::failed(
+                field_name,
+                Some(file_name),
+                UploadErrorReason::PathConflict,
+            )),
+            // * A failed statement aborts the transaction, so nothing from this request lands
+            Err(err) => {
+                batch.rolled_back(&state).await;
+                return Err(AppError::db_error(err));
+            }
+        }
     }
 
-    tx.commit().await.map_err(|err| AppError::db_error(err))?;
-    Ok(AppResponse::default_response(vec![]))
+    if let Err(err) = tx.commit().await {
+        batch.rolled_back(&state).await;
+        return Err(AppError::db_error(err));
+    }
+    batch.committed(&state).await;
+    // * Versions past the owner's limit were queued
+    let _ = flush_object_deletions(&state, &mut conn).await;
+    spawn_image_processing(
+        &state,
+        uploaded
+            .iter()
+            .filter(|file| {
+                matches!(
+                    file.outcome,
+                    UploadOutcome::Created | UploadOutcome::Replaced | UploadOutcome::Renamed
+                )
+            })
+            .filter_map(|file| file.id)
+            .collect(),
+    );
+    Ok(AppResponse::default_response(uploaded))
 }
 
 async fn create_folder_route(
@@ -224,7 +375,7 @@ async fn create_folder_route(
                 &folder_id,
                 &payload.title,
                 &session.user.id,
-                &query.path,
+                &query.format_path(&state.root),
                 &query.is_public,
             ],
         )
@@ -235,89 +386,674 @@ async fn create_folder_route(
 
 async fn download_file(
     State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<Uuid>,
-    Query(query): Query<FileQuery>,
+    headers: HeaderMap,
 ) -> Result<Response<Body>, AppErrorResponse> {
-    let conn = state.get_db_conn().await?;
+    let mut conn = state.get_db_conn().await?;
 
     let row = conn
-        .query_one(
-            "SELECT id, created_at, title, type FROM files WHERE id = $1;",
-            &[&id],
+        .query_opt(
+            "SELECT COALESCE(files.updated_at, files.created_at) AS created_at, files.title,
+                files.path, files.type, files.size, files.etag, files.owner_id, files.version,
+                blobs.key AS blob_key, blobs.key_id, blobs.data_key, blobs.nonce
+            FROM files LEFT JOIN blobs ON blobs.hash = files.blob_hash
+            WHERE files.id = $1 AND (files.owner_id = $2 OR files.is_public)
+                AND files.deleted_at IS NULL;",
+            &[&id, &session.user.id],
         )
         .await
-        .map_err(|err| AppError::db_error(err))?;
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
 
-    let title: String = row.get("title");
+    // * Others get the stripped copy, told apart from the original by its own validator
     let file_type: FileTypes = row.get("type");
+    if row.get::<_, Uuid>("owner_id") != session.user.id
+        && file_type.has_exif()
+        && strips_metadata(&conn, &id)
+            .await
+            .map_err(|err| AppError::db_error(err))?
+    {
+        let object = stripped_object(&state, &mut conn, &id).await?;
+        let etag = format!(
+            "\"{}-{}-{}\"",
+            id.simple(),
+            row.get::<_, i32>("version"),
+            STRIPPED_VARIANT
+        )
+        .parse::<ETag>()
+        .ok();
+        return object_response(&state, &object, etag, &row, &headers).await;
+    }
+
+    file_response(&state, &row, &headers).await
+}
+
+/// The copy of an image served without its metadata, made on first use.
+async fn stripped_object(
+    state: &AppState,
+    conn: &mut Object,
+    id: &Uuid,
+) -> Result<StoredObject, AppErrorResponse> {
+    let source = conn
+        .query_opt(&format!("{} AND files.id = $1;", IMAGE_SOURCE_QUERY), &[id])
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    if !source.get::<_, FileTypes>("type").is_strippable() {
+        return Err(AppError::storage_error(StorageError::Unsupported(
+            "stripping metadata from this image type",
+        )));
+    }
 
-    let resp = state
-        .s3_client
-        .get_object()
-        .bucket(&state.s3_name)
-        .key(format!("{}{}", &query.format_path(&state.root), title))
-        .send()
+    let object = stripped_copy(state, &*conn, &source)
         .await
-        .map_err(|err| AppError::s3_error(err))?;
+        .map_err(|err| AppError::render_error(err))?;
+    // * The copy of an older version was queued
+    let _ = flush_object_deletions(state, conn).await;
+    Ok(object)
+}
 
-    let content_length = resp.content_length().unwrap_or(0);
-    let stream = resp.body.into_async_read();
-    let reader_stream = ReaderStream::new(stream);
+/// Answers a download of the content in `row`, honouring conditional and range headers. The
+/// row selects `created_at, title, path, type, size, etag` and the blob's `blob_key, key_id,
+/// data_key, nonce`.
+async fn file_response(
+    state: &AppState,
+    row: &Row,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let blob_key: Option<String> = row.get("blob_key");
+    let object = StoredObject {
+        key: blob_key.unwrap_or_else(|| legacy_key(row)),
+        size: row.get("size"),
+        encryption: blob_encryption(row),
+    };
+    let etag = row
+        .get::<_, Option<String>>("etag")
+        .and_then(|etag| etag.parse::<ETag>().ok());
+    object_response(state, &object, etag, row, headers).await
+}
 
-    let body = Body::from_stream(reader_stream);
+/// Answers a download of `object` as the file in `row`, with `etag` and the row's
+/// `created_at` as validators for conditional and range headers.
+async fn object_response(
+    state: &AppState,
+    object: &StoredObject,
+    etag: Option<ETag>,
+    row: &Row,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let title: String = row.get("title");
+    let file_type: FileTypes = row.get("type");
+    // * HTTP dates only carry whole seconds, so compare against a truncated created_at
+    let last_modified = row
+        .get::<_, Option<Timestamp>>("created_at")
+        .map(|created_at| {
+            LastModified::from(UNIX_EPOCH + Duration::from_secs(created_at.as_second() as u64))
+        });
 
-    let response = Response::builder()
-        .header(CONTENT_TYPE, file_type.content_type())
+    // * If-None-Match takes precedence over If-Modified-Since when both are sent
+    let is_fresh = match headers.typed_get::<IfNoneMatch>() {
+        Some(if_none_match) => etag
+            .as_ref()
+            .is_some_and(|etag| !if_none_match.precondition_passes(etag)),
+        None => headers
+            .typed_get::<IfModifiedSince>()
+            .zip(last_modified)
+            .is_some_and(|(since, modified)| !since.is_modified(modified.into())),
+    };
+
+    let mut response = match is_fresh {
+        true => Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .map_err(|err| AppError::critical_error(err))?,
+        false => {
+            // * A stale If-Range means the client's partial copy is outdated, so send everything
+            let range = headers.typed_get::<Range>().filter(|_| {
+                headers.typed_get::<IfRange>().is_none_or(|if_range| {
+                    !if_range.is_modified(etag.as_ref(), last_modified.as_ref())
+                })
+            });
+
+            let mut response = match range {
+                Some(range) => download_ranges(state, object, &range, &file_type).await?,
+                None => {
+                    let body = open_object(state, object)
+                        .await
+                        .map_err(|err| AppError::storage_error(err))?;
+
+                    Response::builder()
+                        .header(CONTENT_TYPE, file_type.content_type())
+                        .header(CONTENT_LENGTH, object.size)
+                        .body(Body::from_stream(ReaderStream::new(body)))
+                        .map_err(|err| AppError::critical_error(err))?
+                }
+            };
+            response.headers_mut().insert(
+                CONTENT_DISPOSITION,
+                HeaderValue::from_str(&format!("attachment; filename=\"{}.{}\"", title, file_type))
+                    .map_err(|err| AppError::critical_error(err))?,
+            );
+            response
+        }
+    };
+
+    let response_headers = response.headers_mut();
+    response_headers.typed_insert(AcceptRanges::bytes());
+    if let Some(etag) = etag {
+        response_headers.typed_insert(etag);
+    }
+    if let Some(last_modified) = last_modified {
+        response_headers.typed_insert(last_modified);
+    }
+    Ok(response)
+}
+
+async fn download_ranges(
+    state: &AppState,
+    object: &StoredObject,
+    range: &Range,
+    file_type: &FileTypes,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let size = object.size as u64;
+    let ranges = resolve_ranges(range, size);
+
+    if ranges.is_empty() {
+        let mut response = Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .body(Body::empty())
+            .map_err(|err| AppError::critical_error(err))?;
+        response
+            .headers_mut()
+            .typed_insert(ContentRange::unsatisfied_bytes(size));
+        return Ok(response);
+    }
+
+    if let [(start, end)] = ranges[..] {
+        let body = open_object_range(state, object, start, end)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        let mut response = Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(CONTENT_TYPE, file_type.content_type())
+            .header(CONTENT_LENGTH, end - start + 1)
+            .body(Body::from_stream(ReaderStream::new(body)))
+            .map_err(|err| AppError::critical_error(err))?;
+        response.headers_mut().typed_insert(
+            ContentRange::bytes(start..=end, size).map_err(|err| AppError::critical_error(err))?,
+        );
+        return Ok(response);
+    }
+
+    // * Too many ranges is answered with the whole file, which RFC 9110 allows
+    if ranges.len() > MAX_RANGES {
+        let body = open_object(state, object)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        return Response::builder()
+            .header(CONTENT_TYPE, file_type.content_type())
+            .header(CONTENT_LENGTH, size)
+            .body(Body::from_stream(ReaderStream::new(body)))
+            .map_err(|err| AppError::critical_error(err));
+    }
+
+    let boundary = Uuid::new_v4().simple().to_string();
+    let mut content_length = 0;
+    let mut body: ObjectBody = Box::pin(tokio::io::empty());
+    for (idx, range) in ranges.into_iter().enumerate() {
+        let part = open_object_range(state, object, range.0, range.1)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        let separator = if idx == 0 { "" } else { "\r\n" };
+        let part_header = format!(
+            "{}{}",
+            separator,
+            byteranges_part_header(&boundary, file_type.content_type(), range, size)
+        );
+        content_length += part_header.len() as u64 + range.1 - range.0 + 1;
+        body = Box::pin(body.chain(Cursor::new(part_header)).chain(part));
+    }
+    let end = byteranges_end(&boundary);
+    content_length += end.len() as u64;
+    body = Box::pin(body.chain(Cursor::new(end)));
+
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/byteranges; boundary={}", boundary),
+        )
         .header(CONTENT_LENGTH, content_length)
+        .body(Body::from_stream(ReaderStream::new(body)))
+        .map_err(|err| AppError::critical_error(err))
+}
+
+async fn download_archive(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Query(query): Query<ArchiveQuery>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let mut ids: Vec<Uuid> = Vec::new();
+    for id in query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+    {
+        let id = Uuid::parse_str(id)
+            .map_err(|_| AppError::bad_request_response(format!("Invalid id {:?}", id)))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Err(AppError::bad_request_response("No files selected"));
+    }
+
+    let conn = state.get_db_conn().await?;
+    let rows = conn
+        .query(
+            "SELECT id, path, title, type FROM files
+            WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL;",
+            &[&ids, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    let mut selected = Vec::with_capacity(ids.len());
+    for id in &ids {
+        let row = rows
+            .iter()
+            .find(|row| row.get::<_, Uuid>("id") == *id)
+            .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+        let title: String = row.get("title");
+        let prefix = (row.get::<_, &str>("type") == "folder")
+            .then(|| folder_prefix(row.get("path"), &title));
+        selected.push((*id, row.get::<_, String>("path"), title, prefix));
+    }
+    // * A selection inside a selected folder is already part of that folder's tree
+    let prefixes: Vec<String> = selected
+        .iter()
+        .filter_map(|(.., prefix)| prefix.clone())
+        .collect();
+    selected.retain(|(_, path, ..)| !prefixes.iter().any(|prefix| path.starts_with(prefix)));
+
+    let mut entries = Vec::new();
+    let mut top_names = HashSet::new();
+    for (id, root_path, root_title, prefix) in &selected {
+        // * Same named selections from different folders are numbered like upload renames
+        let mut top_name = root_title.clone();
+        let mut number = 0;
+        while !top_names.insert(top_name.clone()) {
+            number += 1;
+            top_name = numbered_title(root_title, number);
+        }
+
+        let rows = conn
+            .query(
+                "SELECT files.path, files.title, files.type, files.size,
+                    COALESCE(files.updated_at, files.created_at) AS created_at,
+                    blobs.key AS blob_key, blobs.key_id, blobs.data_key, blobs.nonce
+                FROM files LEFT JOIN blobs ON blobs.hash = files.blob_hash
+                WHERE files.owner_id = $1
+                    AND files.deleted_at IS NULL
+                    AND (files.id = $2 OR starts_with(files.path, $3))
+                ORDER BY files.path, files.title;",
+                &[&session.user.id, id, prefix],
+            )
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+
+        for row in rows {
+            let path: &str = row.get("path");
+            let title: &str = row.get("title");
+            let is_folder = row.get::<_, &str>("type") == "folder";
+            // * Relative to the selection's parent, with the selection renamed to `top_name`
+            let relative = format!("{}{}", &path[root_path.len()..], title);
+            let name = format!(
+                "{}{}{}",
+                top_name,
+                &relative[root_title.len()..],
+                if is_folder { "/" } else { "" }
+            );
+            let size = if is_folder { 0 } else { row.get("size") };
+            entries.push(ArchiveEntry {
+                entry: ZipEntry {
+                    name,
+                    size: size as u64,
+                    // * Both timestamps are nullable columns
+                    modified: row
+                        .get::<_, Option<Timestamp>>("created_at")
+                        .unwrap_or_else(Timestamp::now),
+                    is_folder,
+                },
+                object: (!is_folder).then(|| StoredObject {
+                    key: row
+                        .get::<_, Option<String>>("blob_key")
+                        .unwrap_or_else(|| legacy_key(&row)),
+                    size,
+                    encryption: blob_encryption(&row),
+                }),
+            });
+        }
+    }
+    drop(conn);
+
+    let file_name = match selected.as_slice() {
+        [(.., title, Some(_))] => format!("{}.zip", title),
+        _ => String::from("archive.zip"),
+    };
+    let size = archive_size(entries.iter().map(|entry| &entry.entry));
+    let body = stream_archive(state, entries);
+
+    Response::builder()
+        .header(CONTENT_TYPE, FileTypes::Zip.content_type())
+        .header(CONTENT_LENGTH, size)
         .header(
             CONTENT_DISPOSITION,
-            format!("attachment; filename=\"{}.{}\"", title, file_type),
+            HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name))
+                .map_err(|err| AppError::critical_error(err))?,
+        )
+        .body(Body::from_stream(ReaderStream::new(body)))
+        .map_err(|err| AppError::critical_error(err))
+}
+
+/// Where the content of the live ZIP archive `id` of `owner_id` is stored.
+async fn stored_archive(
+    state: &AppState,
+    owner_id: &Uuid,
+    id: &Uuid,
+) -> Result<StoredObject, AppErrorResponse> {
+    let conn = state.get_db_conn().await?;
+    let row = conn
+        .query_opt(
+            "SELECT files.path, files.title, files.type, files.size,
+                blobs.key AS blob_key, blobs.key_id, blobs.data_key, blobs.nonce
+            FROM files LEFT JOIN blobs ON blobs.hash = files.blob_hash
+            WHERE files.id = $1
+                AND files.owner_id = $2
+                AND files.deleted_at IS NULL
+                AND files.type <> 'folder';",
+            &[id, owner_id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+
+    let title: String = row.get("title");
+    if row.get::<_, FileTypes>("type") != FileTypes::Zip && !title.to_lowercase().ends_with(".zip")
+    {
+        return Err(AppError::bad_request_response(format!(
+            "{} is not a ZIP archive",
+            id
+        )));
+    }
+    Ok(StoredObject {
+        key: row
+            .get::<_, Option<String>>("blob_key")
+            .unwrap_or_else(|| legacy_key(&row)),
+        size: row.get("size"),
+        encryption: blob_encryption(&row),
+    })
+}
+
+async fn list_archive_entries(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> RouteResponse<Vec<ZipMember>> {
+    let archive = stored_archive(&state, &session.user.id, &id).await?;
+    let members = read_zip_directory(&state, &archive)
+        .await
+        .map_err(|err| AppError::archive_error(err))?;
+
+    Ok(AppResponse::default_response(members))
+}
+
+async fn download_archive_entry(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path((id, name)): Path<(Uuid, String)>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let archive = stored_archive(&state, &session.user.id, &id).await?;
+    // * Only the directory and the entry's own bytes are read, never the whole archive. A
+    // * name listed twice means the later entry, as unzip tools do
+    let member = read_zip_directory(&state, &archive)
+        .await
+        .map_err(|err| AppError::archive_error(err))?
+        .into_iter()
+        .rev()
+        .find(|member| !member.is_folder && member.name == name)
+        .ok_or_else(|| AppError::not_found_response(format!("No entry {:?} in {}", name, id)))?;
+    let body = open_zip_member(&state, &archive, &member)
+        .await
+        .map_err(|err| AppError::archive_error(err))?;
+
+    let title = name.rsplit('/').next().unwrap_or(&name);
+    Response::builder()
+        .header(
+            CONTENT_TYPE,
+            FileTypes::from_file_name(title).content_type(),
+        )
+        .header(CONTENT_LENGTH, member.size)
+        .header(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_str(&format!("attachment; filename=\"{}\"", title))
+                .map_err(|err| AppError::critical_error(err))?,
+        )
+        .body(Body::from_stream(ReaderStream::new(body)))
+        .map_err(|err| AppError::critical_error(err))
+}
+
+async fn get_thumbnail(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let mut conn = state.get_db_conn().await?;
+    let source = conn
+        .query_opt(
+            &format!(
+                "{} AND files.id = $1 AND files.owner_id = $2;",
+                IMAGE_SOURCE_QUERY
+            ),
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let file_type: FileTypes = source.get("type");
+    if !file_type.is_renderable() {
+        return Err(AppError::not_found_response(format!(
+            "No thumbnails for {} files",
+            file_type
+        )));
+    }
+    let version: i32 = source.get("version");
+
+    // * Missing, outdated or lost thumbnails are rendered again, once
+    let mut is_rendered = false;
+    let (thumbnail, body) = loop {
+        let thumbnail = Renderings::Thumbnails
+            .current(&conn, &id, version, &query.size.to_string())
+            .await
+            .map_err(|err| AppError::render_error(err))?;
+        if let Some(thumbnail) = thumbnail {
+            let object = StoredObject {
+                key: thumbnail.get("key"),
+                size: thumbnail.get("byte_size"),
+                encryption: blob_encryption(&thumbnail),
+            };
+            match open_object(&state, &object).await {
+                Ok(body) => break (thumbnail, body),
+                Err(StorageError::NotFound(_)) if !is_rendered => {}
+                Err(err) => return Err(AppError::storage_error(err)),
+            }
+        } else if is_rendered {
+            return Err(AppError::critical_error(format!(
+                "Thumbnail of {} missing after rendering",
+                id
+            )));
+        }
+
+        generate_thumbnails(&state, &conn, &source)
+            .await
+            .map_err(|err| AppError::render_error(err))?;
+        is_rendered = true;
+    };
+    if is_rendered {
+        // * Thumbnails of an older version were queued
+        let _ = flush_object_deletions(&state, &mut conn).await;
+    }
+
+    image_response(
+        &thumbnail.get("type"),
+        thumbnail.get("byte_size"),
+        Body::from_stream(ReaderStream::new(body)),
+    )
+}
+
+async fn get_image(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ImageQuery>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let mut conn = state.get_db_conn().await?;
+    let presets = image_presets(&conn, &session.user.id)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    let transform = match &query.preset {
+        Some(name) => presets
+            .into_iter()
+            .find(|preset| preset.name == *name)
+            .map(|preset| preset.transform)
+            .ok_or_else(|| AppError::not_found_response(format!("No image preset {}", name)))?,
+        None => {
+            let transform = query.transform();
+            if !presets.iter().any(|preset| preset.transform == transform) {
+                return Err(AppError::forbidden_response(
+                    "The transform matches none of your image presets",
+                ));
+            }
+            transform
+        }
+    };
+
+    let source = conn
+        .query_opt(
+            &format!(
+                "{} AND files.id = $1 AND files.owner_id = $2;",
+                IMAGE_SOURCE_QUERY
+            ),
+            &[&id, &session.user.id],
         )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let file_type: FileTypes = source.get("type");
+    if !file_type.is_renderable() {
+        return Err(AppError::not_found_response(format!(
+            "Cannot transform {} files",
+            file_type
+        )));
+    }
+
+    let variant = transform.variant();
+    let cached = Renderings::Variants
+        .current(&conn, &id, source.get("version"), &variant)
+        .await
+        .map_err(|err| AppError::render_error(err))?;
+    if let Some(cached) = cached {
+        let object = StoredObject {
+            key: cached.get("key"),
+            size: cached.get("byte_size"),
+            encryption: blob_encryption(&cached),
+        };
+        // * A lost object is rendered again
+        match open_object(&state, &object).await {
+            Ok(body) => {
+                return image_response(
+                    &cached.get("type"),
+                    object.size,
+                    Body::from_stream(ReaderStream::new(body)),
+                );
+            }
+            Err(StorageError::NotFound(_)) => {}
+            Err(err) => return Err(AppError::storage_error(err)),
+        }
+    }
+
+    let output = transform.format.file_type();
+    let body = render_image(&state, &source, move |image| {
+        transform_image(image, &transform)
+    })
+    .await
+    .map_err(|err| AppError::render_error(err))?;
+    Renderings::Variants
+        .store(&state, &conn, &source, &variant, &body, &output)
+        .await
+        .map_err(|err| AppError::render_error(err))?;
+    // * The rendering of an older version was queued
+    let _ = flush_object_deletions(&state, &mut conn).await;
+
+    image_response(&output, body.len() as i64, Body::from(body))
+}
+
+fn image_response(
+    file_type: &FileTypes,
+    size: i64,
+    body: Body,
+) -> Result<Response<Body>, AppErrorResponse> {
+    Response::builder()
+        .header(CONTENT_TYPE, file_type.content_type())
+        .header(CONTENT_LENGTH, size)
         .body(body)
-        .map_err(|err| AppError::critical_error(err))?;
-    Ok(response)
+        .map_err(|err| AppError::critical_error(err))
 }
 
 async fn generate_link(
     State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<Uuid>,
 ) -> RouteResponse<String> {
-    let conn = state.get_db_conn().await?;
+    let mut conn = state.get_db_conn().await?;
 
     let row = conn
-        .query_one("SELECT title, path FROM files WHERE id = $1;", &[&id])
+        .query_opt(
+            "SELECT files.title, files.path, files.type, blobs.key AS blob_key, blobs.key_id
+            FROM files LEFT JOIN blobs ON blobs.hash = files.blob_hash
+            WHERE files.id = $1 AND (files.owner_id = $2 OR files.is_public)
+                AND files.deleted_at IS NULL;",
+            &[&id, &session.user.id],
+        )
         .await
-        .map_err(|err| AppError::db_error(err))?;
-    let path: Option<String> = row.get("path");
-    let title: String = row.get("title");
-
-    let provider =
-        S3Providers::from_str(&state.s3_provider).map_err(|err| AppError::critical_error(err))?;
-    let link = match provider {
-        S3Providers::DigitalOcean => format!(
-            "https://{bucket}.{region}.cdn.digitaloceanspaces.com/{path}{id}",
-            bucket = state.s3_name,
-            region = state.s3_region,
-            path = path
-                .map(|p| match p.is_empty() {
-                    true => state.root.to_string(),
-                    false => format!("{}/{}/", &state.root, p),
-                })
-                .unwrap_or_default(),
-            id = title
-        ),
-        S3Providers::AWS => {
-            let p = state
-                .s3_client
-                .get_object()
-                .bucket(state.s3_name)
-                .presigned(PresigningConfig::expires_in(Duration::from_secs(3600)).unwrap())
-                .await
-                .map_err(|err| AppError::s3_error(err))?;
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let blob_key: Option<String> = row.get("blob_key");
+    // * A link hands out the stored bytes, which only the server can decrypt
+    if row.get::<_, Option<String>>("key_id").is_some() {
+        return Err(AppError::storage_error(StorageError::Unsupported(
+            "links to encrypted files",
+        )));
+    }
 
-            p.uri().to_owned()
-        }
+    // * Links are handed on, so they lead to the stripped copy whoever asks
+    let file_type: FileTypes = row.get("type");
+    let strip = file_type.has_exif()
+        && strips_metadata(&conn, &id)
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+    let key = match strip {
+        true => stripped_object(&state, &mut conn, &id).await?.key,
+        false => blob_key.unwrap_or_else(|| legacy_key(&row)),
     };
+    let link = state
+        .storage
+        .generate_link(&key, Duration::from_secs(3600))
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
 
     Ok(AppResponse::default_response(link))
 }
@@ -335,16 +1071,35 @@ async fn list_files(
         true => "ORDER BY type = 'folder' desc",
         false => &query_sort.replace("ORDER BY", "ORDER BY type = 'folder' desc, "),
     };
-    let mut sql_params = vec![query.path, session.user.id.to_string()];
+    let folder = FileQuery {
+        path: query.path,
+        is_public: false,
+        is_folder: true,
+    };
+    let mut sql_params = vec![folder.format_path(&state.root), session.user.id.to_string()];
 
     let mut builder = WhereBuilder::new(&Models::Files, Some(sql_params.len()));
     let (filter_where, filter_params) = builder.build_where_clause(filters)?;
     sql_params.extend(filter_params);
-    let inputs_dyn: Vec<Box<dyn ToSql + Sync + Send>> = sql_params
+    let mut inputs_dyn: Vec<Box<dyn ToSql + Sync + Send>> = sql_params
         .iter()
         .filter_map(convert_filter_type)
         .collect::<Vec<_>>();
 
+    // * Folders stay listed so the category can be browsed into
+    let category_where = match query.category {
+        Some(category) => {
+            let names = FILE_TYPES
+                .names_in(category)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            inputs_dyn.push(Box::new(names));
+            format!("(type = 'folder' OR type = ANY(${}))", inputs_dyn.len())
+        }
+        None => String::from("TRUE"),
+    };
+
     let inputs_dyn = inputs_dyn
         .iter()
         .map(|input| input.as_ref() as &(dyn ToSql + Sync))
@@ -352,13 +1107,17 @@ async fn list_files(
 
     let stmt = format!(
         "
-        SELECT id, created_at, title, type, size, is_public, path
+        SELECT id, created_at, title, type, size, is_public, path, taken_at
         FROM files
         WHERE
             path = $1
                 AND
             owner_id = $2
                 AND
+            deleted_at IS NULL
+                AND
+            {category_where}
+                AND
             {where_clause}
         {sort}
         LIMIT 25
@@ -378,7 +1137,6 @@ async fn list_files(
 async fn delete_file(
     Extension(session): Extension<AuthSession>,
     State(state): State<AppState>,
-    Query(query): Query<FileQuery>,
     Path(id): Path<Uuid>,
 ) -> RouteResponse<Uuid> {
     let mut conn = state.get_db_conn().await?;
@@ -387,28 +1145,473 @@ async fn delete_file(
         .await
         .map_err(|err| AppError::db_error(err))?;
 
-    tx.execute(
-        "DELETE FROM files WHERE id = $1 AND owner_id = $2;",
-        &[&id, &session.user.id],
-    )
-    .await
-    .map_err(|err| AppError::db_error(err))?;
+    let row = tx
+        .query_opt(
+            "SELECT path, title, type FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL
+            FOR UPDATE;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let prefix = (row.get::<_, &str>("type") == "folder")
+        .then(|| folder_prefix(row.get("path"), row.get("title")));
+
+    // * Objects stay until the trash is emptied or purged
+    trash_tree(&tx, &session.user.id, &id, prefix.as_deref())
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(id))
+}
+
+async fn list_trash(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+) -> RouteResponse<Value> {
+    let conn = state.get_db_conn().await?;
+
+    // * Rows trashed along with their folder are listed through the folder
+    let rows = conn
+        .query(
+            "SELECT id, created_at, deleted_at, title, type, size, is_public, path
+            FROM files AS trashed
+            WHERE owner_id = $1
+                AND deleted_at IS NOT NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM files AS parent
+                    WHERE parent.owner_id = trashed.owner_id
+                        AND parent.type = 'folder'
+                        AND parent.deleted_at = trashed.deleted_at
+                        AND parent.path || parent.title || '/' = trashed.path
+                )
+            ORDER BY deleted_at DESC, type = 'folder' DESC, title;",
+            &[&session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(rows.serialize_list()))
+}
+
+async fn restore_file(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> RouteResponse<Uuid> {
+    let mut conn = state.get_db_conn().await?;
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    let row = tx
+        .query_opt(
+            "SELECT path, title, type, deleted_at FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NOT NULL
+            FOR UPDATE;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No trashed file {}", id)))?;
+    let path: String = row.get("path");
+    let deleted_at: Timestamp = row.get("deleted_at");
+    let prefix =
+        (row.get::<_, &str>("type") == "folder").then(|| folder_prefix(&path, row.get("title")));
+
+    // * Folders trashed or deleted since are recreated, so the tree lands where it was
+    let root_path = FileQuery {
+        path: String::new(),
+        is_public: false,
+        is_folder: true,
+    }
+    .format_path(&state.root);
+    let has_parent = create_parent_folders(&tx, &session.user.id, &root_path, &path, false)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    if !has_parent {
+        return Err(AppError::conflict_response(format!(
+            "A file holds the place of a folder above {}",
+            id
+        )));
+    }
+    restore_tree(&tx, &session.user.id, &id, prefix.as_deref(), &deleted_at)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(id))
+}
+
+async fn delete_trashed_file(
+    Extension(session): Extension<AuthSession>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> RouteResponse<Uuid> {
+    let mut conn = state.get_db_conn().await?;
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    let row = tx
+        .query_opt(
+            "SELECT path, title, type, deleted_at FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NOT NULL
+            FOR UPDATE;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No trashed file {}", id)))?;
+    let deleted_at: Timestamp = row.get("deleted_at");
+    let prefix = (row.get::<_, &str>("type") == "folder")
+        .then(|| folder_prefix(row.get("path"), row.get("title")));
+
+    // * The whole tree goes in one transaction, its objects are queued alongside
+    let rows = delete_tree(&tx, &session.user.id, &id, prefix.as_deref(), &deleted_at)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    release_deleted_files(&tx, &rows)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+    // * Objects that fail to delete stay queued for the next flush
+    let _ = flush_object_deletions(&state, &mut conn).await;
+
+    Ok(AppResponse::default_response(id))
+}
+
+/// The folder, in the form of the `path` parameter, and title a file is moved or copied to.
+/// They are checked like the relative names of uploads, so `.` and `..` segments and titles
+/// with separators are refused.
+fn destination(folder: &str, title: &str) -> Result<(String, String), AppErrorResponse> {
+    match split_relative_path(&format!("{}/{}", folder, title)) {
+        Some((folder, checked)) if checked == title => Ok((folder, checked)),
+        _ => Err(AppError::bad_request_response(format!(
+            "Invalid destination {:?} in {:?}",
+            title, folder
+        ))),
+    }
+}
+
+/// Creates the folders leading to `path`, the formatted form of `folder`, like uploads do.
+async fn create_destination(
+    client: &impl GenericClient,
+    state: &AppState,
+    owner_id: &Uuid,
+    path: &str,
+    folder: &str,
+) -> Result<(), AppErrorResponse> {
+    let root_path = FileQuery {
+        path: String::new(),
+        is_public: false,
+        is_folder: true,
+    }
+    .format_path(&state.root);
+    let has_parent = create_parent_folders(client, owner_id, &root_path, path, false)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    match has_parent {
+        true => Ok(()),
+        false => Err(AppError::conflict_response(format!(
+            "A file holds the place of a folder in {:?}",
+            folder
+        ))),
+    }
+}
+
+async fn move_file(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<MoveFile>,
+) -> RouteResponse<Uuid> {
+    let mut conn = state.get_db_conn().await?;
+    let row = conn
+        .query_opt(
+            "SELECT path, title, type FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let current_path: String = row.get("path");
+    let current_title: String = row.get("title");
+    let prefix = (row.get::<_, String>("type") == "folder")
+        .then(|| folder_prefix(&current_path, &current_title));
+
+    let title = payload.title.unwrap_or_else(|| current_title.clone());
+    let (folder, title) = destination(payload.path.as_deref().unwrap_or_default(), &title)?;
+    let path = match payload.path {
+        Some(_) => FileQuery {
+            path: folder.clone(),
+            is_public: false,
+            is_folder: true,
+        }
+        .format_path(&state.root),
+        None => current_path.clone(),
+    };
+    if prefix
+        .as_ref()
+        .is_some_and(|prefix| path.starts_with(prefix))
+    {
+        return Err(AppError::bad_request_response(format!(
+            "Folder {} cannot be moved into itself",
+            id
+        )));
+    }
+    if path == current_path && title == current_title {
+        return Ok(AppResponse::default_response(id));
+    }
+
+    // * Objects still keyed by their path would be stranded by the move
+    let legacy = legacy_files_in_tree(&conn, &session.user.id, &id, prefix.as_deref())
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    for row in legacy {
+        adopt_legacy_object(
+            &state,
+            &mut conn,
+            &row.get("id"),
+            &legacy_key(&row),
+            row.get("is_public"),
+        )
+        .await?;
+    }
+
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    create_destination(&tx, &state, &session.user.id, &path, &folder).await?;
+    move_tree(&tx, &session.user.id, &id, prefix.as_deref(), &path, &title)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(id))
+}
+
+async fn copy_file(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CopyFile>,
+) -> RouteResponse<Vec<CopiedFile>> {
+    let mut conn = state.get_db_conn().await?;
+    let row = conn
+        .query_opt(
+            "SELECT path, title, type FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+    let source_title: String = row.get("title");
+    let prefix = (row.get::<_, String>("type") == "folder")
+        .then(|| folder_prefix(row.get("path"), &source_title));
+
+    let title = payload.title.unwrap_or(source_title);
+    let (folder, title) = destination(&payload.path, &title)?;
+    let path = FileQuery {
+        path: folder.clone(),
+        is_public: false,
+        is_folder: true,
+    }
+    .format_path(&state.root);
+
+    // * Copies share blobs, so objects still keyed by their path get one first
+    let legacy = legacy_files_in_tree(&conn, &session.user.id, &id, prefix.as_deref())
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    for row in legacy {
+        adopt_legacy_object(
+            &state,
+            &mut conn,
+            &row.get("id"),
+            &legacy_key(&row),
+            row.get("is_public"),
+        )
+        .await?;
+    }
+
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    create_destination(&tx, &state, &session.user.id, &path, &folder).await?;
+    let copied = copy_tree(&tx, &session.user.id, &id, prefix.as_deref(), &path, &title)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(copied))
+}
+
+async fn get_photo_metadata(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> RouteResponse<PhotoMetadata> {
+    let conn = state.get_db_conn().await?;
+    let metadata = photo_metadata_of(&conn, &id, &session.user.id)
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| AppError::not_found_response(format!("No file {}", id)))?;
+
+    Ok(AppResponse::default_response(metadata))
+}
 
-    let key = format!("{}{}", &query.format_path(&state.root), id);
-    state
-        .s3_client
-        .delete_object()
-        .bucket(&state.s3_name)
-        .key(&key)
-        .send()
+async fn list_versions(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> RouteResponse<Value> {
+    let conn = state.get_db_conn().await?;
+
+    let rows = conn
+        .query(
+            "SELECT version, size, etag, COALESCE(updated_at, created_at) AS created_at,
+                true AS is_current
+            FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL AND type <> 'folder'
+            UNION ALL
+            SELECT file_versions.version, file_versions.size, file_versions.etag,
+                file_versions.created_at, false
+            FROM file_versions JOIN files ON files.id = file_versions.file_id
+            WHERE files.id = $1 AND files.owner_id = $2 AND files.deleted_at IS NULL
+            ORDER BY version DESC;",
+            &[&id, &session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    if rows.is_empty() {
+        return Err(AppError::not_found_response(format!("No file {}", id)));
+    }
+
+    Ok(AppResponse::default_response(rows.serialize_list()))
+}
+
+async fn download_version(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path((id, version)): Path<(Uuid, i32)>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let conn = state.get_db_conn().await?;
+
+    let row = conn
+        .query_opt(
+            "SELECT version.created_at, version.title, version.path, version.type, version.size,
+                version.etag, blobs.key AS blob_key, blobs.key_id, blobs.data_key, blobs.nonce
+            FROM (
+                SELECT COALESCE(updated_at, created_at) AS created_at, title, path, type, size,
+                    etag, blob_hash
+                FROM files
+                WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL AND version = $3
+                UNION ALL
+                SELECT file_versions.created_at, files.title, files.path, files.type,
+                    file_versions.size, file_versions.etag, file_versions.blob_hash
+                FROM file_versions JOIN files ON files.id = file_versions.file_id
+                WHERE files.id = $1
+                    AND files.owner_id = $2
+                    AND files.deleted_at IS NULL
+                    AND file_versions.version = $3
+            ) AS version
+            LEFT JOIN blobs ON blobs.hash = version.blob_hash;",
+            &[&id, &session.user.id, &version],
+        )
         .await
-        .map_err(|err| AppError::s3_error(err))?;
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| {
+            AppError::not_found_response(format!("No version {} of file {}", version, id))
+        })?;
+
+    file_response(&state, &row, &headers).await
+}
 
+async fn restore_version(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path((id, version)): Path<(Uuid, i32)>,
+) -> RouteResponse<Uuid> {
+    let mut conn = state.get_db_conn().await?;
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    let row = tx
+        .query_opt(
+            "SELECT file_versions.blob_hash, file_versions.hash, file_versions.size
+            FROM file_versions JOIN files ON files.id = file_versions.file_id
+            WHERE files.id = $1
+                AND files.owner_id = $2
+                AND files.deleted_at IS NULL
+                AND file_versions.version = $3;",
+            &[&id, &session.user.id, &version],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        .ok_or_else(|| {
+            AppError::not_found_response(format!("No version {} of file {}", version, id))
+        })?;
+
+    // * The restored content becomes a new version, so the history stays intact
+    add_version(
+        &tx,
+        &id,
+        row.get("blob_hash"),
+        row.get::<_, Option<&str>>("hash").unwrap_or_default(),
+        row.get("size"),
+    )
+    .await
+    .map_err(|err| AppError::db_error(err))?;
     tx.commit().await.map_err(|err| AppError::db_error(err))?;
+    let _ = flush_object_deletions(&state, &mut conn).await;
+    spawn_image_processing(&state, vec![id]);
 
     Ok(AppResponse::default_response(id))
 }
 
+async fn dedup_savings(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+) -> RouteResponse<DedupSavings> {
+    let conn = state.get_db_conn().await?;
+
+    let row = conn
+        .query_one(
+            "SELECT
+                COALESCE(SUM(files.size), 0)::BIGINT AS logical_bytes,
+                (
+                    SELECT COALESCE(SUM(blobs.size), 0)::BIGINT
+                    FROM blobs
+                    WHERE blobs.hash IN (SELECT blob_hash FROM files WHERE owner_id = $1)
+                ) AS stored_bytes
+            FROM files
+            WHERE owner_id = $1 AND blob_hash IS NOT NULL;",
+            &[&session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    let logical_bytes: i64 = row.get("logical_bytes");
+    let stored_bytes: i64 = row.get("stored_bytes");
+
+    Ok(AppResponse::default_response(DedupSavings {
+        logical_bytes,
+        stored_bytes,
+        saved_bytes: logical_bytes - stored_bytes,
+    }))
+}
+
 pub fn file_routes() -> Router<AppState> {
     Router::new()
         .nest(
@@ -418,8 +1621,23 @@ pub fn file_routes() -> Router<AppState> {
                 .route("/upload", post(upload_file_route))
                 .route("/read/{id}/link", get(generate_link))
                 .route("/download/{id}", get(download_file))
+                .route("/archive", get(download_archive))
                 .route("/list", get(list_files))
-                .route("/delete/{id}", delete(delete_file)),
+                .route("/dedup", get(dedup_savings))
+                .route("/delete/{id}", delete(delete_file))
+                .route("/trash", get(list_trash))
+                .route("/trash/{id}", delete(delete_trashed_file))
+                .route("/trash/{id}/restore", post(restore_file))
+                .route("/{id}", patch(move_file))
+                .route("/{id}/copy", post(copy_file))
+                .route("/{id}/entries", get(list_archive_entries))
+                .route("/{id}/entries/{*name}", get(download_archive_entry))
+                .route("/{id}/thumbnail", get(get_thumbnail))
+                .route("/{id}/image", get(get_image))
+                .route("/{id}/metadata", get(get_photo_metadata))
+                .route("/{id}/versions", get(list_versions))
+                .route("/{id}/versions/{version}", get(download_version))
+                .route("/{id}/versions/{version}/restore", post(restore_version)),
         )
         .layer(DefaultBodyLimit::max(*MAX_FILE_SIZE))
 }
@@ -1,3 +1,6 @@
 pub mod auth_routes;
 pub mod bucket_routes;
+pub mod direct_upload_routes;
 pub mod file_routes;
+pub mod tus_routes;
+pub mod user_routes;
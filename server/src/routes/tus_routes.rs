@@ -0,0 +1,297 @@
+use axum::{
+    Extension, Router,
+    body::Body,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+    middleware::{Next, from_fn},
+    response::IntoResponse,
+    routing::{head, post},
+};
+use reqwest::header::{CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use uuid::Uuid;
+
+use crate::{
+    consts::{
+        MAX_FILE_SIZE, TUS_EXTENSION_HEADER, TUS_EXTENSIONS, TUS_MAX_SIZE, TUS_RESUMABLE,
+        TUS_VERSION, TUS_VERSION_HEADER, UPLOAD_LENGTH, UPLOAD_METADATA, UPLOAD_OFFSET,
+    },
+    enums::errors::AppError,
+    models::{auth::AuthSession, response::AppErrorResponse, state::AppState, upload::TusUpload},
+    routes::file_routes::FileQuery,
+    utils::{
+        blob_utils::new_blob_key,
+        crypto_utils::owner_keyring,
+        tree_utils::split_relative_path,
+        tus_utils::{
+            append_upload, complete_upload, discard_pending, get_upload, lock_upload,
+            parse_metadata, save_upload, unlock_upload,
+        },
+    },
+};
+
+const TUS_CONTENT_TYPE: &str = "application/offset+octet-stream";
+// * Pending bytes of unfinished uploads are kept under this folder of the storage root
+const TUS_PENDING_FOLDER: &str = ".tus";
+
+fn header_i64(headers: &HeaderMap, name: &str) -> Result<i64, AppErrorResponse> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+        .ok_or_else(|| AppError::bad_request_response(format!("Missing or invalid {}", name)))
+}
+
+async fn get_owned_upload(
+    state: &AppState,
+    session: &AuthSession,
+    id: &Uuid,
+) -> Result<TusUpload, AppErrorResponse> {
+    get_upload(state, id)
+        .await?
+        .filter(|upload| upload.owner_id == session.user.id)
+        .ok_or_else(|| AppError::not_found_response(format!("No tus upload {}", id)))
+}
+
+// * Every request except OPTIONS must name the protocol version, and every response names it
+async fn tus_resumable(request: Request, next: Next) -> Response<Body> {
+    let is_supported = request
+        .headers()
+        .get(TUS_RESUMABLE)
+        .is_some_and(|version| version == TUS_VERSION);
+    if request.method() != "OPTIONS" && !is_supported {
+        return (
+            StatusCode::PRECONDITION_FAILED,
+            [(TUS_VERSION_HEADER, TUS_VERSION)],
+        )
+            .into_response();
+    }
+
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+async fn tus_options() -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_VERSION_HEADER, TUS_VERSION.to_string()),
+            (TUS_EXTENSION_HEADER, TUS_EXTENSIONS.to_string()),
+            (TUS_MAX_SIZE, MAX_FILE_SIZE.to_string()),
+        ],
+    )
+}
+
+async fn create_upload(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let length = header_i64(&headers, UPLOAD_LENGTH)?;
+    if length > *MAX_FILE_SIZE as i64 {
+        return Err(AppError::payload_too_large_response(format!(
+            "Upload-Length {} exceeds the maximum file size",
+            length
+        )));
+    }
+
+    let raw_metadata = headers
+        .get(UPLOAD_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let metadata = parse_metadata(raw_metadata.as_deref().unwrap_or_default())
+        .ok_or_else(|| AppError::bad_request_response("Invalid Upload-Metadata"))?;
+    let file_name = metadata
+        .get("filename")
+        .filter(|file_name| !file_name.is_empty())
+        .ok_or_else(|| AppError::bad_request_response("Upload-Metadata has no filename"))?;
+    let folder = metadata.get("path").map(String::as_str).unwrap_or_default();
+    // * Checked like the relative names of multipart uploads; the folders are created once
+    // * the upload completes
+    let (folder, title) =
+        split_relative_path(&format!("{}/{}", folder, file_name)).ok_or_else(|| {
+            AppError::bad_request_response(format!(
+                "Invalid filename {:?} in {:?}",
+                file_name, folder
+            ))
+        })?;
+
+    let query = FileQuery {
+        path: folder,
+        is_public: metadata
+            .get("is_public")
+            .is_some_and(|value| value == "true"),
+        is_folder: false,
+    };
+    let pending_folder = FileQuery {
+        path: String::from(TUS_PENDING_FOLDER),
+        is_public: false,
+        is_folder: true,
+    };
+    let id = Uuid::new_v4();
+    let path = query.format_path(&state.root);
+    let encryption = owner_keyring(&state, &session.user.id)
+        .await?
+        .map(|keyring| keyring.new_encryption())
+        .transpose()
+        .map_err(|err| AppError::storage_error(err))?;
+
+    let mut upload = TusUpload {
+        id,
+        owner_id: session.user.id,
+        key: new_blob_key(&state.root, &id),
+        title,
+        path,
+        content_type: metadata
+            .get("filetype")
+            .filter(|content_type| !content_type.is_empty())
+            .cloned()
+            .unwrap_or(String::from("application/octet-stream")),
+        is_public: query.is_public,
+        metadata: raw_metadata,
+        length,
+        offset: 0,
+        upload_id: None,
+        parts: vec![],
+        pending_prefix: format!("{}{}/", pending_folder.format_path(&state.root), id),
+        completed: false,
+        encryption,
+    };
+
+    match length {
+        0 => complete_upload(&state, &mut upload, Default::default()).await?,
+        _ => save_upload(&state, &upload).await?,
+    }
+
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header(
+            LOCATION,
+            format!("{}/api/v1/files/tus/{}", state.server_url, id),
+        )
+        .body(Body::empty())
+        .map_err(|err| AppError::critical_error(err))
+}
+
+async fn upload_status(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let upload = get_owned_upload(&state, &session, &id).await?;
+
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(UPLOAD_OFFSET, upload.offset)
+        .header(UPLOAD_LENGTH, upload.length)
+        .header(CACHE_CONTROL, "no-store");
+    if let Some(metadata) = upload.metadata {
+        response = response.header(UPLOAD_METADATA, metadata);
+    }
+    response
+        .body(Body::empty())
+        .map_err(|err| AppError::critical_error(err))
+}
+
+async fn patch_upload(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, AppErrorResponse> {
+    let is_offset_stream = headers
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == TUS_CONTENT_TYPE);
+    if !is_offset_stream {
+        return Err(AppError::bad_request_response(format!(
+            "PATCH Content-Type must be {}",
+            TUS_CONTENT_TYPE
+        )));
+    }
+    let offset = header_i64(&headers, UPLOAD_OFFSET)?;
+
+    // * The state is read under the lock so a concurrent PATCH cannot move the offset under us
+    if !lock_upload(&state, &id).await? {
+        return Err(AppError::conflict_response(format!(
+            "Upload {} is locked by another request",
+            id
+        )));
+    }
+    let appended = async {
+        let mut upload = get_owned_upload(&state, &session, &id).await?;
+        match upload.offset == offset {
+            true if upload.completed => Ok(upload),
+            true => append_upload(&state, &mut upload, body)
+                .await
+                .map(|_| upload),
+            false => Err(AppError::conflict_response(format!(
+                "Upload-Offset {} does not match {} for upload {}",
+                offset, upload.offset, id
+            ))),
+        }
+    }
+    .await;
+    unlock_upload(&state, &id).await;
+    let upload = appended?;
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(UPLOAD_OFFSET, upload.offset)
+        .body(Body::empty())
+        .map_err(|err| AppError::critical_error(err))
+}
+
+async fn terminate_upload(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(id): Path<Uuid>,
+) -> Result<Response<Body>, AppErrorResponse> {
+    if !lock_upload(&state, &id).await? {
+        return Err(AppError::conflict_response(format!(
+            "Upload {} is locked by another request",
+            id
+        )));
+    }
+    let terminated = async {
+        let upload = get_owned_upload(&state, &session, &id).await?;
+        if let Some(upload_id) = &upload.upload_id {
+            let aborted = state
+                .storage
+                .abort_multipart_upload(&upload.key, upload_id)
+                .await;
+            if let Err(err) = aborted {
+                AppError::storage_error(err);
+            }
+        }
+        discard_pending(&state, &upload).await;
+        state
+            .cache
+            .del(&TusUpload::cache_key(&id))
+            .await
+            .map_err(|err| AppError::dfly_error(err))
+    }
+    .await;
+    unlock_upload(&state, &id).await;
+    terminated?;
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .map_err(|err| AppError::critical_error(err))
+}
+
+pub fn tus_routes() -> Router<AppState> {
+    Router::new()
+        .route("/files/tus", post(create_upload).options(tus_options))
+        .route(
+            "/files/tus/{id}",
+            head(upload_status)
+                .patch(patch_upload)
+                .delete(terminate_upload),
+        )
+        .layer(from_fn(tus_resumable))
+}
@@ -0,0 +1,194 @@
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, State},
+    routing::{get, put},
+};
+
+use crate::{
+    consts::{MAX_FILE_VERSIONS, MAX_IMAGE_PRESETS},
+    enums::errors::{AppError, StorageError},
+    models::{
+        auth::{AuthSession, UpdateUserSettings, UserSettings},
+        file::{ImagePreset, ImageTransform},
+        response::{AppResponse, RouteResponse},
+        state::AppState,
+    },
+    utils::image_utils::{image_presets, prune_image_variants},
+};
+
+async fn get_settings(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+) -> RouteResponse<UserSettings> {
+    let conn = state.get_db_conn().await?;
+    let row = conn
+        .query_one(
+            "SELECT encrypt_files, max_file_versions, strip_metadata FROM users WHERE id = $1;",
+            &[&session.user.id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(UserSettings {
+        encrypt_files: row.get("encrypt_files"),
+        max_file_versions: row.get("max_file_versions"),
+        strip_metadata: row.get("strip_metadata"),
+    }))
+}
+
+async fn update_settings(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Json(payload): Json<UpdateUserSettings>,
+) -> RouteResponse<UserSettings> {
+    if payload.encrypt_files == Some(true) && state.keyring.is_none() {
+        return Err(AppError::storage_error(StorageError::Unsupported(
+            "encryption without a master key",
+        )));
+    }
+    if let Some(max_file_versions) = payload.max_file_versions
+        && !(1..=MAX_FILE_VERSIONS).contains(&max_file_versions)
+    {
+        return Err(AppError::bad_request_response(format!(
+            "maxFileVersions must be between 1 and {}",
+            MAX_FILE_VERSIONS
+        )));
+    }
+
+    let conn = state.get_db_conn().await?;
+    let row = conn
+        .query_one(
+            "UPDATE users
+            SET encrypt_files = COALESCE($1, encrypt_files),
+                max_file_versions = COALESCE($2, max_file_versions),
+                strip_metadata = COALESCE($3, strip_metadata)
+            WHERE id = $4
+            RETURNING encrypt_files, max_file_versions, strip_metadata;",
+            &[
+                &payload.encrypt_files,
+                &payload.max_file_versions,
+                &payload.strip_metadata,
+                &session.user.id,
+            ],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(UserSettings {
+        encrypt_files: row.get("encrypt_files"),
+        max_file_versions: row.get("max_file_versions"),
+        strip_metadata: row.get("strip_metadata"),
+    }))
+}
+
+async fn list_image_presets(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+) -> RouteResponse<Vec<ImagePreset>> {
+    let conn = state.get_db_conn().await?;
+    let presets = image_presets(&conn, &session.user.id)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(presets))
+}
+
+async fn save_image_preset(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(name): Path<String>,
+    Json(transform): Json<ImageTransform>,
+) -> RouteResponse<ImagePreset> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+    {
+        return Err(AppError::bad_request_response(
+            "Preset names are up to 64 letters, digits, dashes and underscores",
+        ));
+    }
+    transform
+        .validate()
+        .map_err(|err| AppError::bad_request_response(err))?;
+
+    let mut conn = state.get_db_conn().await?;
+    // * New presets stop at the limit, existing ones can always change
+    let saved = conn
+        .query_opt(
+            "INSERT INTO image_presets (owner_id, name, width, height, fit, format, quality)
+            SELECT $1, $2, $3, $4, $5, $6, $7
+            WHERE EXISTS (SELECT 1 FROM image_presets WHERE owner_id = $1 AND name = $2)
+                OR (SELECT COUNT(*) FROM image_presets WHERE owner_id = $1) < $8
+            ON CONFLICT (owner_id, name) DO UPDATE SET
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                fit = EXCLUDED.fit,
+                format = EXCLUDED.format,
+                quality = EXCLUDED.quality
+            RETURNING name;",
+            &[
+                &session.user.id,
+                &name,
+                &transform.width.map(|width| width as i32),
+                &transform.height.map(|height| height as i32),
+                &transform.fit.to_string(),
+                &transform.format.to_string(),
+                &(transform.quality as i32),
+                &MAX_IMAGE_PRESETS,
+            ],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    if saved.is_none() {
+        return Err(AppError::bad_request_response(format!(
+            "At most {} image presets per user",
+            MAX_IMAGE_PRESETS
+        )));
+    }
+    prune_image_variants(&state, &mut conn, &session.user.id)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(ImagePreset {
+        name,
+        transform,
+    }))
+}
+
+async fn delete_image_preset(
+    State(state): State<AppState>,
+    Extension(session): Extension<AuthSession>,
+    Path(name): Path<String>,
+) -> RouteResponse<String> {
+    let mut conn = state.get_db_conn().await?;
+    let deleted = conn
+        .execute(
+            "DELETE FROM image_presets WHERE owner_id = $1 AND name = $2;",
+            &[&session.user.id, &name],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    if deleted == 0 {
+        return Err(AppError::not_found_response(format!(
+            "No image preset {}",
+            name
+        )));
+    }
+    prune_image_variants(&state, &mut conn, &session.user.id)
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    Ok(AppResponse::default_response(name))
+}
+
+pub fn user_routes() -> Router<AppState> {
+    Router::new()
+        .route("/users/settings", get(get_settings).patch(update_settings))
+        .route("/users/image-presets", get(list_image_presets))
+        .route(
+            "/users/image-presets/{name}",
+            put(save_image_preset).delete(delete_image_preset),
+        )
+}
@@ -0,0 +1,352 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use jiff::Timestamp;
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use crate::{
+    enums::errors::StorageError,
+    models::storage::{ObjectMeta, PresignedRequest, PutOptions, StorageObject, UploadedPart},
+    traits::storage_traits::StorageBackend,
+};
+
+// * Writes land here first and are renamed into place once complete
+const STAGING_DIR: &str = ".staging";
+const MULTIPART_DIR: &str = "multipart";
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn object_path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let segments = key
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+
+        let is_invalid = segments.is_empty()
+            || segments[0] == STAGING_DIR
+            || segments
+                .iter()
+                .any(|segment| *segment == "." || *segment == ".." || segment.contains('\\'));
+        if is_invalid {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+
+        Ok(segments
+            .into_iter()
+            .fold(self.root.clone(), |path, segment| path.join(segment)))
+    }
+
+    fn staging_path(&self) -> PathBuf {
+        self.root.join(STAGING_DIR).join(Uuid::new_v4().to_string())
+    }
+
+    fn multipart_dir(&self, upload_id: &str) -> Result<PathBuf, StorageError> {
+        let upload_id = Uuid::try_parse(upload_id)
+            .map_err(|_| StorageError::InvalidKey(upload_id.to_string()))?;
+
+        Ok(self
+            .root
+            .join(STAGING_DIR)
+            .join(MULTIPART_DIR)
+            .join(upload_id.to_string()))
+    }
+
+    async fn meta(&self, key: &str, path: &Path) -> Result<ObjectMeta, StorageError> {
+        let metadata = fs::metadata(path).await.map_err(|err| match err.kind() {
+            ErrorKind::NotFound => StorageError::NotFound(key.to_string()),
+            _ => StorageError::Io(err),
+        })?;
+        if !metadata.is_file() {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+
+        let last_modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| Timestamp::try_from(modified).ok());
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: metadata.len() as i64,
+            content_type: None,
+            etag: Some(format!(
+                "\"{:x}-{:x}\"",
+                metadata.len(),
+                last_modified.map(|ts| ts.as_nanosecond()).unwrap_or(0)
+            )),
+            last_modified,
+        })
+    }
+
+    async fn ensure_parent(path: &Path) -> Result<(), StorageError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        Ok(())
+    }
+
+    // * Removes directories emptied by a delete, stopping at the storage root
+    async fn prune_empty_dirs(&self, path: &Path) {
+        let mut current = path.parent();
+        while let Some(dir) = current {
+            if dir == self.root || fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+            current = dir.parent();
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put_object(
+        &self,
+        key: &str,
+        body: Bytes,
+        _options: &PutOptions,
+    ) -> Result<ObjectMeta, StorageError> {
+        let path = self.object_path(key)?;
+        let staging = self.staging_path();
+        Self::ensure_parent(&staging).await?;
+        Self::ensure_parent(&path).await?;
+
+        let mut file = fs::File::create(&staging).await?;
+        file.write_all(&body).await?;
+        file.sync_all().await?;
+        fs::rename(&staging, &path).await?;
+
+        self.meta(key, &path).await
+    }
+
+    async fn get_object(&self, key: &str) -> Result<StorageObject, StorageError> {
+        let path = self.object_path(key)?;
+        let meta = self.meta(key, &path).await?;
+        let file = fs::File::open(&path).await?;
+
+        Ok(StorageObject {
+            meta,
+            body: Box::pin(file),
+        })
+    }
+
+    async fn get_object_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<StorageObject, StorageError> {
+        let path = self.object_path(key)?;
+        let meta = self.meta(key, &path).await?;
+        let mut file = fs::File::open(&path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        Ok(StorageObject {
+            meta,
+            body: Box::pin(file.take((end + 1).saturating_sub(start))),
+        })
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectMeta, StorageError> {
+        let path = self.object_path(key)?;
+        self.meta(key, &path).await
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.object_path(key)?;
+        match fs::remove_file(&path).await {
+            Ok(()) => {
+                self.prune_empty_dirs(&path).await;
+                Ok(())
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(StorageError::Io(err)),
+        }
+    }
+
+    async fn delete_objects(&self, keys: &[String]) -> Result<(), StorageError> {
+        for key in keys {
+            self.delete_object(key).await?;
+        }
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMeta>, StorageError> {
+        let prefix = prefix.unwrap_or_default();
+        // * Only walk the deepest directory the prefix is guaranteed to live in
+        let start = match prefix.rfind('/') {
+            Some(idx) => prefix[..idx].to_string(),
+            None => String::from(""),
+        };
+        let start_path = match start.is_empty() {
+            true => self.root.clone(),
+            false => self.object_path(&start)?,
+        };
+
+        let mut objects = Vec::new();
+        let mut pending = vec![(start_path, start)];
+
+        while let Some((dir, dir_key)) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(StorageError::Io(err)),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if dir_key.is_empty() && name == STAGING_DIR {
+                    continue;
+                }
+                let key = match dir_key.is_empty() {
+                    true => name,
+                    false => format!("{}/{}", dir_key, name),
+                };
+
+                if entry.file_type().await?.is_dir() {
+                    pending.push((entry.path(), key));
+                } else if key.starts_with(prefix) {
+                    objects.push(self.meta(&key, &entry.path()).await?);
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn copy_object(
+        &self,
+        from: &str,
+        to: &str,
+        _is_public: bool,
+    ) -> Result<ObjectMeta, StorageError> {
+        let source = self.object_path(from)?;
+        let destination = self.object_path(to)?;
+        // * Surface a missing source as NotFound rather than an IO error
+        self.meta(from, &source).await?;
+
+        let staging = self.staging_path();
+        Self::ensure_parent(&staging).await?;
+        Self::ensure_parent(&destination).await?;
+        fs::copy(&source, &staging).await?;
+        fs::rename(&staging, &destination).await?;
+
+        self.meta(to, &destination).await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        _options: &PutOptions,
+    ) -> Result<String, StorageError> {
+        self.object_path(key)?;
+        let upload_id = Uuid::new_v4().to_string();
+        fs::create_dir_all(self.multipart_dir(&upload_id)?).await?;
+
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: Bytes,
+    ) -> Result<UploadedPart, StorageError> {
+        let dir = self.multipart_dir(upload_id)?;
+        if !fs::try_exists(&dir).await? {
+            return Err(StorageError::NotFound(upload_id.to_string()));
+        }
+
+        fs::write(dir.join(part_number.to_string()), &body).await?;
+
+        Ok(UploadedPart {
+            part_number,
+            etag: format!("\"{}\"", blake3::hash(&body).to_hex()),
+        })
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<ObjectMeta, StorageError> {
+        let path = self.object_path(key)?;
+        let dir = self.multipart_dir(upload_id)?;
+        let staging = self.staging_path();
+        Self::ensure_parent(&path).await?;
+
+        let mut file = fs::File::create(&staging).await?;
+        for part in parts {
+            let mut part_file = fs::File::open(dir.join(part.part_number.to_string()))
+                .await
+                .map_err(|err| match err.kind() {
+                    ErrorKind::NotFound => {
+                        StorageError::NotFound(format!("{} part {}", upload_id, part.part_number))
+                    }
+                    _ => StorageError::Io(err),
+                })?;
+            io::copy(&mut part_file, &mut file).await?;
+        }
+        file.sync_all().await?;
+        fs::rename(&staging, &path).await?;
+        fs::remove_dir_all(&dir).await?;
+
+        self.meta(key, &path).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        _key: &str,
+        upload_id: &str,
+    ) -> Result<(), StorageError> {
+        match fs::remove_dir_all(self.multipart_dir(upload_id)?).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(StorageError::Io(err)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn presign_put(
+        &self,
+        _key: &str,
+        _options: &PutOptions,
+        _expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        Err(StorageError::Unsupported("presign_put"))
+    }
+
+    async fn presign_upload_part(
+        &self,
+        _key: &str,
+        _upload_id: &str,
+        _part_number: i32,
+        _expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        Err(StorageError::Unsupported("presign_upload_part"))
+    }
+
+    async fn generate_link(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Err(StorageError::Unsupported("generate_link"))
+    }
+}
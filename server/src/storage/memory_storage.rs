@@ -0,0 +1,275 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+    sync::{Mutex, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use jiff::Timestamp;
+use uuid::Uuid;
+
+use crate::{
+    enums::errors::StorageError,
+    models::storage::{ObjectMeta, PresignedRequest, PutOptions, StorageObject, UploadedPart},
+    traits::storage_traits::StorageBackend,
+};
+
+struct MemoryObject {
+    body: Bytes,
+    meta: ObjectMeta,
+}
+
+struct MemoryUpload {
+    key: String,
+    options: PutOptions,
+    parts: HashMap<i32, Bytes>,
+}
+
+/// In-process object storage, used by the integration tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: RwLock<BTreeMap<String, MemoryObject>>,
+    uploads: Mutex<HashMap<String, MemoryUpload>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn put_object(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<ObjectMeta, StorageError> {
+        let meta = ObjectMeta {
+            key: key.to_string(),
+            size: body.len() as i64,
+            content_type: options.content_type.clone(),
+            etag: Some(format!("\"{}\"", blake3::hash(&body).to_hex())),
+            last_modified: Some(Timestamp::now()),
+        };
+        self.objects.write().unwrap().insert(
+            key.to_string(),
+            MemoryObject {
+                body,
+                meta: meta.clone(),
+            },
+        );
+
+        Ok(meta)
+    }
+
+    async fn get_object(&self, key: &str) -> Result<StorageObject, StorageError> {
+        let objects = self.objects.read().unwrap();
+        let object = objects
+            .get(key)
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))?;
+
+        Ok(StorageObject {
+            meta: object.meta.clone(),
+            body: Box::pin(Cursor::new(object.body.clone())),
+        })
+    }
+
+    async fn get_object_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<StorageObject, StorageError> {
+        let objects = self.objects.read().unwrap();
+        let object = objects
+            .get(key)
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))?;
+        let end = (end as usize + 1).min(object.body.len());
+        let start = (start as usize).min(end);
+
+        Ok(StorageObject {
+            meta: object.meta.clone(),
+            body: Box::pin(Cursor::new(object.body.slice(start..end))),
+        })
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectMeta, StorageError> {
+        self.objects
+            .read()
+            .unwrap()
+            .get(key)
+            .map(|object| object.meta.clone())
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), StorageError> {
+        self.objects.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn delete_objects(&self, keys: &[String]) -> Result<(), StorageError> {
+        let mut objects = self.objects.write().unwrap();
+        for key in keys {
+            objects.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMeta>, StorageError> {
+        let prefix = prefix.unwrap_or_default();
+        Ok(self
+            .objects
+            .read()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(_, object)| object.meta.clone())
+            .collect())
+    }
+
+    async fn copy_object(
+        &self,
+        from: &str,
+        to: &str,
+        _is_public: bool,
+    ) -> Result<ObjectMeta, StorageError> {
+        let mut objects = self.objects.write().unwrap();
+        let source = objects
+            .get(from)
+            .ok_or_else(|| StorageError::NotFound(from.to_string()))?;
+
+        let meta = ObjectMeta {
+            key: to.to_string(),
+            last_modified: Some(Timestamp::now()),
+            ..source.meta.clone()
+        };
+        let body = source.body.clone();
+        objects.insert(
+            to.to_string(),
+            MemoryObject {
+                body,
+                meta: meta.clone(),
+            },
+        );
+
+        Ok(meta)
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        options: &PutOptions,
+    ) -> Result<String, StorageError> {
+        let upload_id = Uuid::new_v4().to_string();
+        self.uploads.lock().unwrap().insert(
+            upload_id.clone(),
+            MemoryUpload {
+                key: key.to_string(),
+                options: options.clone(),
+                parts: HashMap::new(),
+            },
+        );
+
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: Bytes,
+    ) -> Result<UploadedPart, StorageError> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let upload = uploads
+            .get_mut(upload_id)
+            .ok_or_else(|| StorageError::NotFound(upload_id.to_string()))?;
+        let etag = format!("\"{}\"", blake3::hash(&body).to_hex());
+        upload.parts.insert(part_number, body);
+
+        Ok(UploadedPart { part_number, etag })
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<ObjectMeta, StorageError> {
+        let upload = self
+            .uploads
+            .lock()
+            .unwrap()
+            .remove(upload_id)
+            .filter(|upload| upload.key == key)
+            .ok_or_else(|| StorageError::NotFound(upload_id.to_string()))?;
+
+        let mut body = BytesMut::new();
+        for part in parts {
+            let data = upload.parts.get(&part.part_number).ok_or_else(|| {
+                StorageError::NotFound(format!("{} part {}", upload_id, part.part_number))
+            })?;
+            body.extend_from_slice(data);
+        }
+
+        self.put_object(key, body.freeze(), &upload.options).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        _key: &str,
+        upload_id: &str,
+    ) -> Result<(), StorageError> {
+        self.uploads.lock().unwrap().remove(upload_id);
+        Ok(())
+    }
+
+    // * Nothing serves these URLs; tests write through the backend and only need the target
+    async fn presign_put(
+        &self,
+        key: &str,
+        options: &PutOptions,
+        _expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        Ok(PresignedRequest {
+            url: format!("memory:///{}", key),
+            headers: options
+                .content_type
+                .iter()
+                .map(|content_type| (String::from("content-type"), content_type.clone()))
+                .collect(),
+        })
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        _expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        if !self.uploads.lock().unwrap().contains_key(upload_id) {
+            return Err(StorageError::NotFound(upload_id.to_string()));
+        }
+
+        Ok(PresignedRequest {
+            url: format!(
+                "memory:///{}?partNumber={}&uploadId={}",
+                key, part_number, upload_id
+            ),
+            headers: Default::default(),
+        })
+    }
+
+    async fn generate_link(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Err(StorageError::Unsupported("generate_link"))
+    }
+}
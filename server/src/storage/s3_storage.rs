@@ -0,0 +1,478 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    Client,
+    error::{DisplayErrorContext, SdkError},
+    presigning::{PresignedRequest as S3PresignedRequest, PresigningConfig},
+    primitives::{ByteStream, DateTime},
+    types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectCannedAcl, ObjectIdentifier},
+};
+use bytes::Bytes;
+use jiff::Timestamp;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+use crate::{
+    consts::DELETE_BATCH_SIZE,
+    enums::{errors::StorageError, storage_enums::S3Providers},
+    models::storage::{ObjectMeta, PresignedRequest, PutOptions, StorageObject, UploadedPart},
+    traits::storage_traits::StorageBackend,
+};
+
+// * Everything except unreserved characters and the path separator
+const COPY_SOURCE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+    region: String,
+    provider: S3Providers,
+}
+
+impl S3Storage {
+    pub fn new(client: Client, bucket: String, region: String, provider: S3Providers) -> Self {
+        Self {
+            client,
+            bucket,
+            region,
+            provider,
+        }
+    }
+}
+
+fn backend_error<E, R>(err: SdkError<E, R>) -> StorageError
+where
+    E: std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    StorageError::Backend(DisplayErrorContext(&err).to_string())
+}
+
+fn canned_acl(is_public: bool) -> ObjectCannedAcl {
+    match is_public {
+        true => ObjectCannedAcl::PublicRead,
+        false => ObjectCannedAcl::Private,
+    }
+}
+
+fn presigning_config(expires_in: Duration) -> Result<PresigningConfig, StorageError> {
+    PresigningConfig::expires_in(expires_in).map_err(|err| StorageError::Backend(err.to_string()))
+}
+
+fn to_presigned(request: S3PresignedRequest) -> PresignedRequest {
+    PresignedRequest {
+        url: request.uri().to_string(),
+        headers: request
+            .headers()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    }
+}
+
+fn to_timestamp(value: Option<&DateTime>) -> Option<Timestamp> {
+    value.and_then(|dt| Timestamp::new(dt.secs(), dt.subsec_nanos() as i32).ok())
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn put_object(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<ObjectMeta, StorageError> {
+        let size = body.len() as i64;
+        let output = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(body))
+            .acl(canned_acl(options.is_public))
+            .set_content_type(options.content_type.clone())
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size,
+            content_type: options.content_type.clone(),
+            etag: output.e_tag().map(|value| value.to_string()),
+            last_modified: Some(Timestamp::now()),
+        })
+    }
+
+    async fn get_object(&self, key: &str) -> Result<StorageObject, StorageError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service_err) if service_err.is_no_such_key() => {
+                    StorageError::NotFound(key.to_string())
+                }
+                _ => backend_error(err),
+            })?;
+
+        let meta = ObjectMeta {
+            key: key.to_string(),
+            size: output.content_length().unwrap_or(0),
+            content_type: output.content_type().map(|value| value.to_string()),
+            etag: output.e_tag().map(|value| value.to_string()),
+            last_modified: to_timestamp(output.last_modified()),
+        };
+
+        Ok(StorageObject {
+            meta,
+            body: Box::pin(output.body.into_async_read()),
+        })
+    }
+
+    async fn get_object_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<StorageObject, StorageError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", start, end))
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service_err) if service_err.is_no_such_key() => {
+                    StorageError::NotFound(key.to_string())
+                }
+                _ => backend_error(err),
+            })?;
+
+        // * Content-Range is "bytes start-end/size"
+        let size = output
+            .content_range()
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|size| size.parse::<i64>().ok())
+            .unwrap_or(0);
+        let meta = ObjectMeta {
+            key: key.to_string(),
+            size,
+            content_type: output.content_type().map(|value| value.to_string()),
+            etag: output.e_tag().map(|value| value.to_string()),
+            last_modified: to_timestamp(output.last_modified()),
+        };
+
+        Ok(StorageObject {
+            meta,
+            body: Box::pin(output.body.into_async_read()),
+        })
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectMeta, StorageError> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service_err) if service_err.is_not_found() => {
+                    StorageError::NotFound(key.to_string())
+                }
+                _ => backend_error(err),
+            })?;
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: output.content_length().unwrap_or(0),
+            content_type: output.content_type().map(|value| value.to_string()),
+            etag: output.e_tag().map(|value| value.to_string()),
+            last_modified: to_timestamp(output.last_modified()),
+        })
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), StorageError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_objects(&self, keys: &[String]) -> Result<(), StorageError> {
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            let objects = batch
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| StorageError::Backend(err.to_string()))?;
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .map_err(|err| StorageError::Backend(err.to_string()))?;
+
+            // * Quiet mode only lists the keys that failed
+            let response = self
+                .client
+                .delete_objects()
+                .bucket(&self.bucket)
+                .delete(delete)
+                .send()
+                .await
+                .map_err(backend_error)?;
+            if let Some(error) = response.errors().first() {
+                return Err(StorageError::Backend(format!(
+                    "{} of {} deletes failed, {} - {}",
+                    response.errors().len(),
+                    batch.len(),
+                    error.key().unwrap_or_default(),
+                    error.message().unwrap_or_default()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn list_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMeta>, StorageError> {
+        let mut objects = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let response = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .set_prefix(prefix.map(|value| value.to_string()))
+                .set_continuation_token(continuation_token.take())
+                .send()
+                .await
+                .map_err(backend_error)?;
+
+            for object in response.contents() {
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                objects.push(ObjectMeta {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or(0),
+                    content_type: None,
+                    etag: object.e_tag().map(|value| value.to_string()),
+                    last_modified: to_timestamp(object.last_modified()),
+                });
+            }
+
+            continuation_token = response
+                .next_continuation_token()
+                .map(|value| value.to_string());
+            if !response.is_truncated().unwrap_or(false) || continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn copy_object(
+        &self,
+        from: &str,
+        to: &str,
+        is_public: bool,
+    ) -> Result<ObjectMeta, StorageError> {
+        let copy_source = format!(
+            "{}/{}",
+            self.bucket,
+            utf8_percent_encode(from, COPY_SOURCE_SET)
+        );
+
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(copy_source)
+            .key(to)
+            .acl(canned_acl(is_public))
+            .send()
+            .await
+            .map_err(
+                |err| match err.raw_response().map(|raw| raw.status().as_u16()) {
+                    Some(404) => StorageError::NotFound(from.to_string()),
+                    _ => backend_error(err),
+                },
+            )?;
+
+        self.head_object(to).await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        options: &PutOptions,
+    ) -> Result<String, StorageError> {
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .acl(canned_acl(options.is_public))
+            .set_content_type(options.content_type.clone())
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        output
+            .upload_id()
+            .map(|value| value.to_string())
+            .ok_or_else(|| StorageError::Backend(format!("No upload id returned for {}", key)))
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: Bytes,
+    ) -> Result<UploadedPart, StorageError> {
+        let output = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        Ok(UploadedPart {
+            part_number,
+            etag: output.e_tag().unwrap_or_default().to_string(),
+        })
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<ObjectMeta, StorageError> {
+        let completed_parts = parts
+            .iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(&part.etag)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(completed_parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        self.head_object(key).await
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        Ok(())
+    }
+
+    async fn presign_put(
+        &self,
+        key: &str,
+        options: &PutOptions,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .acl(canned_acl(options.is_public))
+            .set_content_type(options.content_type.clone())
+            .presigned(presigning_config(expires_in)?)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(to_presigned(request))
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError> {
+        let request = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(presigning_config(expires_in)?)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(to_presigned(request))
+    }
+
+    async fn generate_link(&self, key: &str, expires_in: Duration) -> Result<String, StorageError> {
+        match self.provider {
+            S3Providers::DigitalOcean => Ok(format!(
+                "https://{bucket}.{region}.cdn.digitaloceanspaces.com/{key}",
+                bucket = self.bucket,
+                region = self.region,
+                key = key
+            )),
+            S3Providers::Aws => {
+                let request = self
+                    .client
+                    .get_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .presigned(presigning_config(expires_in)?)
+                    .await
+                    .map_err(backend_error)?;
+
+                Ok(request.uri().to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use async_compression::tokio::bufread::DeflateEncoder;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header::CONTENT_LENGTH},
+};
+use serde_json::{Value, json};
+use tokio::io::AsyncReadExt;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+
+/// A ZIP with every file deflated. Names ending in `/` are folders. The entry named
+/// `understated` has half its real size in the central directory.
+async fn build_zip(entries: &[(&str, &[u8])], understated: Option<&str>) -> Vec<u8> {
+    let mut archive = vec![];
+    let mut directory = vec![];
+    for (name, body) in entries {
+        let is_folder = name.ends_with('/');
+        let mut compressed = vec![];
+        if !is_folder {
+            DeflateEncoder::new(*body)
+                .read_to_end(&mut compressed)
+                .await
+                .unwrap();
+        }
+        let size = match understated == Some(*name) {
+            true => body.len() as u32 / 2,
+            false => body.len() as u32,
+        };
+        let method: u16 = if is_folder { 0 } else { 8 };
+        let mut fields = vec![];
+        fields.extend(0x0800u16.to_le_bytes());
+        fields.extend(method.to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        fields.extend(0x21u16.to_le_bytes());
+        fields.extend(crc32fast::hash(body).to_le_bytes());
+        fields.extend((compressed.len() as u32).to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend((name.len() as u16).to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+
+        directory.extend(0x02014b50u32.to_le_bytes());
+        directory.extend([20, 0, 20, 0]);
+        directory.extend(&fields);
+        directory.extend([0; 10]);
+        directory.extend((archive.len() as u32).to_le_bytes());
+        directory.extend(name.as_bytes());
+
+        archive.extend(0x04034b50u32.to_le_bytes());
+        archive.extend([20, 0]);
+        archive.extend(&fields);
+        archive.extend(name.as_bytes());
+        archive.extend(&compressed);
+    }
+
+    let offset = archive.len() as u32;
+    archive.extend(&directory);
+    archive.extend(0x06054b50u32.to_le_bytes());
+    archive.extend([0; 4]);
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(offset.to_le_bytes());
+    archive.extend([0; 2]);
+    archive
+}
+
+async fn upload_archive(app: &TestApp, file_name: &str, body: &[u8]) -> Vec<Value> {
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=docs&extract=true",
+            &[TestFile {
+                field: "file0",
+                file_name,
+                content_type: "application/zip",
+                body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await["data"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+/// Uploads one file to the top level without extracting it and returns its id.
+async fn upload(app: &TestApp, file_name: &str, content_type: &str, body: &[u8]) -> String {
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name,
+                content_type,
+                body,
+            }],
+        )
+        .await;
+    body_json(response).await["data"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn titles(app: &TestApp, path: &str) -> Vec<String> {
+    let listed = body_json(app.get(&format!("/api/v1/files/list?path={}", path)).await).await;
+    let mut titles = listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["title"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    titles.sort();
+    titles
+}
+
+/// Name and content of every entry, read through the central directory of a ZIP without
+/// ZIP64 records or comment. CRCs are checked on the way.
+fn read_zip(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes(archive[at..at + 2].try_into().unwrap()) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap()) as usize;
+
+    let end = archive.len() - 22;
+    assert_eq!(u32_at(end), 0x06054b50);
+    let mut at = u32_at(end + 16);
+    (0..u16_at(end + 10))
+        .map(|_| {
+            assert_eq!(u32_at(at), 0x02014b50);
+            let crc = u32_at(at + 16) as u32;
+            let size = u32_at(at + 24);
+            let name_size = u16_at(at + 28);
+            let local = u32_at(at + 42);
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_size].to_vec()).unwrap();
+            at += 46 + name_size + u16_at(at + 30) + u16_at(at + 32);
+
+            assert_eq!(u32_at(local), 0x04034b50);
+            let start = local + 30 + u16_at(local + 26) + u16_at(local + 28);
+            let content = archive[start..start + size].to_vec();
+            assert_eq!(crc32fast::hash(&content), crc);
+            (name, content)
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn folders_and_selections_download_as_zip() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .request(
+            Request::post("/api/v1/files/create/folder?path=")
+                .header("Content-Type", "application/json")
+                .body(Body::from(json!({ "title": "docs" }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    let docs = body_json(response).await["data"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    app.request(
+        Request::post("/api/v1/files/create/folder?path=docs")
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "title": "empty" }).to_string()))
+            .unwrap(),
+    )
+    .await;
+
+    // * Half the files are encrypted, so entries are read through the decrypting path too
+    let file = |file_name, body| TestFile {
+        field: "file0",
+        file_name,
+        content_type: "text/plain",
+        body,
+    };
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=docs",
+            &[file("photos/2024/a.txt", b"alpha"), file("c.txt", b"")],
+        )
+        .await;
+    let uploaded = body_json(response).await;
+    let docs_c = uploaded["data"][1]["id"].as_str().unwrap().to_string();
+    app.encrypt_files().await;
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=docs",
+            &[file("photos/b.txt", b"bravo")],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=other",
+            &[file("c.txt", b"charlie")],
+        )
+        .await;
+    let other_c = body_json(response).await["data"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    // * Neither timestamp column is required
+    app.state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .execute(
+            "UPDATE files SET created_at = NULL, updated_at = NULL WHERE id::text = $1",
+            &[&docs_c],
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .get(&format!("/api/v1/files/archive?ids={}", docs))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"docs.zip\""
+    );
+    let size: usize = response.headers()[CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let archive = body_bytes(response).await;
+    assert_eq!(archive.len(), size);
+    let entry = |name: &str, content: &[u8]| (name.to_string(), content.to_vec());
+    assert_eq!(
+        read_zip(&archive),
+        vec![
+            entry("docs/", b""),
+            entry("docs/c.txt", b""),
+            entry("docs/empty/", b""),
+            entry("docs/photos/", b""),
+            entry("docs/photos/2024/", b""),
+            entry("docs/photos/b.txt", b"bravo"),
+            entry("docs/photos/2024/a.txt", b"alpha"),
+        ]
+    );
+
+    // * Same named selections are numbered, and selections inside a selected folder dropped
+    let response = app
+        .get(&format!(
+            "/api/v1/files/archive?ids={},{},{}",
+            docs_c, other_c, docs
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"archive.zip\""
+    );
+    let names = read_zip(&body_bytes(response).await)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names[..2], ["c.txt", "docs/"]);
+    assert_eq!(names.len(), 8);
+
+    let response = app
+        .get(&format!("/api/v1/files/archive?ids={},{}", other_c, docs_c))
+        .await;
+    let entries = read_zip(&body_bytes(response).await);
+    assert_eq!(
+        entries,
+        vec![entry("c.txt", b"charlie"), entry("c (1).txt", b"")]
+    );
+
+    let response = app.get("/api/v1/files/archive?ids=").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app
+        .get(&format!(
+            "/api/v1/files/archive?ids={}",
+            uuid::Uuid::new_v4()
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn uploaded_archives_are_extracted_within_limits() {
+    let app = TestApp::spawn().await;
+
+    // * The archive is encrypted at rest, so its directory and entries come from ranged
+    // * reads through the decrypting path
+    app.encrypt_files().await;
+    let source = "console.log(1);\n".repeat(200);
+    let archive = build_zip(
+        &[
+            ("readme.md", b"# bundle"),
+            ("assets/", b""),
+            ("src/app.js", source.as_bytes()),
+            // * A PNG under a JPEG name is stored as what its content is
+            ("logo.jpg", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            ("../escape.txt", b"x"),
+            ("broken.txt", &[b'z'; 2000]),
+        ],
+        Some("broken.txt"),
+    )
+    .await;
+    let results = upload_archive(&app, "bundle.zip", &archive).await;
+    let summary = results
+        .iter()
+        .map(|result| {
+            (
+                result["fileName"].as_str().unwrap(),
+                result["path"]
+                    .as_str()
+                    .or(result["error"].as_str())
+                    .unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("readme.md", "docs/bundle/readme.md"),
+            ("src/app.js", "docs/bundle/src/app.js"),
+            ("logo.jpg", "docs/bundle/logo.jpg"),
+            ("../escape.txt", "invalid_name"),
+            ("broken.txt", "invalid_archive"),
+        ]
+    );
+    assert_eq!(results[0]["type"], "md");
+    assert_eq!(results[1]["type"], "js");
+    assert_eq!(results[2]["type"], "png");
+    assert_eq!(results[2]["declaredType"], "jpg");
+
+    assert_eq!(titles(&app, "docs").await, vec!["bundle"]);
+    assert_eq!(
+        titles(&app, "docs/bundle").await,
+        vec!["assets", "logo.jpg", "readme.md", "src"]
+    );
+    let response = app
+        .get(&format!(
+            "/api/v1/files/download/{}",
+            results[1]["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(&body_bytes(response).await[..], source.as_bytes());
+    // * Neither the archive nor the broken entry left an object behind
+    assert_eq!(app.state.storage.list_objects(None).await.unwrap().len(), 3);
+
+    // * Ten MiB of zeros deflate to about ten KiB
+    let zeros = vec![0; 10 * 1024 * 1024];
+    let bomb = build_zip(&[("zeros.bin", &zeros)], None).await;
+    let results = upload_archive(&app, "bomb.zip", &bomb).await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["fileName"], "bomb.zip");
+    assert_eq!(results[0]["error"], "archive_too_large");
+
+    let results = upload_archive(&app, "junk.zip", b"not an archive at all").await;
+    assert_eq!(results[0]["error"], "invalid_archive");
+    assert_eq!(titles(&app, "docs").await, vec!["bundle"]);
+    assert_eq!(app.state.storage.list_objects(None).await.unwrap().len(), 3);
+
+    app.cleanup().await;
+}
+
+/// An archive made of nothing but ZIP64 end records, whose directory sits at
+/// `directory_offset` and whose end record is said to sit at `record_offset`.
+fn zip64_ends(record_offset: u64, directory_offset: u64) -> Vec<u8> {
+    let mut archive = vec![];
+    archive.extend(0x06064b50u32.to_le_bytes());
+    archive.extend(44u64.to_le_bytes());
+    archive.extend([45, 0, 45, 0]);
+    archive.extend([0; 8]);
+    archive.extend(1u64.to_le_bytes());
+    archive.extend(1u64.to_le_bytes());
+    archive.extend(46u64.to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend(0x07064b50u32.to_le_bytes());
+    archive.extend([0; 4]);
+    archive.extend(record_offset.to_le_bytes());
+    archive.extend(1u32.to_le_bytes());
+    archive.extend(0x06054b50u32.to_le_bytes());
+    archive.extend([0; 4]);
+    archive.extend([0xff; 12]);
+    archive.extend([0; 2]);
+    archive
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn offsets_past_the_largest_archive_are_refused() {
+    let app = TestApp::spawn().await;
+
+    for archive in [zip64_ends(0, u64::MAX - 10), zip64_ends(u64::MAX - 10, 0)] {
+        let results = upload_archive(&app, "crafted.zip", &archive).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["error"], "invalid_archive");
+    }
+
+    // * An entry said to start past the directory refuses the archive before extraction
+    let mut archive = build_zip(&[("a.txt", b"a")], None).await;
+    let header = archive
+        .windows(4)
+        .position(|window| window == 0x02014b50u32.to_le_bytes())
+        .unwrap();
+    archive[header + 42..header + 46].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+    let results = upload_archive(&app, "crafted.zip", &archive).await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["fileName"], "crafted.zip");
+    assert_eq!(results[0]["error"], "invalid_archive");
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn stored_archives_are_browsed_entry_by_entry() {
+    let app = TestApp::spawn().await;
+
+    let archive = build_zip(
+        &[
+            ("dist/", b""),
+            ("dist/app.js", b"console.log(1);"),
+            ("notes.txt", b"release notes"),
+        ],
+        None,
+    )
+    .await;
+    let id = upload(&app, "build.zip", "application/zip", &archive).await;
+
+    let listed = body_json(app.get(&format!("/api/v1/files/{}/entries", id)).await).await;
+    assert_eq!(
+        listed["data"],
+        json!([
+            {
+                "name": "dist/",
+                "size": 0,
+                "compressedSize": 0,
+                "modified": "1980-01-01T00:00:00Z",
+                "isFolder": true,
+            },
+            {
+                "name": "dist/app.js",
+                "size": 15,
+                "compressedSize": listed["data"][1]["compressedSize"],
+                "modified": "1980-01-01T00:00:00Z",
+                "isFolder": false,
+            },
+            {
+                "name": "notes.txt",
+                "size": 13,
+                "compressedSize": listed["data"][2]["compressedSize"],
+                "modified": "1980-01-01T00:00:00Z",
+                "isFolder": false,
+            },
+        ])
+    );
+
+    let response = app
+        .get(&format!("/api/v1/files/{}/entries/dist/app.js", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/javascript");
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"app.js\""
+    );
+    assert_eq!(&body_bytes(response).await[..], b"console.log(1);");
+
+    let response = app.get(&format!("/api/v1/files/{}/entries/dist", id)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let text = upload(&app, "notes.txt", "text/plain", b"plain").await;
+    let response = app.get(&format!("/api/v1/files/{}/entries", text)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // * Archives streamed by `/files/archive` carry data descriptors and read back the same
+    let response = app
+        .get(&format!("/api/v1/files/archive?ids={},{}", text, id))
+        .await;
+    let streamed = upload(
+        &app,
+        "bundle.zip",
+        "application/zip",
+        &body_bytes(response).await,
+    )
+    .await;
+    let response = app
+        .get(&format!("/api/v1/files/{}/entries/build.zip", streamed))
+        .await;
+    assert_eq!(&body_bytes(response).await[..], &archive[..]);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,240 @@
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use crate::{
+    cache::memory_cache::MemoryCache,
+    consts::UPLOAD_PART_SIZE,
+    enums::errors::StorageError,
+    models::storage::PutOptions,
+    storage::{local_storage::LocalStorage, memory_storage::MemoryStorage},
+    traits::{cache_traits::CacheStore, storage_traits::StorageBackend},
+    utils::file_utils::StreamingUpload,
+};
+
+async fn read_object(storage: &dyn StorageBackend, key: &str) -> Vec<u8> {
+    let mut object = storage.get_object(key).await.unwrap();
+    let mut body = Vec::new();
+    object.body.read_to_end(&mut body).await.unwrap();
+    body
+}
+
+async fn exercise_backend(storage: &dyn StorageBackend) {
+    let options = PutOptions {
+        content_type: Some(String::from("text/plain")),
+        is_public: false,
+    };
+
+    let meta = storage
+        .put_object("docs/a.txt", Bytes::from_static(b"first"), &options)
+        .await
+        .unwrap();
+    assert_eq!(meta.size, 5);
+    assert!(meta.etag.is_some());
+    storage
+        .put_object("docs/nested/b.txt", Bytes::from_static(b"second"), &options)
+        .await
+        .unwrap();
+    storage
+        .put_object("other.txt", Bytes::from_static(b"third"), &options)
+        .await
+        .unwrap();
+
+    assert_eq!(read_object(storage, "docs/a.txt").await, b"first");
+    let mut object = storage.get_object_range("docs/a.txt", 1, 3).await.unwrap();
+    let mut body = Vec::new();
+    object.body.read_to_end(&mut body).await.unwrap();
+    assert_eq!(body, b"irs");
+    assert_eq!(object.meta.size, 5);
+    assert_eq!(
+        storage.head_object("docs/nested/b.txt").await.unwrap().size,
+        6
+    );
+
+    let keys = storage
+        .list_objects(Some("docs/"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|object| object.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec!["docs/a.txt", "docs/nested/b.txt"]);
+
+    storage
+        .copy_object("docs/a.txt", "copies/a.txt", false)
+        .await
+        .unwrap();
+    assert_eq!(read_object(storage, "copies/a.txt").await, b"first");
+
+    storage.delete_object("docs/a.txt").await.unwrap();
+    storage.delete_object("docs/a.txt").await.unwrap();
+    assert!(matches!(
+        storage.head_object("docs/a.txt").await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert!(matches!(
+        storage
+            .copy_object("docs/a.txt", "copies/missing.txt", false)
+            .await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert_eq!(storage.list_objects(None).await.unwrap().len(), 3);
+
+    storage
+        .put_object("batch/one.txt", Bytes::from_static(b"1"), &options)
+        .await
+        .unwrap();
+    storage
+        .delete_objects(&[
+            String::from("batch/one.txt"),
+            String::from("batch/missing.txt"),
+        ])
+        .await
+        .unwrap();
+    assert!(
+        storage
+            .list_objects(Some("batch/"))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let upload_id = storage
+        .create_multipart_upload("parts/joined.txt", &options)
+        .await
+        .unwrap();
+    let second = storage
+        .upload_part(
+            "parts/joined.txt",
+            &upload_id,
+            2,
+            Bytes::from_static(b"-two"),
+        )
+        .await
+        .unwrap();
+    let first = storage
+        .upload_part(
+            "parts/joined.txt",
+            &upload_id,
+            1,
+            Bytes::from_static(b"one"),
+        )
+        .await
+        .unwrap();
+    let meta = storage
+        .complete_multipart_upload("parts/joined.txt", &upload_id, &[first, second])
+        .await
+        .unwrap();
+    assert_eq!(meta.size, 7);
+    assert_eq!(read_object(storage, "parts/joined.txt").await, b"one-two");
+
+    let upload_id = storage
+        .create_multipart_upload("parts/aborted.txt", &options)
+        .await
+        .unwrap();
+    storage
+        .upload_part(
+            "parts/aborted.txt",
+            &upload_id,
+            1,
+            Bytes::from_static(b"gone"),
+        )
+        .await
+        .unwrap();
+    storage
+        .abort_multipart_upload("parts/aborted.txt", &upload_id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        storage.head_object("parts/aborted.txt").await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert_eq!(storage.list_objects(None).await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn memory_storage_roundtrip() {
+    exercise_backend(&MemoryStorage::new()).await;
+}
+
+#[tokio::test]
+async fn local_storage_roundtrip() {
+    let root = std::env::temp_dir().join(format!("filestore-test-{}", Uuid::new_v4()));
+    exercise_backend(&LocalStorage::new(&root)).await;
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+}
+
+#[tokio::test]
+async fn streaming_upload_switches_to_multipart() {
+    let storage = MemoryStorage::new();
+    let chunk = vec![7u8; 1024 * 1024];
+    let chunks = UPLOAD_PART_SIZE / chunk.len() + 2;
+
+    let mut upload = StreamingUpload::new(&storage, "large.bin", PutOptions::default());
+    let mut hasher = blake3::Hasher::new();
+    for _ in 0..chunks {
+        upload.write(&chunk).await.unwrap();
+        hasher.update(&chunk);
+    }
+    let (meta, hash, size) = upload.finish().await.unwrap();
+
+    assert_eq!(size, (chunks * chunk.len()) as i64);
+    assert_eq!(meta.size, size);
+    assert_eq!(hash, hasher.finalize());
+    assert_eq!(read_object(&storage, "large.bin").await.len() as i64, size);
+
+    let mut upload = StreamingUpload::new(&storage, "small.bin", PutOptions::default());
+    upload.write(b"small").await.unwrap();
+    let (meta, hash, _) = upload.finish().await.unwrap();
+    assert_eq!(meta.size, 5);
+    assert_eq!(hash, blake3::hash(b"small"));
+}
+
+#[tokio::test]
+async fn local_storage_rejects_escaping_keys() {
+    let root = std::env::temp_dir().join(format!("filestore-test-{}", Uuid::new_v4()));
+    let storage = LocalStorage::new(&root);
+
+    for key in [
+        "../outside.txt",
+        "docs/../../outside.txt",
+        ".staging/file",
+        "",
+    ] {
+        assert!(matches!(
+            storage
+                .put_object(key, Bytes::from_static(b"x"), &PutOptions::default())
+                .await,
+            Err(StorageError::InvalidKey(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn memory_cache_expires_values() {
+    let cache = MemoryCache::new();
+
+    cache.set_ex("session", "value", 60).await.unwrap();
+    cache.set_ex("expired", "value", 0).await.unwrap();
+    assert_eq!(
+        cache.get("session").await.unwrap().as_deref(),
+        Some("value")
+    );
+    assert_eq!(cache.get("expired").await.unwrap(), None);
+
+    assert!(!cache.set_nx_ex("session", "other", 60).await.unwrap());
+    assert!(cache.set_nx_ex("expired", "fresh", 60).await.unwrap());
+    assert_eq!(
+        cache.get("session").await.unwrap().as_deref(),
+        Some("value")
+    );
+
+    cache.sadd("set", "a").await.unwrap();
+    cache.sadd("set", "a").await.unwrap();
+    assert_eq!(cache.smembers("set").await.unwrap(), vec!["a"]);
+
+    cache.del("session").await.unwrap();
+    cache.del("set").await.unwrap();
+    assert_eq!(cache.get("session").await.unwrap(), None);
+    assert!(cache.smembers("set").await.unwrap().is_empty());
+}
@@ -0,0 +1,268 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+
+use serde_json::json;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+use crate::{models::storage::PutOptions, utils::blob_utils::migrate_legacy_objects};
+
+async fn stored_keys(app: &TestApp) -> Vec<String> {
+    app.state
+        .storage
+        .list_objects(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|object| object.key)
+        .collect()
+}
+
+async fn delete(app: &TestApp, id: &str) {
+    for uri in [
+        format!("/api/v1/files/delete/{}", id),
+        format!("/api/v1/files/trash/{}", id),
+    ] {
+        let response = app
+            .request(Request::delete(uri).body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn identical_uploads_share_one_blob() {
+    let app = TestApp::spawn().await;
+
+    let file = |file_name| TestFile {
+        field: "file0",
+        file_name,
+        content_type: "text/plain",
+        body: b"same bytes",
+    };
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[file("a.txt"), file("b.txt")],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .upload("/api/v1/files/upload?path=", &[file("c.txt")])
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(stored_keys(&app).await.len(), 1);
+    let row = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT hash, ref_count FROM blobs", &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        row.get::<_, String>("hash"),
+        blake3::hash(b"same bytes").to_string()
+    );
+    assert_eq!(row.get::<_, i64>("ref_count"), 3);
+
+    let savings = body_json(app.get("/api/v1/files/dedup").await).await;
+    assert_eq!(savings["data"]["logicalBytes"], 30);
+    assert_eq!(savings["data"]["storedBytes"], 10);
+    assert_eq!(savings["data"]["savedBytes"], 20);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let ids = listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["id"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(ids.len(), 3);
+
+    // * Every file reads the shared blob, and it outlives all but the last reference
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", ids[1]))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    delete(&app, &ids[0]).await;
+    delete(&app, &ids[1]).await;
+    assert_eq!(stored_keys(&app).await.len(), 1);
+
+    delete(&app, &ids[2]).await;
+    assert!(stored_keys(&app).await.is_empty());
+
+    let blobs = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query("SELECT hash FROM blobs", &[])
+        .await
+        .unwrap();
+    assert!(blobs.is_empty());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn public_and_private_copies_do_not_share_a_blob() {
+    let app = TestApp::spawn().await;
+
+    let file = |file_name| TestFile {
+        field: "file0",
+        file_name,
+        content_type: "text/plain",
+        body: b"same bytes",
+    };
+    for (uri, file_name) in [
+        ("/api/v1/files/upload?path=", "private.txt"),
+        ("/api/v1/files/upload?path=&is_public=true", "public.txt"),
+        (
+            "/api/v1/files/upload?path=&is_public=true",
+            "public copy.txt",
+        ),
+    ] {
+        let response = app.upload(uri, &[file(file_name)]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // * The ACL is set once per object, so each visibility needs its own
+    assert_eq!(stored_keys(&app).await.len(), 2);
+    let rows = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query(
+            "SELECT files.is_public, blobs.ref_count FROM files
+            JOIN blobs ON blobs.hash = files.blob_hash
+            ORDER BY files.title;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| (row.get::<_, bool>(0), row.get::<_, i64>(1)))
+        .collect::<Vec<_>>();
+    assert_eq!(rows, vec![(false, 1), (true, 2), (true, 2)]);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn skipped_uploads_do_not_leave_blobs_behind() {
+    let app = TestApp::spawn().await;
+
+    let file = |file_name, body: &'static [u8]| TestFile {
+        field: "file0",
+        file_name,
+        content_type: "text/plain",
+        body,
+    };
+    app.upload("/api/v1/files/upload?path=", &[file("notes.txt", b"first")])
+        .await;
+    let response = app
+        .request(
+            Request::post("/api/v1/files/create/folder?path=")
+                .header("Content-Type", "application/json")
+                .body(Body::from(json!({ "title": "drafts" }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // * A file cannot become a version of a folder, so its content must not linger
+    app.upload("/api/v1/files/upload?path=", &[file("drafts", b"second")])
+        .await;
+
+    assert_eq!(stored_keys(&app).await.len(), 1);
+    let count: i64 = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT COUNT(*) FROM blobs", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(count, 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn legacy_objects_move_to_blob_keys() {
+    let app = TestApp::spawn().await;
+
+    // * Rows as written before objects were keyed by id: no blob, stored under path + title
+    let conn = app.state.db_pool.get().await.unwrap();
+    let mut ids = vec![];
+    for (title, body) in [
+        ("a.txt", Some("shared")),
+        ("b.txt", Some("shared")),
+        ("c.txt", Some("unique")),
+        ("missing.txt", None),
+    ] {
+        let row = conn
+            .query_one(
+                "INSERT INTO files (title, owner_id, size, type, path)
+                SELECT $1, id, 6, 'txt', 'docs/' FROM users
+                RETURNING id::text;",
+                &[&title],
+            )
+            .await
+            .unwrap();
+        ids.push(row.get::<_, String>(0));
+        if let Some(body) = body {
+            app.state
+                .storage
+                .put_object(
+                    &format!("docs/{}", title),
+                    body.as_bytes().to_vec().into(),
+                    &PutOptions::default(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    assert_eq!(migrate_legacy_objects(&app.state).await.unwrap(), 3);
+    assert_eq!(migrate_legacy_objects(&app.state).await.unwrap(), 0);
+
+    // * Rows are moved in id order, so either shared file may own the blob
+    let keys = stored_keys(&app).await;
+    assert_eq!(keys.len(), 2);
+    assert!(keys.contains(&format!("blobs/{}", ids[2])));
+    assert!(
+        ids[..2]
+            .iter()
+            .any(|id| keys.contains(&format!("blobs/{}", id)))
+    );
+
+    let response = app.get(&format!("/api/v1/files/download/{}", ids[1])).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"shared");
+    let missing = conn
+        .query_one(
+            "SELECT blob_hash FROM files WHERE id::text = $1",
+            &[&ids[3]],
+        )
+        .await
+        .unwrap();
+    assert!(missing.get::<_, Option<String>>(0).is_none());
+
+    delete(&app, &ids[0]).await;
+    assert_eq!(stored_keys(&app).await.len(), 2);
+
+    drop(conn);
+    app.cleanup().await;
+}
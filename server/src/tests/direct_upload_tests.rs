@@ -0,0 +1,263 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{Request, Response, StatusCode},
+};
+use serde_json::{Value, json};
+
+use super::{TestApp, body_bytes, body_json};
+use crate::models::storage::PutOptions;
+
+// * The memory backend presigns `memory:///{key}` URLs
+fn presigned_key(request: &Value) -> String {
+    let url = request["url"].as_str().unwrap();
+    let url = url.strip_prefix("memory:///").unwrap();
+    url.split('?').next().unwrap().to_string()
+}
+
+async fn post_json(app: &TestApp, uri: &str, payload: Value) -> Response<Body> {
+    app.request(
+        Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap(),
+    )
+    .await
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn direct_put_is_recorded_on_completion() {
+    let app = TestApp::spawn().await;
+
+    let response = post_json(
+        &app,
+        "/api/v1/files/direct",
+        json!({ "title": "notes.txt", "contentType": "text/plain", "size": 11 }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let target = body_json(response).await;
+    assert!(target["data"]["uploadId"].is_null());
+    assert_eq!(
+        target["data"]["put"]["headers"]["content-type"],
+        "text/plain"
+    );
+    let id = target["data"]["id"].as_str().unwrap().to_string();
+    let key = presigned_key(&target["data"]["put"]);
+    assert_eq!(key, format!("blobs/{}", id));
+
+    // * Stands in for the browser sending the presigned PUT
+    app.state
+        .storage
+        .put_object(
+            &key,
+            Bytes::from_static(b"hello world"),
+            &PutOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let response = post_json(
+        &app,
+        &format!("/api/v1/files/direct/{}/complete", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["data"], id);
+
+    let row = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "SELECT size, hash, etag, type FROM files WHERE id::text = $1",
+            &[&id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, i64>("size"), 11);
+    assert_eq!(
+        row.get::<_, String>("hash"),
+        blake3::hash(b"hello world").to_string()
+    );
+    assert!(row.get::<_, Option<String>>("etag").is_some());
+    assert_eq!(row.get::<_, String>("type"), "txt");
+
+    // * The pending upload is gone once completed
+    let response = post_json(
+        &app,
+        &format!("/api/v1/files/direct/{}/complete", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn direct_multipart_upload_is_completed_with_part_etags() {
+    let app = TestApp::spawn().await;
+
+    let body = (0..9 * 1024 * 1024)
+        .map(|idx| (idx % 251) as u8)
+        .collect::<Vec<_>>();
+    let response = post_json(
+        &app,
+        "/api/v1/files/direct",
+        json!({ "title": "video.bin", "size": body.len() }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let target = body_json(response).await;
+    let data = &target["data"];
+    assert!(data["put"].is_null());
+    let upload_id = data["uploadId"].as_str().unwrap();
+    let part_size = data["partSize"].as_u64().unwrap() as usize;
+    let parts = data["parts"].as_array().unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[1]["partNumber"], 2);
+    let key = presigned_key(&parts[0]);
+
+    let mut uploaded = vec![];
+    for (idx, chunk) in body.chunks(part_size).enumerate() {
+        let part = app
+            .state
+            .storage
+            .upload_part(
+                &key,
+                upload_id,
+                idx as i32 + 1,
+                Bytes::copy_from_slice(chunk),
+            )
+            .await
+            .unwrap();
+        uploaded.push(json!({ "partNumber": part.part_number, "etag": part.etag }));
+    }
+
+    let response = post_json(
+        &app,
+        &format!(
+            "/api/v1/files/direct/{}/complete",
+            data["id"].as_str().unwrap()
+        ),
+        json!({ "parts": uploaded }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let id = body_json(response).await["data"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn direct_uploads_that_never_arrived_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let response = post_json(
+        &app,
+        "/api/v1/files/direct",
+        json!({ "title": "missing.txt", "size": 5 }),
+    )
+    .await;
+    let target = body_json(response).await;
+    let id = target["data"]["id"].as_str().unwrap().to_string();
+    let key = presigned_key(&target["data"]["put"]);
+
+    let response = post_json(
+        &app,
+        &format!("/api/v1/files/direct/{}/complete", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // * A short object is rejected and removed
+    app.state
+        .storage
+        .put_object(&key, Bytes::from_static(b"abc"), &PutOptions::default())
+        .await
+        .unwrap();
+    let response = post_json(
+        &app,
+        &format!("/api/v1/files/direct/{}/complete", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(app.state.storage.head_object(&key).await.is_err());
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    assert!(listed["data"].as_array().unwrap().is_empty());
+
+    let response = post_json(
+        &app,
+        "/api/v1/files/direct",
+        json!({ "title": "huge.bin", "size": 104857600 + 1 }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn direct_upload_names_are_checked_and_folders_created() {
+    let app = TestApp::spawn().await;
+
+    for payload in [
+        json!({ "title": "../escape.txt", "size": 5 }),
+        json!({ "title": "notes.txt", "path": "docs/../..", "size": 5 }),
+        json!({ "title": "..", "path": "docs", "size": 5 }),
+    ] {
+        let response = post_json(&app, "/api/v1/files/direct", payload).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let response = post_json(
+        &app,
+        "/api/v1/files/direct",
+        json!({ "title": "notes.txt", "path": "docs/2024", "size": 5 }),
+    )
+    .await;
+    let target = body_json(response).await;
+    let id = target["data"]["id"].as_str().unwrap().to_string();
+    app.state
+        .storage
+        .put_object(
+            &presigned_key(&target["data"]["put"]),
+            Bytes::from_static(b"hello"),
+            &PutOptions::default(),
+        )
+        .await
+        .unwrap();
+    let response = post_json(
+        &app,
+        &format!("/api/v1/files/direct/{}/complete", id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=docs/2024").await).await;
+    assert_eq!(listed["data"][0]["id"], id);
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    assert_eq!(listed["data"][0]["title"], "docs");
+    assert_eq!(listed["data"][0]["type"], "folder");
+
+    app.cleanup().await;
+}
@@ -0,0 +1,221 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use bytes::BytesMut;
+use serde_json::json;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+use crate::{
+    consts::ENCRYPTION_CHUNK_SIZE,
+    utils::crypto_utils::{ChunkEncryptor, Keyring, MasterKey, encrypted_size, rotate_data_keys},
+};
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|idx| (idx % 251) as u8).collect()
+}
+
+async fn stored_object(app: &TestApp) -> Vec<u8> {
+    let objects = app.state.storage.list_objects(None).await.unwrap();
+    assert_eq!(objects.len(), 1);
+    let object = app.state.storage.get_object(&objects[0].key).await.unwrap();
+    let mut stored = vec![];
+    tokio::io::AsyncReadExt::read_to_end(&mut { object.body }, &mut stored)
+        .await
+        .unwrap();
+    stored
+}
+
+async fn only_file_id(app: &TestApp) -> String {
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    listed["data"][0]["id"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn streamed_chunks_match_whole_object_encryption() {
+    let keyring = Keyring::new(MasterKey::new(&rand::random()), vec![]);
+    let encryption = keyring.new_encryption().unwrap();
+    let cipher = keyring.cipher(&encryption).unwrap();
+
+    for len in [
+        0,
+        100,
+        ENCRYPTION_CHUNK_SIZE,
+        3 * ENCRYPTION_CHUNK_SIZE + 100,
+    ] {
+        let plaintext = pattern(len);
+        let mut encryptor = ChunkEncryptor::new(keyring.cipher(&encryption).unwrap());
+        let mut streamed = BytesMut::new();
+        for piece in plaintext.chunks(1000) {
+            encryptor.update(piece, &mut streamed).unwrap();
+        }
+        encryptor.finish(&mut streamed).unwrap();
+
+        let last_index = len.div_ceil(ENCRYPTION_CHUNK_SIZE).max(1) as u32 - 1;
+        let whole = cipher.encrypt_chunks(0, last_index, &plaintext).unwrap();
+        assert_eq!(streamed.as_ref(), whole.as_slice());
+        assert_eq!(whole.len() as u64, encrypted_size(len as u64));
+    }
+
+    // * A chunk sealed as the last one does not open at another position
+    let sealed = cipher.encrypt_chunk(0, true, b"tail").unwrap();
+    assert!(cipher.decrypt_chunk(0, false, &sealed).is_err());
+    assert_eq!(cipher.decrypt_chunk(0, true, &sealed).unwrap(), b"tail");
+}
+
+#[test]
+fn data_keys_are_rewrapped_under_the_current_master_key() {
+    let old = rand::random();
+    let encryption = Keyring::new(MasterKey::new(&old), vec![])
+        .new_encryption()
+        .unwrap();
+    let keyring = Keyring::new(MasterKey::new(&rand::random()), vec![MasterKey::new(&old)]);
+
+    let rewrapped = keyring.rewrap(&encryption).unwrap();
+    assert_eq!(rewrapped.key_id, keyring.current_id());
+    assert_ne!(rewrapped.key_id, encryption.key_id);
+
+    let sealed = keyring
+        .cipher(&encryption)
+        .unwrap()
+        .encrypt_chunk(0, true, b"data")
+        .unwrap();
+    let opened = keyring
+        .cipher(&rewrapped)
+        .unwrap()
+        .decrypt_chunk(0, true, &sealed)
+        .unwrap();
+    assert_eq!(opened, b"data");
+
+    let unknown = Keyring::new(MasterKey::new(&rand::random()), vec![]);
+    assert!(unknown.cipher(&encryption).is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn encrypted_uploads_are_decrypted_on_download() {
+    let app = TestApp::spawn().await;
+
+    let settings = body_json(app.get("/api/v1/users/settings").await).await;
+    assert_eq!(settings["data"]["encryptFiles"], false);
+    app.encrypt_files().await;
+    let settings = body_json(app.get("/api/v1/users/settings").await).await;
+    assert_eq!(settings["data"]["encryptFiles"], true);
+
+    let body = pattern(3 * ENCRYPTION_CHUNK_SIZE + 17);
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name: "secret.bin",
+                content_type: "application/octet-stream",
+                body: &body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let stored = stored_object(&app).await;
+    assert_eq!(stored.len() as u64, encrypted_size(body.len() as u64));
+    assert!(!stored.windows(64).any(|window| window == &body[..64]));
+
+    let id = only_file_id(&app).await;
+    let uri = format!("/api/v1/files/download/{}?path=", id);
+    let response = app.get(&uri).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-length"], body.len().to_string());
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    // * Ranges crossing a chunk boundary and ending in the last chunk
+    let start = ENCRYPTION_CHUNK_SIZE - 5;
+    for (header, expected) in [
+        (
+            format!("bytes={}-{}", start, start + 9),
+            &body[start..start + 10],
+        ),
+        (String::from("bytes=-20"), &body[body.len() - 20..]),
+    ] {
+        let response = app
+            .request(
+                Request::get(&uri)
+                    .header("Range", header)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(body_bytes(response).await.as_ref() == expected);
+    }
+
+    let response = app.get(&format!("/api/v1/files/read/{}/link", id)).await;
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+
+    let response = app
+        .request(
+            Request::post("/api/v1/files/direct")
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    json!({ "title": "notes.txt", "size": 11 }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn rotated_keys_still_open_stored_files() {
+    let mut app = TestApp::spawn().await;
+
+    let old = rand::random();
+    app.set_keyring(Keyring::new(MasterKey::new(&old), vec![]));
+    app.encrypt_files().await;
+    let body = pattern(ENCRYPTION_CHUNK_SIZE + 1);
+    app.upload(
+        "/api/v1/files/upload?path=",
+        &[TestFile {
+            field: "file0",
+            file_name: "secret.bin",
+            content_type: "application/octet-stream",
+            body: &body,
+        }],
+    )
+    .await;
+    let stored = stored_object(&app).await;
+
+    let new = rand::random();
+    app.set_keyring(Keyring::new(
+        MasterKey::new(&new),
+        vec![MasterKey::new(&old)],
+    ));
+    assert_eq!(rotate_data_keys(&app.state).await.unwrap(), 1);
+    assert_eq!(rotate_data_keys(&app.state).await.unwrap(), 0);
+
+    // * Only the wrapped data key changes, the object itself is left alone
+    assert_eq!(stored_object(&app).await, stored);
+    let key_id: String = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT key_id FROM blobs", &[])
+        .await
+        .unwrap()
+        .get("key_id");
+    assert_eq!(key_id, app.state.keyring.as_ref().unwrap().current_id());
+
+    // * Once every key is rewrapped the previous master key can be dropped
+    app.set_keyring(Keyring::new(MasterKey::new(&new), vec![]));
+    let id = only_file_id(&app).await;
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    app.cleanup().await;
+}
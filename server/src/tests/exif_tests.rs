@@ -0,0 +1,355 @@
+use std::{io::Cursor, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{
+        Request, StatusCode,
+        header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE},
+    },
+};
+use image::{ImageFormat, Rgb, RgbImage, codecs::jpeg::JpegEncoder};
+use img_parts::{Bytes, ImageEXIF, png::Png};
+use jiff::Timestamp;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+use crate::{
+    enums::file_enums::FileTypes,
+    models::file::PhotoMetadata,
+    utils::exif_utils::{read_photo_metadata, strip_metadata},
+};
+
+/// A big-endian TIFF field: tag, type and the encoded values.
+struct Field(u16, u16, u32, Vec<u8>);
+
+fn ascii(tag: u16, text: &str) -> Field {
+    let mut data = text.as_bytes().to_vec();
+    data.push(0);
+    Field(tag, 2, data.len() as u32, data)
+}
+
+fn short(tag: u16, value: u16) -> Field {
+    Field(tag, 3, 1, value.to_be_bytes().to_vec())
+}
+
+fn long(tag: u16, value: u32) -> Field {
+    Field(tag, 4, 1, value.to_be_bytes().to_vec())
+}
+
+fn rationals(tag: u16, values: &[(u32, u32)]) -> Field {
+    let data = values
+        .iter()
+        .flat_map(|(numerator, denominator)| {
+            [numerator.to_be_bytes(), denominator.to_be_bytes()].concat()
+        })
+        .collect();
+    Field(tag, 5, values.len() as u32, data)
+}
+
+/// An IFD starting at `start`, with values that do not fit an entry right after it.
+fn ifd(start: u32, fields: &[Field]) -> Vec<u8> {
+    let mut entries = (fields.len() as u16).to_be_bytes().to_vec();
+    let mut values = vec![];
+    let values_start = start + 2 + 12 * fields.len() as u32 + 4;
+    for Field(tag, kind, count, data) in fields {
+        entries.extend(tag.to_be_bytes());
+        entries.extend(kind.to_be_bytes());
+        entries.extend(count.to_be_bytes());
+        match data.len() <= 4 {
+            true => {
+                let mut inline = data.clone();
+                inline.resize(4, 0);
+                entries.extend(inline);
+            }
+            false => {
+                entries.extend((values_start + values.len() as u32).to_be_bytes());
+                values.extend(data);
+                values.resize(values.len().next_multiple_of(2), 0);
+            }
+        }
+    }
+    entries.extend(0u32.to_be_bytes());
+    [entries, values].concat()
+}
+
+/// EXIF of a photo taken in Berlin, as TIFF data.
+fn photo_exif(orientation: u16) -> Vec<u8> {
+    let primary = |exif: u32, gps: u32| {
+        vec![
+            ascii(0x010f, "Canon"),
+            ascii(0x0110, "Canon EOS R6"),
+            short(0x0112, orientation),
+            long(0x8769, exif),
+            long(0x8825, gps),
+        ]
+    };
+    let exif = [
+        ascii(0x9003, "2024:05:01 12:30:00"),
+        ascii(0x9011, "+02:00"),
+        long(0xa002, 6000),
+        long(0xa003, 4000),
+    ];
+    let gps = [
+        ascii(0x0001, "N"),
+        rationals(0x0002, &[(52, 1), (30, 1), (0, 1)]),
+        ascii(0x0003, "W"),
+        rationals(0x0004, &[(13, 1), (15, 1), (0, 1)]),
+    ];
+
+    let exif_start = 8 + ifd(8, &primary(0, 0)).len() as u32;
+    let gps_start = exif_start + ifd(exif_start, &exif).len() as u32;
+    [
+        b"MM\0*\0\0\0\x08".to_vec(),
+        ifd(8, &primary(exif_start, gps_start)),
+        ifd(exif_start, &exif),
+        ifd(gps_start, &gps),
+    ]
+    .concat()
+}
+
+fn photo(orientation: u16) -> Vec<u8> {
+    let mut jpeg = vec![];
+    JpegEncoder::new(&mut jpeg)
+        .encode_image(&RgbImage::from_pixel(8, 4, Rgb([200, 120, 40])))
+        .unwrap();
+    let app1 = [b"Exif\0\0".to_vec(), photo_exif(orientation)].concat();
+    [
+        jpeg[..2].to_vec(),
+        vec![0xff, 0xe1],
+        ((app1.len() + 2) as u16).to_be_bytes().to_vec(),
+        app1,
+        jpeg[2..].to_vec(),
+    ]
+    .concat()
+}
+
+#[test]
+fn photo_metadata_is_read_from_exif() {
+    assert_eq!(
+        read_photo_metadata(&photo(6)),
+        PhotoMetadata {
+            camera_make: Some(String::from("Canon")),
+            camera_model: Some(String::from("Canon EOS R6")),
+            taken_at: Some("2024-05-01T10:30:00Z".parse::<Timestamp>().unwrap()),
+            image_width: Some(6000),
+            image_height: Some(4000),
+            orientation: Some(6),
+            gps_latitude: Some(52.5),
+            gps_longitude: Some(-13.25),
+        }
+    );
+    assert_eq!(read_photo_metadata(b"plain text"), PhotoMetadata::default());
+}
+
+#[test]
+fn stripping_keeps_only_the_orientation() {
+    let stripped = strip_metadata(photo(6), &FileTypes::Jpeg).unwrap();
+    assert_eq!(
+        read_photo_metadata(&stripped),
+        PhotoMetadata {
+            orientation: Some(6),
+            ..PhotoMetadata::default()
+        }
+    );
+    assert_eq!(image::load_from_memory(&stripped).unwrap().width(), 8);
+
+    let upright = strip_metadata(photo(1), &FileTypes::Jpeg).unwrap();
+    assert_eq!(read_photo_metadata(&upright), PhotoMetadata::default());
+
+    let mut png = vec![];
+    RgbImage::from_pixel(4, 4, Rgb([0, 0, 0]))
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let mut png = Png::from_bytes(Bytes::from(png)).unwrap();
+    png.set_exif(Some(Bytes::from(photo_exif(1))));
+    let png = png.encoder().bytes().to_vec();
+    assert_eq!(
+        read_photo_metadata(&png).camera_make.as_deref(),
+        Some("Canon")
+    );
+    let stripped = strip_metadata(png, &FileTypes::Png).unwrap();
+    assert_eq!(read_photo_metadata(&stripped), PhotoMetadata::default());
+}
+
+async fn upload(app: &TestApp, file_name: &str, body: &[u8]) -> String {
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name,
+                content_type: "image/jpeg",
+                body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await["data"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+/// The metadata of `id` once the background processing of its upload read it.
+async fn read_metadata(app: &TestApp, id: &str) -> Value {
+    for _ in 0..50 {
+        let response = app.get(&format!("/api/v1/files/{}/metadata", id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let metadata = body_json(response).await["data"].clone();
+        if !metadata["takenAt"].is_null() {
+            return metadata;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("NO METADATA READ FOR {}", id);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn photos_are_listed_by_date_taken_and_stripped_for_others() {
+    let app = TestApp::spawn().await;
+
+    let body = photo(1);
+    let id = upload(&app, "berlin.jpg", &body).await;
+    let metadata = read_metadata(&app, &id).await;
+    assert_eq!(metadata["cameraModel"], "Canon EOS R6");
+    assert_eq!(metadata["gpsLatitude"], 52.5);
+    assert_eq!(metadata["imageWidth"], 6000);
+
+    // * An earlier capture sorts first, whenever it was uploaded
+    let conn = app.state.get_db_conn().await.unwrap();
+    let older = upload(&app, "older.jpg", &photo(3)).await;
+    read_metadata(&app, &older).await;
+    conn.execute(
+        "UPDATE files SET taken_at = '2020-01-01T00:00:00Z' WHERE id = $1;",
+        &[&Uuid::parse_str(&older).unwrap()],
+    )
+    .await
+    .unwrap();
+    let listed = body_json(
+        app.get("/api/v1/files/list?path=&sortField=taken_at&sortType=asc")
+            .await,
+    )
+    .await;
+    let titles = listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["older.jpg", "berlin.jpg"]);
+
+    // * The owner always gets the original
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(body_bytes(response).await.to_vec(), body);
+
+    let other = Uuid::new_v4();
+    conn.execute(
+        "INSERT INTO users (id, first_name, last_name, username, email, pw_hsh)
+        VALUES ($1, 'Other', 'User', 'other-user', 'other-user@example.com', '');",
+        &[&other],
+    )
+    .await
+    .unwrap();
+    conn.execute(
+        "UPDATE files SET owner_id = $2 WHERE id = $1;",
+        &[&Uuid::parse_str(&id).unwrap(), &other],
+    )
+    .await
+    .unwrap();
+    // * Private files of others are not there to download or link to
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.get(&format!("/api/v1/files/read/{}/link", id)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    conn.execute(
+        "UPDATE files SET is_public = TRUE WHERE id = $1;",
+        &[&Uuid::parse_str(&id).unwrap()],
+    )
+    .await
+    .unwrap();
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(body_bytes(response).await.to_vec(), body);
+
+    conn.execute(
+        "UPDATE users SET strip_metadata = TRUE WHERE id = $1;",
+        &[&other],
+    )
+    .await
+    .unwrap();
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "image/jpeg");
+    let etag = response.headers()[ETAG].clone();
+    let stripped = body_bytes(response).await;
+    assert_eq!(read_photo_metadata(&stripped), PhotoMetadata::default());
+    assert_eq!(image::load_from_memory(&stripped).unwrap().width(), 8);
+
+    // * Ranges and validators apply to the stripped copy
+    let response = app
+        .request(
+            Request::get(format!("/api/v1/files/download/{}", id))
+                .header(RANGE, "bytes=0-9")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[CONTENT_RANGE],
+        format!("bytes 0-9/{}", stripped.len())
+    );
+    assert_eq!(body_bytes(response).await, stripped[..10]);
+    let response = app
+        .request(
+            Request::get(format!("/api/v1/files/download/{}", id))
+                .header(IF_NONE_MATCH, etag)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn buckets_ask_for_stripping_on_their_own() {
+    let app = TestApp::spawn().await;
+
+    let conn = app.state.get_db_conn().await.unwrap();
+    let bucket: Uuid = conn
+        .query_one(
+            "INSERT INTO buckets (title, owner_id) SELECT 'photos', id FROM users RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let response = app
+        .request(
+            Request::patch(format!("/api/v1/buckets/{}", bucket))
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "stripMetadata": true }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["data"]["stripMetadata"], true);
+
+    let response = app
+        .request(
+            Request::patch(format!("/api/v1/buckets/{}", Uuid::new_v4()))
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "stripMetadata": true }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,374 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode, header::RANGE},
+};
+use headers::{HeaderMapExt, Range};
+use serde_json::Value;
+
+use tower::ServiceExt;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+use crate::utils::range_utils::resolve_ranges;
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn upload_list_download_delete_roundtrip() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name: "notes.txt",
+                content_type: "text/plain",
+                body: b"hello from the test suite",
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let files = listed["data"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["title"], "notes.txt");
+    assert_eq!(files[0]["type"], "txt");
+    assert_eq!(files[0]["size"], 25);
+    let id = files[0]["id"].as_str().unwrap().to_string();
+
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        body_bytes(response).await.as_ref(),
+        b"hello from the test suite"
+    );
+
+    let response = app
+        .request(
+            Request::delete(format!("/api/v1/files/delete/{}", id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    assert!(listed["data"].as_array().unwrap().is_empty());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn upload_conflicts_skip_replace_or_rename() {
+    let app = TestApp::spawn().await;
+
+    let upload = async |query: &str, body: &'static [u8]| {
+        let response = app
+            .upload(
+                &format!("/api/v1/files/upload?path={}", query),
+                &[TestFile {
+                    field: "file0",
+                    file_name: "report.pdf",
+                    content_type: "application/pdf",
+                    body,
+                }],
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let uploaded = body_json(response).await;
+        assert_eq!(uploaded["data"][0]["fileName"], "report.pdf");
+        (
+            uploaded["data"][0]["id"].as_str().unwrap().to_string(),
+            uploaded["data"][0]["title"].as_str().unwrap().to_string(),
+            uploaded["data"][0]["outcome"].as_str().unwrap().to_string(),
+        )
+    };
+    let content = async |id: &str| {
+        let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+        body_bytes(response).await
+    };
+
+    let (id, _, outcome) = upload("", b"first").await;
+    assert_eq!(outcome, "created");
+
+    let (skipped_id, title, outcome) = upload("&conflict=skip", b"second").await;
+    assert_eq!(
+        (skipped_id.as_str(), title.as_str()),
+        (id.as_str(), "report.pdf")
+    );
+    assert_eq!(outcome, "skipped");
+    assert_eq!(content(&id).await.as_ref(), b"first");
+
+    for (number, body) in [(1, &b"third"[..]), (2, &b"fourth"[..])] {
+        let (renamed_id, title, outcome) = upload("&conflict=rename", body).await;
+        assert_ne!(renamed_id, id);
+        assert_eq!(title, format!("report ({}).pdf", number));
+        assert_eq!(outcome, "renamed");
+        assert_eq!(content(&renamed_id).await.as_ref(), body);
+    }
+
+    let (replaced_id, _, outcome) = upload("&conflict=replace", b"fifth").await;
+    assert_eq!(
+        (replaced_id.as_str(), outcome.as_str()),
+        (id.as_str(), "replaced")
+    );
+    assert_eq!(content(&id).await.as_ref(), b"fifth");
+    // * Replacing is the default
+    let (_, _, outcome) = upload("", b"sixth").await;
+    assert_eq!(outcome, "replaced");
+
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=&conflict=overwrite",
+            &[TestFile {
+                field: "file0",
+                file_name: "report.pdf",
+                content_type: "application/pdf",
+                body: b"seventh",
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn uploads_report_every_field() {
+    let app = TestApp::spawn().await;
+
+    let file = |field, file_name, body| TestFile {
+        field,
+        file_name,
+        content_type: "text/plain",
+        body,
+    };
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=docs",
+            &[
+                file("file0", "a.txt", b"alpha"),
+                file("file0.tags", "tags.json", b"not json"),
+                file("file1", "unnamed", b"beta"),
+                file("file2", "b.txt", b"gamma"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let uploaded = body_json(response).await;
+    let results = uploaded["data"].as_array().unwrap();
+    assert_eq!(results.len(), 4);
+
+    assert_eq!(results[0]["field"], "file0");
+    assert_eq!(results[0]["outcome"], "created");
+    assert_eq!(results[0]["error"], Value::Null);
+    assert_eq!(results[0]["path"], "docs/a.txt");
+    assert_eq!(results[0]["title"], "a.txt");
+    assert_eq!(results[0]["size"], 5);
+    assert_eq!(results[0]["hash"], blake3::hash(b"alpha").to_string());
+    assert_eq!(results[0]["type"], "txt");
+
+    assert_eq!(results[1]["field"], "file0.tags");
+    assert_eq!(results[1]["outcome"], "failed");
+    assert_eq!(results[1]["error"], "invalid_tags");
+    assert_eq!(results[2]["fileName"], "unnamed");
+    assert_eq!(results[2]["outcome"], "failed");
+    assert_eq!(results[2]["error"], "invalid_name");
+    assert_eq!(results[2]["id"], Value::Null);
+
+    assert_eq!(results[3]["outcome"], "created");
+    let id = results[3]["id"].as_str().unwrap();
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"gamma");
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn large_uploads_are_sent_in_parts() {
+    let app = TestApp::spawn().await;
+
+    let body = (0..9 * 1024 * 1024)
+        .map(|idx| (idx % 251) as u8)
+        .collect::<Vec<_>>();
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name: "large.bin",
+                content_type: "application/octet-stream",
+                body: &body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let file = &listed["data"][0];
+    assert_eq!(file["size"], body.len());
+    let id = file["id"].as_str().unwrap().to_string();
+
+    let hash: String = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT hash FROM files WHERE id::text = $1", &[&id])
+        .await
+        .unwrap()
+        .get("hash");
+    assert_eq!(hash, blake3::hash(&body).to_string());
+
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    app.cleanup().await;
+}
+
+#[test]
+fn ranges_are_clamped_to_the_object() {
+    let resolve = |header: &str, size| {
+        let range = HeaderMap::from_iter([(RANGE, header.parse().unwrap())])
+            .typed_get::<Range>()
+            .unwrap();
+        resolve_ranges(&range, size)
+    };
+
+    assert_eq!(resolve("bytes=0-4", 10), vec![(0, 4)]);
+    assert_eq!(resolve("bytes=5-", 10), vec![(5, 9)]);
+    assert_eq!(resolve("bytes=-3", 10), vec![(7, 9)]);
+    assert_eq!(resolve("bytes=8-20", 10), vec![(8, 9)]);
+    assert_eq!(resolve("bytes=0-0,4-5", 10), vec![(0, 0), (4, 5)]);
+    assert!(resolve("bytes=10-", 10).is_empty());
+    assert!(resolve("bytes=0-", 0).is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn downloads_answer_ranges_and_conditionals() {
+    let app = TestApp::spawn().await;
+
+    app.upload(
+        "/api/v1/files/upload?path=",
+        &[TestFile {
+            field: "file0",
+            file_name: "digits.txt",
+            content_type: "text/plain",
+            body: b"0123456789",
+        }],
+    )
+    .await;
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let uri = format!(
+        "/api/v1/files/download/{}?path=",
+        listed["data"][0]["id"].as_str().unwrap()
+    );
+    let download = |headers: &[(&str, &str)]| {
+        let mut request = Request::get(&uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.request(request.body(Body::empty()).unwrap())
+    };
+
+    let response = download(&[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["last-modified"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = download(&[("Range", "bytes=2-4")]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["content-range"], "bytes 2-4/10");
+    assert_eq!(response.headers()["content-length"], "3");
+    assert_eq!(body_bytes(response).await.as_ref(), b"234");
+
+    let response = download(&[("Range", "bytes=-2")]).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"89");
+
+    let response = download(&[("Range", "bytes=0-1,8-")]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = response.headers()["content-type"].to_str().unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap()
+        .to_string();
+    let content_length: usize = response.headers()["content-length"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let body = body_bytes(response).await;
+    assert_eq!(body.len(), content_length);
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        format!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+             --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+             --{b}--\r\n",
+            b = boundary
+        )
+    );
+
+    let response = download(&[("Range", "bytes=20-")]).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()["content-range"], "bytes */10");
+
+    let response = download(&[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+    assert!(body_bytes(response).await.is_empty());
+
+    let response = download(&[("If-Modified-Since", &last_modified)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // * A mismatched etag wins over a matching date
+    let response = download(&[
+        ("If-None-Match", "\"stale\""),
+        ("If-Modified-Since", &last_modified),
+    ])
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = download(&[("Range", "bytes=0-1"), ("If-Range", "\"stale\"")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_bytes(response).await.as_ref(), b"0123456789");
+
+    let response = download(&[("Range", "bytes=0-1"), ("If-Range", &etag)]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn file_routes_require_a_session() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .router
+        .clone()
+        .oneshot(
+            Request::get("/api/v1/files/list")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,116 @@
+use axum::http::StatusCode;
+use serde_json::Value;
+
+use super::{TestApp, TestFile, body_json};
+use crate::{
+    enums::file_enums::{FileCategory, FileTypes},
+    utils::file_type_utils::{FileTypeEntry, FileTypeRegistry},
+};
+
+#[test]
+fn configured_types_extend_the_built_in_ones() {
+    let configured: Vec<FileTypeEntry> = serde_json::from_str(
+        r#"[
+            { "name": "epub", "extensions": ["epub"], "mimeTypes": ["application/epub+zip"], "category": "document" },
+            { "name": "svg", "category": "code" },
+            { "name": "markdown", "extensions": ["md"] }
+        ]"#,
+    )
+    .unwrap();
+    let registry = FileTypeRegistry::new(configured);
+
+    let epub = registry.by_mime("application/epub+zip").unwrap();
+    assert_eq!(epub.file_type(), FileTypes::Custom(String::from("epub")));
+    assert_eq!(epub.file_type().to_string(), "epub");
+    assert_eq!(epub.content_type(), "application/epub+zip");
+    assert!(registry.names_in(FileCategory::Document).contains(&"epub"));
+
+    // * Naming a known type changes it in place
+    assert_eq!(
+        registry.by_name("svg").unwrap().category,
+        Some(FileCategory::Code)
+    );
+    assert!(!registry.names_in(FileCategory::Image).contains(&"svg"));
+
+    assert_eq!(
+        registry.by_extension("MD").unwrap().file_type(),
+        FileTypes::Custom(String::from("markdown"))
+    );
+    assert_eq!(
+        registry.by_extension("yaml").unwrap().file_type(),
+        FileTypes::Yml
+    );
+    assert_eq!(
+        registry.by_extension("tif").unwrap().file_type(),
+        FileTypes::Tiff
+    );
+    assert_eq!(
+        registry.by_mime("text/markdown").unwrap().file_type(),
+        FileTypes::Md
+    );
+    assert!(registry.by_extension("unknown").is_none());
+}
+
+#[test]
+fn unknown_types_fall_back_to_other() {
+    assert_eq!(FileTypes::from_extension("JPG"), FileTypes::Jpg);
+    assert_eq!(FileTypes::from_mime("audio/x-wav"), FileTypes::Wav);
+    assert_eq!(
+        FileTypes::from_extension("xyz"),
+        FileTypes::Other(String::from("xyz"))
+    );
+    assert_eq!(FileTypes::Mdx.content_type(), "text/markdown");
+    assert_eq!(
+        FileTypes::Other(String::from("xyz")).content_type(),
+        "application/octet-stream"
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn listing_filters_by_category() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[
+                TestFile {
+                    field: "file0",
+                    file_name: "photo.png",
+                    content_type: "image/png",
+                    body: b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+                },
+                TestFile {
+                    field: "file1",
+                    file_name: "notes.txt",
+                    content_type: "text/plain",
+                    body: b"plain notes",
+                },
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let titles = |listed: Value| {
+        listed["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["title"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let listed = body_json(app.get("/api/v1/files/list?path=&category=image").await).await;
+    assert_eq!(titles(listed), vec!["photo.png"]);
+    let listed = body_json(app.get("/api/v1/files/list?path=&category=document").await).await;
+    assert_eq!(titles(listed), vec!["notes.txt"]);
+    let listed = body_json(app.get("/api/v1/files/list?path=&category=font").await).await;
+    assert!(titles(listed).is_empty());
+
+    let response = app
+        .get("/api/v1/files/list?path=&category=spreadsheet")
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,132 @@
+use std::io::Cursor;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header::CONTENT_TYPE},
+};
+use image::{ImageFormat, Rgb, RgbImage};
+use serde_json::{Value, json};
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+
+async fn save_preset(app: &TestApp, name: &str, transform: Value) -> StatusCode {
+    app.request(
+        Request::put(format!("/api/v1/users/image-presets/{}", name))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(transform.to_string()))
+            .unwrap(),
+    )
+    .await
+    .status()
+}
+
+/// Content type and dimensions of a transformed image.
+async fn transformed(app: &TestApp, id: &str, query: &str) -> (String, u32, u32) {
+    let response = app
+        .get(&format!("/api/v1/files/{}/image?{}", id, query))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let content_type = response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let image = image::load_from_memory(&body_bytes(response).await).unwrap();
+    (content_type, image.width(), image.height())
+}
+
+async fn cached_variants(app: &TestApp) -> usize {
+    app.state
+        .storage
+        .list_objects(Some("variants/"))
+        .await
+        .unwrap()
+        .len()
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn images_are_transformed_by_presets_only() {
+    let app = TestApp::spawn().await;
+
+    let mut png = vec![];
+    RgbImage::from_pixel(600, 300, Rgb([0, 128, 255]))
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name: "photo.png",
+                content_type: "image/png",
+                body: &png,
+            }],
+        )
+        .await;
+    let id = body_json(response).await["data"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let card =
+        json!({ "width": 200, "height": 200, "fit": "cover", "format": "jpeg", "quality": 70 });
+    assert_eq!(save_preset(&app, "card", card).await, StatusCode::OK);
+    assert_eq!(
+        save_preset(&app, "huge", json!({ "width": 100_000 })).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        save_preset(&app, "bad.name", json!({ "width": 10 })).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(
+        transformed(&app, &id, "preset=card").await,
+        (String::from("image/jpeg"), 200, 200)
+    );
+    // * The same transform spelled out is allowed and served from the cache
+    assert_eq!(
+        transformed(&app, &id, "w=200&h=200&fit=cover&format=jpeg&quality=70").await,
+        (String::from("image/jpeg"), 200, 200)
+    );
+    assert_eq!(cached_variants(&app).await, 1);
+
+    // * Transforms without a preset are refused
+    let response = app.get(&format!("/api/v1/files/{}/image?w=300", id)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        save_preset(&app, "wide", json!({ "width": 300 })).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        transformed(&app, &id, "w=300").await,
+        (String::from("image/webp"), 300, 150)
+    );
+    assert_eq!(cached_variants(&app).await, 2);
+
+    let presets = body_json(app.get("/api/v1/users/image-presets").await).await;
+    let names = presets["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|preset| preset["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["card", "wide"]);
+
+    // * Dropping a preset drops what was cached for it
+    let response = app
+        .request(
+            Request::delete("/api/v1/users/image-presets/card")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(cached_variants(&app).await, 1);
+    let response = app
+        .get(&format!("/api/v1/files/{}/image?preset=card", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
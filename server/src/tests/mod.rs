@@ -0,0 +1,290 @@
+//! Integration test harness. Storage and sessions run in memory; Postgres comes from
+//! `TEST_DATABASE_URL` and every `TestApp` gets its own schema. Tests that need the
+//! database are ignored by default; run them with `cargo test -- --ignored` once the variable
+//! is set.
+
+mod archive_tests;
+mod backend_tests;
+mod dedup_tests;
+mod direct_upload_tests;
+mod encryption_tests;
+mod exif_tests;
+mod file_tests;
+mod file_type_tests;
+mod image_tests;
+mod sniff_tests;
+mod thumbnail_tests;
+mod tree_tests;
+mod tus_tests;
+mod version_tests;
+
+use std::{env::var, fs, path::Path, sync::Arc, sync::Once};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    http::{
+        Request, Response, StatusCode,
+        header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+    },
+    response::IntoResponse,
+};
+use axum_extra::extract::{
+    PrivateCookieJar,
+    cookie::{Cookie, Key},
+};
+use deadpool_postgres::ManagerConfig;
+use http_body_util::BodyExt;
+use reqwest::Client;
+use serde_json::{Value, json};
+use tokio::sync::OnceCell;
+use tokio_postgres::NoTls;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    app_router,
+    cache::memory_cache::MemoryCache,
+    enums::server_enums::Environment,
+    models::{auth::Credentials, state::AppState},
+    storage::memory_storage::MemoryStorage,
+    utils::{
+        crypto_utils::{Keyring, MasterKey},
+        db_utils::db_init_setup,
+    },
+};
+
+const BOUNDARY: &str = "filestore-test-boundary";
+const TEST_PASSWORD: &str = "Test-Password-123";
+
+static ENV: Once = Once::new();
+static EXTENSIONS: OnceCell<()> = OnceCell::const_new();
+
+pub struct TestFile<'a> {
+    pub field: &'a str,
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub body: &'a [u8],
+}
+
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+    cookie: String,
+    schema: String,
+}
+
+impl TestApp {
+    pub async fn spawn() -> Self {
+        let db_url = var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL MUST POINT AT A POSTGRES DATABASE FOR THIS TEST");
+
+        ENV.call_once(|| {
+            // SAFETY: runs once, before any test reads the environment
+            unsafe { std::env::set_var("MAX_FILE_SIZE", "104857600") };
+        });
+
+        EXTENSIONS
+            .get_or_init(|| async {
+                let (client, connection) = tokio_postgres::connect(&db_url, NoTls)
+                    .await
+                    .expect("COULD NOT CONNECT TO TEST DATABASE");
+                tokio::spawn(connection);
+                client
+                    .batch_execute("CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;")
+                    .await
+                    .expect("COULD NOT CREATE pg_trgm EXTENSION");
+            })
+            .await;
+
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+        let mut cfg = deadpool_postgres::Config::new();
+        cfg.url = Some(db_url);
+        cfg.options = Some(format!("-c search_path={},public", schema));
+        cfg.manager = Some(ManagerConfig {
+            recycling_method: deadpool_postgres::RecyclingMethod::Fast,
+        });
+        let db_pool = cfg
+            .create_pool(Some(deadpool_postgres::Runtime::Tokio1), NoTls)
+            .unwrap();
+
+        let conn = db_pool.get().await.expect("COULD NOT GET TEST CONNECTION");
+        conn.batch_execute(&format!("CREATE SCHEMA {};", schema))
+            .await
+            .expect("COULD NOT CREATE TEST SCHEMA");
+        conn.batch_execute(&migrations())
+            .await
+            .expect("COULD NOT RUN MIGRATIONS");
+        drop(conn);
+
+        let state = AppState {
+            server_url: String::from("http://localhost:3000"),
+            db_pool,
+            rqw_client: Client::new(),
+            cache: Arc::new(MemoryCache::new()),
+            cookie_key: Key::generate(),
+            storage: Arc::new(MemoryStorage::new()),
+            keyring: Some(Arc::new(Keyring::new(
+                MasterKey::new(&rand::random()),
+                vec![],
+            ))),
+            cdn_endpoint: String::from(""),
+            root: String::from(""),
+            environment: Environment::Development,
+        };
+        db_init_setup(&state)
+            .await
+            .expect("COULD NOT SET UP TEST DATABASE");
+
+        let mut app = Self {
+            router: app_router(state.clone()),
+            state,
+            cookie: String::from(""),
+            schema,
+        };
+        app.cookie = app.login().await;
+
+        app
+    }
+
+    async fn login(&self) -> String {
+        let username = String::from("test-user");
+        let response = self
+            .request(
+                Request::post("/auth/register")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        json!({
+                            "email": "test-user@example.com",
+                            "password1": TEST_PASSWORD,
+                            "password2": TEST_PASSWORD,
+                            "username": username,
+                            "firstName": "Test",
+                            "lastName": "User",
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (session_id, _) = self
+            .state
+            .create_session(Credentials {
+                username,
+                password: String::from(TEST_PASSWORD),
+            })
+            .await
+            .expect("COULD NOT CREATE SESSION")
+            .expect("TEST USER WAS NOT AUTHENTICATED");
+
+        let jar = PrivateCookieJar::new(self.state.cookie_key.clone())
+            .add(Cookie::new("session_id", session_id));
+        let response = jar.into_response();
+        let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+
+        set_cookie.split(';').next().unwrap().to_string()
+    }
+
+    /// Sends a request through the full router, authenticated as the test user.
+    pub async fn request(&self, mut request: Request<Body>) -> Response<Body> {
+        if !self.cookie.is_empty() {
+            request
+                .headers_mut()
+                .insert(COOKIE, self.cookie.parse().unwrap());
+        }
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    pub async fn get(&self, uri: &str) -> Response<Body> {
+        self.request(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn upload(&self, uri: &str, files: &[TestFile<'_>]) -> Response<Body> {
+        let mut body = Vec::new();
+        for file in files {
+            // * An empty content type leaves the header out
+            let content_type = match file.content_type {
+                "" => String::new(),
+                content_type => format!("Content-Type: {}\r\n", content_type),
+            };
+            body.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n{}\r\n",
+                    file.field, file.file_name, content_type
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(file.body);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+
+        self.request(
+            Request::post(uri)
+                .header(
+                    CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={BOUNDARY}"),
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Turns on encryption at rest for the test user's new uploads.
+    pub async fn encrypt_files(&self) {
+        let response = self
+            .request(
+                Request::patch("/api/v1/users/settings")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(json!({ "encryptFiles": true }).to_string()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Swaps the master keys, as a restart with new configuration would.
+    pub fn set_keyring(&mut self, keyring: Keyring) {
+        self.state.keyring = Some(Arc::new(keyring));
+        self.router = app_router(self.state.clone());
+    }
+
+    pub async fn cleanup(self) {
+        let conn = self.state.get_db_conn().await.unwrap();
+        conn.batch_execute(&format!("DROP SCHEMA {} CASCADE;", self.schema))
+            .await
+            .unwrap();
+    }
+}
+
+pub async fn body_bytes(response: Response<Body>) -> Bytes {
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+pub async fn body_json(response: Response<Body>) -> Value {
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
+
+// * The `migrate:up` half of every dbmate migration, in order
+fn migrations() -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("db/migrations");
+    let mut files = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    files
+        .iter()
+        .map(|file| {
+            let sql = fs::read_to_string(file).unwrap();
+            let up = sql.split("-- migrate:down").next().unwrap_or_default();
+            up.replace("-- migrate:up", "")
+        })
+        .collect::<Vec<_>>()
+        .join("\n;\n")
+}
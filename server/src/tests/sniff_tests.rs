@@ -0,0 +1,215 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{Request, StatusCode, header::CONTENT_TYPE},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::{Value, json};
+
+use super::{TestApp, TestFile, body_json};
+use crate::{
+    enums::file_enums::FileTypes,
+    models::storage::PutOptions,
+    utils::sniff_utils::{Sniffed, VerifiedType, sniff, verify_type},
+};
+
+#[test]
+fn content_decides_over_claims_it_contradicts() {
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    assert_eq!(
+        verify_type(png, "photo.png", Some("image/png")),
+        VerifiedType {
+            file_type: FileTypes::Png,
+            mismatch: None,
+        }
+    );
+    assert_eq!(
+        verify_type(png, "photo.jpg", None),
+        VerifiedType {
+            file_type: FileTypes::Png,
+            mismatch: Some(String::from("jpg")),
+        }
+    );
+    // * Browsers guess content types from names, which the extension already covers
+    assert_eq!(
+        verify_type(png, "photo", Some("application/octet-stream")),
+        VerifiedType {
+            file_type: FileTypes::Png,
+            mismatch: None,
+        }
+    );
+    assert_eq!(
+        verify_type(b"MZ\x90\0", "invoice.pdf", Some("application/pdf")),
+        VerifiedType {
+            file_type: FileTypes::Exe,
+            mismatch: Some(String::from("pdf")),
+        }
+    );
+}
+
+#[test]
+fn containers_and_text_keep_the_claimed_type() {
+    let zip = b"PK\x03\x04\x14\0\0\0";
+    assert_eq!(
+        verify_type(zip, "report.docx", None).file_type,
+        FileTypes::Docx
+    );
+    assert_eq!(verify_type(zip, "report", None).file_type, FileTypes::Zip);
+
+    let json = "{\"name\": \"caf\u{e9}\"}".as_bytes();
+    assert_eq!(
+        verify_type(json, "data.json", Some("text/plain")),
+        VerifiedType {
+            file_type: FileTypes::Json,
+            mismatch: None,
+        }
+    );
+    // * A multi-byte character cut off at the end is still text
+    assert_eq!(sniff(&json[..json.len() - 3]), Sniffed::Text);
+    assert_eq!(
+        verify_type(b"plain words", "notes.png", None),
+        VerifiedType {
+            file_type: FileTypes::Txt,
+            mismatch: Some(String::from("png")),
+        }
+    );
+    assert_eq!(
+        verify_type(b"\0\x01\x02garbage", "notes.txt", None).mismatch,
+        Some(String::from("txt"))
+    );
+    assert_eq!(
+        verify_type(b"", "empty.png", None).file_type,
+        FileTypes::Png
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn uploads_are_stored_with_the_type_of_their_content() {
+    let app = TestApp::spawn().await;
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[
+                TestFile {
+                    field: "file0",
+                    file_name: "photo.jpg",
+                    content_type: "image/jpeg",
+                    body: png,
+                },
+                // * Parts without a content type go by their content and name
+                TestFile {
+                    field: "file1",
+                    file_name: "notes.md",
+                    content_type: "",
+                    body: b"# Notes",
+                },
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = body_json(response).await["data"].clone();
+    assert_eq!(results[0]["outcome"], "created");
+    assert_eq!(results[0]["type"], "png");
+    assert_eq!(results[0]["declaredType"], "jpg");
+    assert_eq!(results[1]["outcome"], "created");
+    assert_eq!(results[1]["type"], "md");
+    assert_eq!(results[1]["declaredType"], Value::Null);
+
+    let id = results[0]["id"].as_str().unwrap();
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+
+    app.cleanup().await;
+}
+
+/// The type recorded for the file `id`.
+async fn stored_type(app: &TestApp, id: &str) -> String {
+    let conn = app.state.get_db_conn().await.unwrap();
+    conn.query_one("SELECT type FROM files WHERE id::text = $1;", &[&id])
+        .await
+        .unwrap()
+        .get("type")
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_and_direct_uploads_are_stored_with_the_type_of_their_content() {
+    let app = TestApp::spawn().await;
+
+    let jpeg = b"\xff\xd8\xff\xe0\0\x10JFIF\0";
+    let response = app
+        .request(
+            Request::post("/api/v1/files/tus")
+                .header("Tus-Resumable", "1.0.0")
+                .header("Upload-Length", jpeg.len())
+                .header(
+                    "Upload-Metadata",
+                    format!(
+                        "filename {},filetype {}",
+                        STANDARD.encode("camera-roll"),
+                        STANDARD.encode("application/octet-stream")
+                    ),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+    let location = location.strip_prefix("http://localhost:3000").unwrap();
+    let response = app
+        .request(
+            Request::patch(location)
+                .header("Tus-Resumable", "1.0.0")
+                .header(CONTENT_TYPE, "application/offset+octet-stream")
+                .header("Upload-Offset", 0)
+                .body(Body::from(jpeg.to_vec()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let id = location.rsplit('/').next().unwrap();
+    assert_eq!(stored_type(&app, id).await, "jpeg");
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let response = app
+        .request(
+            Request::post("/api/v1/files/direct")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    json!({ "title": "scan.jpg", "contentType": "image/jpeg", "size": png.len() })
+                        .to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let id = body_json(response).await["data"]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    // * Stands in for the browser sending the presigned PUT
+    app.state
+        .storage
+        .put_object(
+            &format!("blobs/{}", id),
+            Bytes::from_static(png),
+            &PutOptions::default(),
+        )
+        .await
+        .unwrap();
+    let response = app
+        .request(
+            Request::post(format!("/api/v1/files/direct/{}/complete", id))
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from("{}"))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(stored_type(&app, &id).await, "png");
+
+    app.cleanup().await;
+}
@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use axum::http::{StatusCode, header::CONTENT_TYPE};
+use image::{ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+
+async fn upload(app: &TestApp, file_name: &str, content_type: &str, body: &[u8]) -> String {
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name,
+                content_type,
+                body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await["data"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+fn png(width: u32, height: u32, alpha: bool) -> Vec<u8> {
+    let mut body = vec![];
+    let mut cursor = Cursor::new(&mut body);
+    match alpha {
+        true => RgbaImage::from_pixel(width, height, Rgba([0, 128, 255, 100]))
+            .write_to(&mut cursor, ImageFormat::Png)
+            .unwrap(),
+        false => RgbImage::from_pixel(width, height, Rgb([0, 128, 255]))
+            .write_to(&mut cursor, ImageFormat::Png)
+            .unwrap(),
+    }
+    body
+}
+
+/// Content type and dimensions of a thumbnail.
+async fn thumbnail(app: &TestApp, id: &str, size: &str) -> (String, u32, u32) {
+    let response = app
+        .get(&format!("/api/v1/files/{}/thumbnail?size={}", id, size))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let content_type = response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let image = image::load_from_memory(&body_bytes(response).await).unwrap();
+    (content_type, image.width(), image.height())
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn images_get_thumbnails_of_their_current_version() {
+    let app = TestApp::spawn().await;
+
+    let id = upload(&app, "photo.png", "image/png", &png(600, 300, false)).await;
+    assert_eq!(
+        thumbnail(&app, &id, "small").await,
+        (String::from("image/jpeg"), 128, 64)
+    );
+    assert_eq!(
+        thumbnail(&app, &id, "large").await,
+        (String::from("image/jpeg"), 512, 256)
+    );
+
+    // * A new version replaces the thumbnails; transparency keeps them PNGs
+    let replaced = upload(&app, "photo.png", "image/png", &png(100, 400, true)).await;
+    assert_eq!(replaced, id);
+    assert_eq!(
+        thumbnail(&app, &id, "small").await,
+        (String::from("image/png"), 32, 128)
+    );
+    // * Small images are not scaled up
+    assert_eq!(
+        thumbnail(&app, &id, "large").await,
+        (String::from("image/png"), 100, 400)
+    );
+
+    // * Lost thumbnails are rendered again
+    app.state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .execute("DELETE FROM thumbnails;", &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        thumbnail(&app, &id, "medium").await,
+        (String::from("image/png"), 64, 256)
+    );
+
+    let notes = upload(&app, "notes.txt", "text/plain", b"no pixels").await;
+    let response = app.get(&format!("/api/v1/files/{}/thumbnail", notes)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
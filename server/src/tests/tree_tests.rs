@@ -0,0 +1,501 @@
+use axum::{
+    body::Body,
+    http::{Request, Response, StatusCode},
+};
+use serde_json::{Value, json};
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+use crate::{
+    models::storage::PutOptions,
+    utils::{blob_utils::flush_object_deletions, trash_utils::purge_trash},
+};
+
+async fn create_folder(app: &TestApp, path: &str, title: &str) -> String {
+    let response = app
+        .request(
+            Request::post(format!("/api/v1/files/create/folder?path={}", path))
+                .header("Content-Type", "application/json")
+                .body(Body::from(json!({ "title": title }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await["data"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn upload(app: &TestApp, path: &str, file_name: &str, body: &[u8]) -> String {
+    let response = app
+        .upload(
+            &format!("/api/v1/files/upload?path={}", path),
+            &[TestFile {
+                field: "file0",
+                file_name,
+                content_type: "text/plain",
+                body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get(&format!("/api/v1/files/list?path={}", path)).await).await;
+    let file = listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["title"] == file_name)
+        .unwrap();
+    file["id"].as_str().unwrap().to_string()
+}
+
+async fn titles(app: &TestApp, path: &str) -> Vec<String> {
+    let listed = body_json(app.get(&format!("/api/v1/files/list?path={}", path)).await).await;
+    let mut titles = listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["title"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    titles.sort();
+    titles
+}
+
+async fn trash_titles(app: &TestApp) -> Vec<String> {
+    let listed = body_json(app.get("/api/v1/files/trash").await).await;
+    listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["title"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn delete(app: &TestApp, uri: &str) -> Response<Body> {
+    app.request(Request::delete(uri).body(Body::empty()).unwrap())
+        .await
+}
+
+async fn restore(app: &TestApp, id: &str) -> Response<Body> {
+    app.request(
+        Request::post(format!("/api/v1/files/trash/{}/restore", id))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+}
+
+async fn move_file(app: &TestApp, id: &str, payload: Value) -> Response<Body> {
+    app.request(
+        Request::patch(format!("/api/v1/files/{}", id))
+            .header("Content-Type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap(),
+    )
+    .await
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn folders_move_with_their_subtree() {
+    let app = TestApp::spawn().await;
+
+    let docs = create_folder(&app, "", "docs").await;
+    create_folder(&app, "docs", "sub").await;
+    let a = upload(&app, "docs", "a.txt", b"a").await;
+    let b = upload(&app, "docs/sub", "b.txt", b"b").await;
+    assert_eq!(titles(&app, "docs").await, vec!["a.txt", "sub"]);
+
+    let response = move_file(&app, &docs, json!({ "title": "notes" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "").await, vec!["notes"]);
+    assert_eq!(titles(&app, "notes").await, vec!["a.txt", "sub"]);
+    assert_eq!(titles(&app, "notes/sub").await, vec!["b.txt"]);
+    assert!(titles(&app, "docs").await.is_empty());
+    let response = app.get(&format!("/api/v1/files/download/{}", b)).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"b");
+
+    let response = move_file(&app, &a, json!({ "path": "notes/sub", "title": "c.txt" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "notes/sub").await, vec!["b.txt", "c.txt"]);
+
+    let response = move_file(&app, &docs, json!({ "path": "notes/sub" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    for payload in [
+        json!({ "title": ".." }),
+        json!({ "path": "notes/../..", "title": "a.txt" }),
+    ] {
+        let response = move_file(&app, &b, payload).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // * Folders missing on the way to the destination are created
+    let response = move_file(&app, &b, json!({ "path": "notes/later/on" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "notes").await, vec!["later", "sub"]);
+    assert_eq!(titles(&app, "notes/later/on").await, vec!["b.txt"]);
+    let response = move_file(&app, &b, json!({ "path": "notes/sub" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // * A taken name leaves everything where it was
+    let response = move_file(&app, &b, json!({ "title": "c.txt" })).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(titles(&app, "notes/sub").await, vec!["b.txt", "c.txt"]);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn moving_a_folder_rekeys_path_keyed_objects() {
+    let app = TestApp::spawn().await;
+
+    let docs = create_folder(&app, "", "docs").await;
+    // * A file written before objects were keyed by id
+    let legacy: String = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one(
+            "INSERT INTO files (title, owner_id, size, type, path)
+            SELECT 'old.txt', id, 3, 'txt', 'docs/' FROM users
+            RETURNING id::text;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    app.state
+        .storage
+        .put_object(
+            "docs/old.txt",
+            b"old".to_vec().into(),
+            &PutOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let response = move_file(&app, &docs, json!({ "title": "archive" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.get(&format!("/api/v1/files/download/{}", legacy)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_bytes(response).await.as_ref(), b"old");
+    let keys = app
+        .state
+        .storage
+        .list_objects(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|object| object.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec![format!("blobs/{}", legacy)]);
+
+    app.cleanup().await;
+}
+
+async fn copy_file(app: &TestApp, id: &str, payload: Value) -> Value {
+    let response = app
+        .request(
+            Request::post(format!("/api/v1/files/{}/copy", id))
+                .header("Content-Type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    body_json(response).await
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn folder_copies_share_blobs_and_report_each_item() {
+    let app = TestApp::spawn().await;
+
+    let docs = create_folder(&app, "", "docs").await;
+    create_folder(&app, "docs", "sub").await;
+    let a = upload(&app, "docs", "a.txt", b"a").await;
+    let b = upload(&app, "docs/sub", "b.txt", b"b").await;
+
+    let copied = copy_file(&app, &docs, json!({ "path": "", "title": "template" })).await;
+    let statuses = copied["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            (
+                format!(
+                    "{}{}",
+                    item["path"].as_str().unwrap(),
+                    item["title"].as_str().unwrap()
+                ),
+                item["status"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            (String::from("template"), String::from("copied")),
+            (String::from("template/a.txt"), String::from("copied")),
+            (String::from("template/sub"), String::from("copied")),
+            (String::from("template/sub/b.txt"), String::from("copied")),
+        ]
+    );
+    assert_eq!(titles(&app, "template").await, vec!["a.txt", "sub"]);
+    assert_eq!(titles(&app, "template/sub").await, vec!["b.txt"]);
+
+    // * No content was duplicated, and copies outlive their source
+    let count: i64 = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT COUNT(*) FROM blobs WHERE ref_count = 2", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(count, 2);
+    app.request(
+        Request::delete(format!("/api/v1/files/delete/{}", a))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let copy_of_a = copied["data"][1]["id"].as_str().unwrap();
+    let response = app
+        .get(&format!("/api/v1/files/download/{}", copy_of_a))
+        .await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"a");
+
+    let copied = copy_file(&app, &docs, json!({ "path": "", "title": "template" })).await;
+    let statuses = copied["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["status"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, vec!["exists", "exists", "exists"]);
+
+    // * Folders missing on the way to the destination are created
+    let copied = copy_file(&app, &b, json!({ "path": "backup/2024" })).await;
+    assert_eq!(copied["data"][0]["status"], "copied");
+    assert_eq!(titles(&app, "backup").await, vec!["2024"]);
+    assert_eq!(titles(&app, "backup/2024").await, vec!["b.txt"]);
+
+    for payload in [
+        json!({ "path": "", "title": "." }),
+        json!({ "path": "backup/../..", "title": "b.txt" }),
+    ] {
+        let response = app
+            .request(
+                Request::post(format!("/api/v1/files/{}/copy", b))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(payload.to_string()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn deleting_a_folder_removes_its_tree() {
+    let app = TestApp::spawn().await;
+
+    let docs = create_folder(&app, "", "docs").await;
+    create_folder(&app, "docs", "sub").await;
+    upload(&app, "docs", "a.txt", b"a").await;
+    upload(&app, "docs/sub", "b.txt", b"b").await;
+    // * A sibling sharing the name prefix is not part of the tree
+    upload(&app, "docs2", "c.txt", b"c").await;
+
+    let response = delete(&app, &format!("/api/v1/files/delete/{}", docs)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "").await, vec!["docs2"]);
+    assert!(titles(&app, "docs").await.is_empty());
+    assert!(titles(&app, "docs/sub").await.is_empty());
+    assert_eq!(titles(&app, "docs2").await, vec!["c.txt"]);
+    assert_eq!(trash_titles(&app).await, vec!["docs"]);
+    assert_eq!(app.state.storage.list_objects(None).await.unwrap().len(), 3);
+
+    let response = delete(&app, &format!("/api/v1/files/trash/{}", docs)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(trash_titles(&app).await.is_empty());
+
+    let objects = app.state.storage.list_objects(None).await.unwrap();
+    assert_eq!(objects.len(), 1);
+    let queued: i64 = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT COUNT(*) FROM object_deletions", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(queued, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn trashed_items_are_restored_or_purged() {
+    let app = TestApp::spawn().await;
+
+    let docs = create_folder(&app, "", "docs").await;
+    create_folder(&app, "docs", "sub").await;
+    let a = upload(&app, "docs", "a.txt", b"a").await;
+    upload(&app, "docs/sub", "b.txt", b"b").await;
+    delete(&app, &format!("/api/v1/files/delete/{}", a)).await;
+    delete(&app, &format!("/api/v1/files/delete/{}", docs)).await;
+    assert_eq!(trash_titles(&app).await, vec!["docs", "a.txt"]);
+
+    // * Trashed names are free, so restoring over a new folder conflicts
+    let new_docs = create_folder(&app, "", "docs").await;
+    let response = restore(&app, &docs).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    delete(&app, &format!("/api/v1/files/delete/{}", new_docs)).await;
+
+    // * The folder comes back with what was trashed along with it, but not a.txt
+    let response = restore(&app, &docs).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "docs").await, vec!["sub"]);
+    assert_eq!(titles(&app, "docs/sub").await, vec!["b.txt"]);
+
+    // * Restoring into a trashed folder recreates it
+    delete(&app, &format!("/api/v1/files/delete/{}", docs)).await;
+    let response = restore(&app, &a).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(titles(&app, "").await, vec!["docs"]);
+    assert_eq!(titles(&app, "docs").await, vec!["a.txt"]);
+
+    let mut conn = app.state.db_pool.get().await.unwrap();
+    conn.execute(
+        "UPDATE files SET deleted_at = deleted_at - INTERVAL '31 days' WHERE deleted_at IS NOT NULL",
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(purge_trash(&mut conn).await.unwrap(), 4);
+    flush_object_deletions(&app.state, &mut conn).await.unwrap();
+    drop(conn);
+    assert!(trash_titles(&app).await.is_empty());
+    assert_eq!(titles(&app, "docs").await, vec!["a.txt"]);
+    assert_eq!(app.state.storage.list_objects(None).await.unwrap().len(), 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn queued_deletions_are_retried() {
+    let app = TestApp::spawn().await;
+
+    // * What a failed flush leaves behind
+    app.state
+        .storage
+        .put_object(
+            "blobs/orphan",
+            b"orphan".to_vec().into(),
+            &PutOptions::default(),
+        )
+        .await
+        .unwrap();
+    let mut conn = app.state.db_pool.get().await.unwrap();
+    conn.execute(
+        "INSERT INTO object_deletions (key) VALUES ('blobs/orphan')",
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        flush_object_deletions(&app.state, &mut conn).await.unwrap(),
+        1
+    );
+    assert!(
+        app.state
+            .storage
+            .list_objects(None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        flush_object_deletions(&app.state, &mut conn).await.unwrap(),
+        0
+    );
+
+    drop(conn);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn directory_uploads_create_their_folders() {
+    let app = TestApp::spawn().await;
+
+    let file = |file_name, body| TestFile {
+        field: "file0",
+        file_name,
+        content_type: "text/plain",
+        body,
+    };
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=docs",
+            &[
+                file("photos/2024/a.txt", b"a"),
+                file("photos/b.txt", b"b"),
+                file("../escape.txt", b"x"),
+                file("c.txt", b"c"),
+                file("c.txt/d.txt", b"d"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let uploaded = body_json(response).await;
+    let results = uploaded["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["outcome"].as_str().unwrap(),
+                result["path"]
+                    .as_str()
+                    .or(result["error"].as_str())
+                    .unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        vec![
+            ("created", "docs/photos/2024/a.txt"),
+            ("created", "docs/photos/b.txt"),
+            ("failed", "invalid_name"),
+            ("created", "docs/c.txt"),
+            ("failed", "path_conflict"),
+        ]
+    );
+
+    assert_eq!(titles(&app, "").await, vec!["docs"]);
+    assert_eq!(titles(&app, "docs").await, vec!["c.txt", "photos"]);
+    assert_eq!(titles(&app, "docs/photos").await, vec!["2024", "b.txt"]);
+    assert_eq!(titles(&app, "docs/photos/2024").await, vec!["a.txt"]);
+    assert_eq!(app.state.storage.list_objects(None).await.unwrap().len(), 3);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,293 @@
+use axum::{
+    body::Body,
+    http::{Request, Response, StatusCode},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use super::{TestApp, body_bytes, body_json};
+use crate::utils::tus_utils::parse_metadata;
+
+fn metadata(file_name: &str, file_type: &str) -> String {
+    format!(
+        "filename {},filetype {}",
+        STANDARD.encode(file_name),
+        STANDARD.encode(file_type)
+    )
+}
+
+async fn create(app: &TestApp, length: usize, metadata: &str) -> Response<Body> {
+    app.request(
+        Request::post("/api/v1/files/tus")
+            .header("Tus-Resumable", "1.0.0")
+            .header("Upload-Length", length)
+            .header("Upload-Metadata", metadata)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+}
+
+async fn patch(app: &TestApp, location: &str, offset: usize, chunk: &[u8]) -> Response<Body> {
+    app.request(
+        Request::patch(location)
+            .header("Tus-Resumable", "1.0.0")
+            .header("Content-Type", "application/offset+octet-stream")
+            .header("Upload-Offset", offset)
+            .body(Body::from(chunk.to_vec()))
+            .unwrap(),
+    )
+    .await
+}
+
+async fn status(app: &TestApp, location: &str) -> Response<Body> {
+    app.request(
+        Request::head(location)
+            .header("Tus-Resumable", "1.0.0")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+}
+
+fn location(response: &Response<Body>) -> String {
+    let location = response.headers()["location"].to_str().unwrap();
+    location
+        .strip_prefix("http://localhost:3000")
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn upload_metadata_is_parsed() {
+    let parsed = parse_metadata("filename bm90ZXMudHh0,is_public").unwrap();
+    assert_eq!(parsed["filename"], "notes.txt");
+    assert_eq!(parsed["is_public"], "");
+
+    assert!(parse_metadata("filename not-base64!").is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_upload_resumes_across_patches() {
+    let app = TestApp::spawn().await;
+
+    let body = (0..9 * 1024 * 1024 + 123)
+        .map(|idx| (idx % 251) as u8)
+        .collect::<Vec<_>>();
+    let response = create(
+        &app,
+        body.len(),
+        &metadata("video.bin", "application/octet-stream"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["tus-resumable"], "1.0.0");
+    let location = location(&response);
+
+    let mut offset = 0;
+    for chunk in body.chunks(3 * 1024 * 1024) {
+        let response = patch(&app, &location, offset, chunk).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        offset += chunk.len();
+        assert_eq!(response.headers()["upload-offset"], offset.to_string());
+
+        let response = status(&app, &location).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["upload-offset"], offset.to_string());
+        assert_eq!(response.headers()["upload-length"], body.len().to_string());
+    }
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let files = listed["data"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["title"], "video.bin");
+    assert_eq!(files[0]["size"], body.len());
+    let id = files[0]["id"].as_str().unwrap().to_string();
+    assert!(location.ends_with(&id));
+
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    // * Only the finished file is left in storage
+    let keys = app
+        .state
+        .storage
+        .list_objects(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|object| object.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec![format!("blobs/{}", id)]);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_rejects_mismatched_offsets_and_versions() {
+    let app = TestApp::spawn().await;
+
+    let response = create(&app, 10, &metadata("notes.txt", "text/plain")).await;
+    let location = location(&response);
+
+    let response = patch(&app, &location, 4, b"hello").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = patch(&app, &location, 0, b"hello world!").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .request(
+            Request::head(&location)
+                .header("Tus-Resumable", "0.2.2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(response.headers()["tus-version"], "1.0.0");
+
+    let response = create(&app, 104857600 + 1, &metadata("big.bin", "")).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = create(&app, 10, "filetype dGV4dC9wbGFpbg==").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_upload_names_are_checked_and_folders_created() {
+    let app = TestApp::spawn().await;
+
+    let with_path = |file_name: &str, path: &str| {
+        format!(
+            "{},path {}",
+            metadata(file_name, "text/plain"),
+            STANDARD.encode(path)
+        )
+    };
+    for metadata in [
+        metadata("../escape.txt", "text/plain"),
+        with_path("notes.txt", "docs/../.."),
+        with_path(".", "docs"),
+    ] {
+        let response = create(&app, 5, &metadata).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let response = create(&app, 5, &with_path("2024/notes.txt", "docs")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = patch(&app, &location(&response), 0, b"hello").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let listed = body_json(app.get("/api/v1/files/list?path=docs/2024").await).await;
+    assert_eq!(listed["data"][0]["title"], "notes.txt");
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    assert_eq!(listed["data"][0]["title"], "docs");
+    assert_eq!(listed["data"][0]["type"], "folder");
+
+    // * A file in the place of a folder refuses the upload and nothing is kept
+    let response = create(&app, 5, &with_path("a.txt", "docs/2024/notes.txt")).await;
+    let response = patch(&app, &location(&response), 0, b"hello").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let keys = app.state.storage.list_objects(None).await.unwrap();
+    assert_eq!(keys.len(), 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_termination_discards_the_upload() {
+    let app = TestApp::spawn().await;
+
+    let response = create(&app, 10, &metadata("notes.txt", "text/plain")).await;
+    let location = location(&response);
+    let response = patch(&app, &location, 0, b"hello").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(
+        !app.state
+            .storage
+            .list_objects(None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let response = app
+        .request(
+            Request::delete(&location)
+                .header("Tus-Resumable", "1.0.0")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    assert_eq!(
+        status(&app, &location).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert!(
+        app.state
+            .storage
+            .list_objects(None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    assert!(listed["data"].as_array().unwrap().is_empty());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn encrypted_tus_upload_spans_parts() {
+    let app = TestApp::spawn().await;
+    app.encrypt_files().await;
+
+    let body = (0..9 * 1024 * 1024 + 123)
+        .map(|idx| (idx % 251) as u8)
+        .collect::<Vec<_>>();
+    let response = create(
+        &app,
+        body.len(),
+        &metadata("video.bin", "application/octet-stream"),
+    )
+    .await;
+    let location = location(&response);
+
+    let mut offset = 0;
+    for chunk in body.chunks(3 * 1024 * 1024 + 7) {
+        let response = patch(&app, &location, offset, chunk).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        offset += chunk.len();
+    }
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let id = listed["data"][0]["id"].as_str().unwrap().to_string();
+    let response = app
+        .get(&format!("/api/v1/files/download/{}?path=", id))
+        .await;
+    assert!(body_bytes(response).await.as_ref() == body.as_slice());
+
+    let hash: String = app
+        .state
+        .db_pool
+        .get()
+        .await
+        .unwrap()
+        .query_one("SELECT hash FROM files WHERE id::text = $1", &[&id])
+        .await
+        .unwrap()
+        .get("hash");
+    assert_eq!(hash, blake3::hash(&body).to_string());
+
+    app.cleanup().await;
+}
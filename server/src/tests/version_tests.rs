@@ -0,0 +1,110 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use serde_json::json;
+
+use super::{TestApp, TestFile, body_bytes, body_json};
+
+async fn upload(app: &TestApp, body: &[u8]) -> String {
+    let response = app
+        .upload(
+            "/api/v1/files/upload?path=",
+            &[TestFile {
+                field: "file0",
+                file_name: "notes.txt",
+                content_type: "text/plain",
+                body,
+            }],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let listed = body_json(app.get("/api/v1/files/list?path=").await).await;
+    let files = listed["data"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    files[0]["id"].as_str().unwrap().to_string()
+}
+
+async fn versions(app: &TestApp, id: &str) -> Vec<(i64, bool)> {
+    let listed = body_json(app.get(&format!("/api/v1/files/{}/versions", id)).await).await;
+    listed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|version| {
+            (
+                version["version"].as_i64().unwrap(),
+                version["isCurrent"].as_bool().unwrap(),
+            )
+        })
+        .collect()
+}
+
+async fn stored_objects(app: &TestApp) -> usize {
+    app.state.storage.list_objects(None).await.unwrap().len()
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn reuploads_add_versions_up_to_the_limit() {
+    let app = TestApp::spawn().await;
+
+    let id = upload(&app, b"first").await;
+    assert_eq!(upload(&app, b"second").await, id);
+    // * Identical content is not a new version
+    upload(&app, b"second").await;
+    assert_eq!(versions(&app, &id).await, vec![(2, true), (1, false)]);
+
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"second");
+    let response = app.get(&format!("/api/v1/files/{}/versions/1", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_bytes(response).await.as_ref(), b"first");
+    let response = app.get(&format!("/api/v1/files/{}/versions/7", id)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .request(
+            Request::patch("/api/v1/users/settings")
+                .header("Content-Type", "application/json")
+                .body(Body::from(json!({ "maxFileVersions": 2 }).to_string()))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let settings = body_json(response).await;
+    assert_eq!(settings["data"]["encryptFiles"], false);
+    assert_eq!(settings["data"]["maxFileVersions"], 2);
+
+    upload(&app, b"third").await;
+    assert_eq!(versions(&app, &id).await, vec![(3, true), (2, false)]);
+    assert_eq!(stored_objects(&app).await, 2);
+
+    // * Restoring makes a new version, sharing the restored content's blob
+    let response = app
+        .request(
+            Request::post(format!("/api/v1/files/{}/versions/2/restore", id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(versions(&app, &id).await, vec![(4, true), (3, false)]);
+    let response = app.get(&format!("/api/v1/files/download/{}", id)).await;
+    assert_eq!(body_bytes(response).await.as_ref(), b"second");
+    assert_eq!(stored_objects(&app).await, 2);
+
+    for uri in [
+        format!("/api/v1/files/delete/{}", id),
+        format!("/api/v1/files/trash/{}", id),
+    ] {
+        let response = app
+            .request(Request::delete(uri).body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert_eq!(stored_objects(&app).await, 0);
+
+    app.cleanup().await;
+}
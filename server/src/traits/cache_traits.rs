@@ -0,0 +1,20 @@
+use async_trait::async_trait;
+
+use crate::enums::errors::CacheError;
+
+/// Key-value store backing sessions and short-lived auth state.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>, CacheError>;
+
+    async fn set_ex(&self, key: &str, value: &str, seconds: u64) -> Result<(), CacheError>;
+
+    /// Sets the key only if it does not exist yet. Returns whether the value was written.
+    async fn set_nx_ex(&self, key: &str, value: &str, seconds: u64) -> Result<bool, CacheError>;
+
+    async fn del(&self, key: &str) -> Result<(), CacheError>;
+
+    async fn sadd(&self, key: &str, member: &str) -> Result<(), CacheError>;
+
+    async fn smembers(&self, key: &str) -> Result<Vec<String>, CacheError>;
+}
@@ -79,7 +79,7 @@ impl SerializeToJson for Row {
                 }
                 &Type::BOOL => {
                     let bool_value: Option<bool> = self.get(col_idx);
-                    bool_value.map_or(Value::Null, |b| Value::Bool(b.into()))
+                    bool_value.map_or(Value::Null, Value::Bool)
                 }
                 &Type::JSON | &Type::JSONB => {
                     let json_value: Option<Value> = self.get(col_idx);
@@ -104,6 +104,6 @@ impl SerializeList for Vec<Row> {
 
         let values = json!(values.to_vec());
 
-        return values;
+        values
     }
 }
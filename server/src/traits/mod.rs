@@ -1 +1,3 @@
+pub mod cache_traits;
 pub mod db_traits;
+pub mod storage_traits;
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use crate::{
+    enums::errors::StorageError,
+    models::storage::{ObjectMeta, PresignedRequest, PutOptions, StorageObject, UploadedPart},
+};
+
+/// Object storage used for file contents. Keys are `/` separated and never start with a `/`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put_object(
+        &self,
+        key: &str,
+        body: Bytes,
+        options: &PutOptions,
+    ) -> Result<ObjectMeta, StorageError>;
+
+    async fn get_object(&self, key: &str) -> Result<StorageObject, StorageError>;
+
+    /// Reads bytes `start..=end` of an object. `meta` still describes the whole object.
+    async fn get_object_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<StorageObject, StorageError>;
+
+    async fn head_object(&self, key: &str) -> Result<ObjectMeta, StorageError>;
+
+    /// Deleting a key that does not exist is not an error.
+    async fn delete_object(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Deletes many keys at once. Missing keys are not an error, and a failed call can be
+    /// repeated with the same keys.
+    async fn delete_objects(&self, keys: &[String]) -> Result<(), StorageError>;
+
+    async fn list_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMeta>, StorageError>;
+
+    /// Copies an object; `is_public` sets the visibility of the copy, not the source's.
+    async fn copy_object(
+        &self,
+        from: &str,
+        to: &str,
+        is_public: bool,
+    ) -> Result<ObjectMeta, StorageError>;
+
+    /// Starts a multipart upload and returns its upload id.
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        options: &PutOptions,
+    ) -> Result<String, StorageError>;
+
+    /// Part numbers start at 1. Every part except the last must be at least 5 MiB on S3.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: Bytes,
+    ) -> Result<UploadedPart, StorageError>;
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<ObjectMeta, StorageError>;
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError>;
+
+    /// Presigned PUT that lets a client upload an object without going through the API.
+    async fn presign_put(
+        &self,
+        key: &str,
+        options: &PutOptions,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError>;
+
+    /// Presigned PUT for one part of an upload started with `create_multipart_upload`.
+    /// The client reports back the `ETag` of each part for `complete_multipart_upload`.
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, StorageError>;
+
+    /// Shareable URL for an object, either a CDN link or a presigned GET.
+    async fn generate_link(&self, key: &str, expires_in: Duration) -> Result<String, StorageError>;
+}
@@ -0,0 +1,553 @@
+use std::collections::HashSet;
+
+use blake3::Hash;
+use deadpool_postgres::{GenericClient, Object};
+use tokio_postgres::{Error as DBError, Row};
+use uuid::Uuid;
+
+use crate::{
+    consts::{DELETE_BATCH_SIZE, KEY_MIGRATION_BATCH, MAX_INSERT_ATTEMPTS},
+    enums::{
+        errors::{AppError, StorageError},
+        file_enums::UploadOutcome,
+        request_enums::ConflictPolicy,
+    },
+    models::{
+        file::{BlobEncryption, InsertedFile, NewFile, StoredObject},
+        response::AppErrorResponse,
+        state::AppState,
+    },
+    routes::file_routes::FileQuery,
+    utils::{
+        file_utils::hash_object,
+        format_utils::{numbered_title, title_stem},
+        thumbnail_utils::spawn_image_processing,
+        tree_utils::create_parent_folders,
+    },
+};
+
+// * Blob objects live under this folder of the storage root
+const BLOBS_FOLDER: &str = "blobs";
+
+/// What `DELETE FROM files` returns for `release_deleted_files`. Versions, thumbnails and
+/// transformed images go with their file through the foreign key, so they are read before the
+/// delete removes them.
+pub const DELETED_FILE_COLUMNS: &str = "path, title, type, blob_hash,
+    ARRAY(SELECT blob_hash FROM file_versions WHERE file_id = files.id) AS version_hashes,
+    ARRAY(SELECT key FROM thumbnails WHERE file_id = files.id
+        UNION ALL SELECT key FROM image_variants WHERE file_id = files.id) AS rendering_keys";
+
+/// Key for new content. Its hash is only known once the upload is done, so every upload gets
+/// its own key and `insert_file` decides whether it becomes a blob or a discarded duplicate.
+pub fn new_blob_key(root: &str, id: &Uuid) -> String {
+    let folder = FileQuery {
+        path: String::from(BLOBS_FOLDER),
+        is_public: false,
+        is_folder: true,
+    };
+    format!("{}{}", folder.format_path(root), id)
+}
+
+/// Key of the blob holding content with this hash. Encrypted and public blobs are tracked
+/// under derived hashes, so plaintext and encrypted copies of the same bytes are never shared,
+/// and neither are public and private ones: visibility is the ACL set on the object itself.
+pub fn blob_hash(hash: &Hash, is_encrypted: bool, is_public: bool) -> String {
+    let context = match (is_encrypted, is_public) {
+        (false, false) => return hash.to_string(),
+        (true, false) => "filestore encrypted blob",
+        (false, true) => "filestore public blob",
+        (true, true) => "filestore encrypted public blob",
+    };
+    Hash::from_bytes(blake3::derive_key(context, hash.as_bytes())).to_string()
+}
+
+/// Key of a file stored before objects were keyed by id, from its `path, title` columns.
+pub fn legacy_key(row: &Row) -> String {
+    format!(
+        "{}{}",
+        row.get::<_, String>("path"),
+        row.get::<_, String>("title")
+    )
+}
+
+/// Envelope of an encrypted blob from a row selecting `blobs.key_id, blobs.data_key, blobs.nonce`.
+pub fn blob_encryption(row: &Row) -> Option<BlobEncryption> {
+    Some(BlobEncryption {
+        key_id: row.get::<_, Option<String>>("key_id")?,
+        data_key: row.get("data_key"),
+        nonce: row.get("nonce"),
+    })
+}
+
+/// Records an uploaded object as a file pointing at the blob for its hash. When the name is
+/// taken, `conflict` decides between skipping, adding a version to the existing file, or
+/// inserting under a numbered name. When the content was already stored, or nothing was
+/// added, `is_stored` is false and the caller deletes the object once the transaction
+/// commits. `ref_count` is kept by the `files_blob_ref_count` and
+/// `file_versions_blob_ref_count` triggers. A name that is still contested after
+/// `MAX_INSERT_ATTEMPTS` fails with a unique violation.
+pub async fn insert_file(
+    client: &impl GenericClient,
+    file: &NewFile,
+    conflict: ConflictPolicy,
+) -> Result<InsertedFile, DBError> {
+    let encryption = file.encryption.as_ref();
+    let blob_hash = blob_hash(&file.hash, encryption.is_some(), file.is_public);
+    // * Touching an existing blob locks it, so a concurrent release cannot delete it before
+    // * the file refers to it; only a new blob has the key of this upload
+    let is_new_blob: bool = client
+        .query_one(
+            "INSERT INTO blobs (hash, key, size, etag, key_id, data_key, nonce)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (hash) DO UPDATE SET hash = EXCLUDED.hash
+            RETURNING key = $2 AS is_new;",
+            &[
+                &blob_hash,
+                &file.key,
+                &file.size,
+                &file.etag,
+                &encryption.map(|encryption| &encryption.key_id),
+                &encryption.map(|encryption| &encryption.data_key),
+                &encryption.map(|encryption| &encryption.nonce),
+            ],
+        )
+        .await?
+        .get("is_new");
+
+    let mut title = file.title.clone();
+    let mut attempts = 0;
+    let (id, outcome) = loop {
+        attempts += 1;
+        // * The last attempt lets a name that keeps changing hands fail
+        let on_conflict = match attempts < MAX_INSERT_ATTEMPTS {
+            true => "ON CONFLICT (path, title, owner_id) WHERE deleted_at IS NULL DO NOTHING",
+            false => "",
+        };
+        let inserted = client
+            .execute(
+                &format!(
+                    "INSERT INTO files (id, title, owner_id, size, type, path, is_public, hash, etag, blob_hash)
+                    SELECT $1, $2, $3, $4, $5, $6, $7, $8, blobs.etag, blobs.hash
+                    FROM blobs WHERE blobs.hash = $9
+                    {};",
+                    on_conflict
+                ),
+                &[
+                    &file.id,
+                    &title,
+                    &file.owner_id,
+                    &file.size,
+                    &file.file_type.to_string(),
+                    &file.path,
+                    &file.is_public,
+                    &file.hash.to_string(),
+                    &blob_hash,
+                ],
+            )
+            .await?
+            == 1;
+        if inserted {
+            let outcome = match title == file.title {
+                true => UploadOutcome::Created,
+                false => UploadOutcome::Renamed,
+            };
+            break (file.id, outcome);
+        }
+
+        // * The row in the way may be gone by now, then the insert is simply retried
+        let Some(existing) = client
+            .query_opt(
+                "SELECT id FROM files
+                WHERE owner_id = $1 AND path = $2 AND title = $3 AND deleted_at IS NULL;",
+                &[&file.owner_id, &file.path, &title],
+            )
+            .await?
+        else {
+            continue;
+        };
+        let existing_id: Uuid = existing.get("id");
+        match conflict {
+            ConflictPolicy::Skip => break (existing_id, UploadOutcome::Skipped),
+            ConflictPolicy::Replace => {
+                let hash = file.hash.to_string();
+                let outcome =
+                    match add_version(client, &existing_id, &blob_hash, &hash, file.size).await? {
+                        true => UploadOutcome::Replaced,
+                        false => UploadOutcome::Skipped,
+                    };
+                break (existing_id, outcome);
+            }
+            ConflictPolicy::Rename => title = free_title(client, file).await?,
+        }
+    };
+
+    let is_added = outcome != UploadOutcome::Skipped;
+    if is_new_blob && !is_added {
+        release_blobs(client, &[blob_hash]).await?;
+    }
+    Ok(InsertedFile {
+        id,
+        title,
+        outcome,
+        is_stored: is_new_blob && is_added,
+    })
+}
+
+/// First numbered variant of `file.title` that is free in its folder.
+async fn free_title(client: &impl GenericClient, file: &NewFile) -> Result<String, DBError> {
+    let taken = client
+        .query(
+            "SELECT title FROM files
+            WHERE owner_id = $1 AND path = $2 AND deleted_at IS NULL AND starts_with(title, $3);",
+            &[&file.owner_id, &file.path, &title_stem(&file.title)],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("title"))
+        .collect::<HashSet<String>>();
+
+    Ok((1..)
+        .map(|number| numbered_title(&file.title, number))
+        .find(|title| !taken.contains(title))
+        .unwrap_or_default())
+}
+
+/// Makes the blob `blob_hash` the current content of a file. The content it replaces is kept
+/// as the previous version, and versions past the owner's `max_file_versions` are released.
+/// Photo metadata of the old content is cleared until `spawn_image_processing` reads it again.
+/// Folders, and files that already hold this content, are left alone and give false.
+pub async fn add_version(
+    client: &impl GenericClient,
+    file_id: &Uuid,
+    blob_hash: &str,
+    hash: &str,
+    size: i64,
+) -> Result<bool, DBError> {
+    let Some(current) = client
+        .query_opt(
+            "SELECT owner_id, path, title, version, blob_hash FROM files
+            WHERE id = $1 AND type <> 'folder'
+            FOR UPDATE;",
+            &[file_id],
+        )
+        .await?
+    else {
+        return Ok(false);
+    };
+    let current_hash: Option<&str> = current.get("blob_hash");
+    if current_hash == Some(blob_hash) {
+        return Ok(false);
+    }
+
+    // * Files stored before blobs have no version to keep, their object just goes
+    let mut keys = vec![];
+    match current_hash {
+        Some(_) => {
+            client
+                .execute(
+                    "INSERT INTO file_versions (file_id, version, size, hash, etag, blob_hash, created_at)
+                    SELECT id, version, size, hash, etag, blob_hash, COALESCE(updated_at, created_at)
+                    FROM files WHERE id = $1;",
+                    &[file_id],
+                )
+                .await?;
+        }
+        None => keys.push(legacy_key(&current)),
+    }
+    client
+        .execute(
+            "UPDATE files
+            SET size = $3, hash = $4, etag = blobs.etag, blob_hash = blobs.hash,
+                version = files.version + 1, updated_at = CURRENT_TIMESTAMP,
+                camera_make = NULL, camera_model = NULL, taken_at = NULL, image_width = NULL,
+                image_height = NULL, orientation = NULL, gps_latitude = NULL,
+                gps_longitude = NULL
+            FROM blobs WHERE files.id = $1 AND blobs.hash = $2;",
+            &[file_id, &blob_hash, &size, &hash],
+        )
+        .await?;
+
+    let version = current.get::<_, i32>("version") + 1;
+    let owner_id: Uuid = current.get("owner_id");
+    let hashes = client
+        .query(
+            "DELETE FROM file_versions
+            WHERE file_id = $1
+                AND version <= $2 - (SELECT max_file_versions FROM users WHERE id = $3)
+            RETURNING blob_hash;",
+            &[file_id, &version, &owner_id],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("blob_hash"))
+        .collect::<Vec<String>>();
+    keys.extend(release_blobs(client, &hashes).await?);
+    queue_object_deletions(client, &keys).await?;
+    Ok(true)
+}
+
+/// Records a single upload in its own transaction, creating the folders leading to it like
+/// `UploadBatch` does, and deletes the object when it turns out not to be needed.
+pub async fn record_file(state: &AppState, file: NewFile) -> Result<(), AppErrorResponse> {
+    let is_stored = async {
+        let mut conn = state.get_db_conn().await?;
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        let root_path = FileQuery {
+            path: String::new(),
+            is_public: false,
+            is_folder: true,
+        }
+        .format_path(&state.root);
+        let has_parent =
+            create_parent_folders(&tx, &file.owner_id, &root_path, &file.path, file.is_public)
+                .await
+                .map_err(|err| AppError::db_error(err))?;
+        if !has_parent {
+            return Err(AppError::conflict_response(format!(
+                "A file holds the place of a folder in {:?}",
+                file.path
+            )));
+        }
+        let inserted = insert_file(&tx, &file, ConflictPolicy::default())
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        tx.commit().await.map_err(|err| AppError::db_error(err))?;
+        // * Versions past the owner's limit were queued
+        let _ = flush_object_deletions(state, &mut conn).await;
+        if inserted.outcome != UploadOutcome::Skipped {
+            spawn_image_processing(state, vec![inserted.id]);
+        }
+        Ok(inserted.is_stored)
+    }
+    .await;
+
+    if !matches!(is_stored, Ok(true)) {
+        delete_objects(state, &[file.key]).await;
+    }
+    is_stored.map(|_| ())
+}
+
+/// Removes blobs whose last reference is gone and returns their keys.
+async fn release_blobs(
+    client: &impl GenericClient,
+    hashes: &[String],
+) -> Result<Vec<String>, DBError> {
+    let rows = client
+        .query(
+            "DELETE FROM blobs WHERE hash = ANY($1) AND ref_count <= 0 RETURNING key;",
+            &[&hashes],
+        )
+        .await?;
+
+    Ok(rows.iter().map(|row| row.get("key")).collect())
+}
+
+/// Queues the objects freed by deleting file rows, given their `path, title, type, blob_hash`,
+/// `version_hashes` and `rendering_keys`, which `DELETED_FILE_COLUMNS` selects. Shared content
+/// stays until the last file or version pointing at it is gone; files not yet moved by
+/// `migrate_legacy_objects` are stored under their path and always go. Run it in the
+/// transaction that deletes the rows, then `flush_object_deletions` once it commits.
+pub async fn release_deleted_files(
+    client: &impl GenericClient,
+    rows: &[Row],
+) -> Result<(), DBError> {
+    let mut keys = vec![];
+    let mut hashes = vec![];
+    for row in rows {
+        match row.get::<_, Option<String>>("blob_hash") {
+            Some(hash) => hashes.push(hash),
+            None if row.get::<_, &str>("type") != "folder" => keys.push(legacy_key(row)),
+            None => {}
+        }
+        hashes.extend(row.get::<_, Vec<String>>("version_hashes"));
+        keys.extend(row.get::<_, Vec<String>>("rendering_keys"));
+    }
+    keys.extend(release_blobs(client, &hashes).await?);
+    queue_object_deletions(client, &keys).await
+}
+
+/// Queues objects for `flush_object_deletions`.
+pub async fn queue_object_deletions(
+    client: &impl GenericClient,
+    keys: &[String],
+) -> Result<(), DBError> {
+    client
+        .execute(
+            "INSERT INTO object_deletions (key) SELECT unnest($1::text[])
+            ON CONFLICT (key) DO NOTHING;",
+            &[&keys],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Deletes queued objects in batches of `DELETE_BATCH_SIZE`. A batch leaves the queue only
+/// once storage confirms it, so a failure keeps the rest queued for the next flush.
+/// Returns the number of objects deleted.
+pub async fn flush_object_deletions(
+    state: &AppState,
+    conn: &mut Object,
+) -> Result<usize, AppErrorResponse> {
+    let mut deleted = 0;
+
+    loop {
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        // * Concurrent flushes each take their own batch
+        let keys = tx
+            .query(
+                "SELECT key FROM object_deletions
+                ORDER BY created_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED;",
+                &[&(DELETE_BATCH_SIZE as i64)],
+            )
+            .await
+            .map_err(|err| AppError::db_error(err))?
+            .iter()
+            .map(|row| row.get("key"))
+            .collect::<Vec<String>>();
+        if keys.is_empty() {
+            break;
+        }
+
+        state
+            .storage
+            .delete_objects(&keys)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        tx.execute(
+            "DELETE FROM object_deletions WHERE key = ANY($1);",
+            &[&keys],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+        tx.commit().await.map_err(|err| AppError::db_error(err))?;
+        deleted += keys.len();
+    }
+
+    Ok(deleted)
+}
+
+/// Deletes objects nothing points at anymore. Failures only leave an orphaned object behind,
+/// so they are logged rather than returned.
+pub async fn delete_objects(state: &AppState, keys: &[String]) {
+    if let Err(err) = state.storage.delete_objects(keys).await {
+        AppError::storage_error(err);
+    }
+}
+
+/// Moves one file stored under its path to a blob key. The object is copied and hashed, so
+/// a duplicate collapses into the existing blob, and the old key is deleted once the row
+/// points at the blob. Returns false if the object or the row is gone.
+pub async fn adopt_legacy_object(
+    state: &AppState,
+    conn: &mut Object,
+    id: &Uuid,
+    legacy_key: &str,
+    is_public: bool,
+) -> Result<bool, AppErrorResponse> {
+    let key = new_blob_key(&state.root, id);
+    let meta = match state.storage.copy_object(legacy_key, &key, is_public).await {
+        Ok(meta) => meta,
+        Err(StorageError::NotFound(_)) => {
+            tracing::warn!("NO OBJECT FOR FILE {} - {}", id, legacy_key);
+            return Ok(false);
+        }
+        Err(err) => return Err(AppError::storage_error(err)),
+    };
+    let object = StoredObject {
+        key: key.clone(),
+        size: meta.size,
+        encryption: None,
+    };
+    let hash = hash_object(state, &object)
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+    let blob_hash = blob_hash(&hash, false, is_public);
+
+    let tx = conn
+        .transaction()
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+    let is_new_blob = tx
+        .execute(
+            "INSERT INTO blobs (hash, key, size, etag)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (hash) DO NOTHING;",
+            &[&blob_hash, &key, &meta.size, &meta.etag],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        == 1;
+    let is_moved = tx
+        .execute(
+            "UPDATE files SET blob_hash = blobs.hash, hash = $2, etag = blobs.etag
+            FROM blobs
+            WHERE files.id = $1 AND files.blob_hash IS NULL AND blobs.hash = $3;",
+            &[id, &hash.to_string(), &blob_hash],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?
+        == 1;
+    if is_new_blob && !is_moved {
+        release_blobs(&tx, &[blob_hash])
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+    }
+    tx.commit().await.map_err(|err| AppError::db_error(err))?;
+
+    if !(is_new_blob && is_moved) {
+        delete_objects(state, &[key]).await;
+    }
+    if is_moved {
+        delete_objects(state, &[legacy_key.to_string()]).await;
+    }
+    Ok(is_moved)
+}
+
+/// Moves every file still stored under its path to a blob key, `KEY_MIGRATION_BATCH` rows
+/// at a time. Returns the number of files moved.
+pub async fn migrate_legacy_objects(state: &AppState) -> Result<usize, AppErrorResponse> {
+    let mut conn = state.get_db_conn().await?;
+    let mut migrated = 0;
+    let mut last_id = Uuid::nil();
+
+    loop {
+        let rows = conn
+            .query(
+                "SELECT id, path, title, is_public FROM files
+                WHERE blob_hash IS NULL AND type <> 'folder' AND id > $1
+                ORDER BY id
+                LIMIT $2;",
+                &[&last_id, &KEY_MIGRATION_BATCH],
+            )
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_id = last.get("id");
+
+        for row in rows {
+            if adopt_legacy_object(
+                state,
+                &mut conn,
+                &row.get("id"),
+                &legacy_key(&row),
+                row.get("is_public"),
+            )
+            .await?
+            {
+                migrated += 1;
+            }
+        }
+    }
+
+    Ok(migrated)
+}
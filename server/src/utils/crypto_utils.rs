@@ -0,0 +1,581 @@
+use std::{
+    env::var,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::{Buf, Bytes, BytesMut};
+use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce, aead::Aead};
+use strum::IntoEnumIterator;
+use tokio::io::{AsyncRead, ReadBuf};
+use uuid::Uuid;
+
+use crate::{
+    consts::{ENCRYPTION_CHUNK_SIZE, ENCRYPTION_TAG_SIZE, KEY_ROTATION_BATCH},
+    enums::errors::{AppError, StorageError},
+    models::{
+        file::{BlobEncryption, StoredObject},
+        response::AppErrorResponse,
+        state::AppState,
+        storage::ObjectBody,
+    },
+    utils::image_utils::Renderings,
+};
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+// * STREAM nonces: a random prefix, the big endian chunk index, then 1 for the final chunk
+const NONCE_PREFIX_SIZE: usize = NONCE_SIZE - 5;
+const ENCRYPTED_CHUNK_SIZE: usize = ENCRYPTION_CHUNK_SIZE + ENCRYPTION_TAG_SIZE;
+
+fn encryption_error(err: impl ToString) -> StorageError {
+    StorageError::Encryption(err.to_string())
+}
+
+/// Splits a value sealed under a random nonce into that nonce and the ciphertext.
+fn split_nonce(sealed: &[u8]) -> Result<(XNonce, &[u8]), StorageError> {
+    if sealed.len() < NONCE_SIZE {
+        return Err(encryption_error("Sealed value is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+    let nonce: [u8; NONCE_SIZE] = nonce.try_into().map_err(encryption_error)?;
+    Ok((XNonce::from(nonce), ciphertext))
+}
+
+fn seal_with_random_nonce(
+    cipher: &XChaCha20Poly1305,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, StorageError> {
+    let nonce: [u8; NONCE_SIZE] = rand::random();
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(&XNonce::from(nonce), plaintext)
+            .map_err(encryption_error)?,
+    );
+    Ok(sealed)
+}
+
+/// Number of sealed chunks for `size` plaintext bytes. Empty objects still get one.
+pub fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(ENCRYPTION_CHUNK_SIZE as u64).max(1)
+}
+
+pub fn encrypted_size(size: u64) -> u64 {
+    size + chunk_count(size) * ENCRYPTION_TAG_SIZE as u64
+}
+
+/// A key encryption key from config. It only ever encrypts data keys.
+pub struct MasterKey {
+    id: String,
+    cipher: XChaCha20Poly1305,
+}
+
+impl MasterKey {
+    pub fn new(key: &[u8; KEY_SIZE]) -> Self {
+        let id = blake3::Hash::from_bytes(blake3::derive_key("filestore master key id", key));
+        Self {
+            id: id.to_hex()[..16].to_string(),
+            cipher: XChaCha20Poly1305::new(&Key::from(*key)),
+        }
+    }
+
+    pub fn from_base64(value: &str) -> Result<Self, StorageError> {
+        let key: [u8; KEY_SIZE] = STANDARD
+            .decode(value.trim())
+            .map_err(encryption_error)?
+            .try_into()
+            .map_err(|_| encryption_error("A master key must be 32 bytes"))?;
+        Ok(Self::new(&key))
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        seal_with_random_nonce(&self.cipher, data_key)
+    }
+
+    fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let (nonce, ciphertext) = split_nonce(wrapped)?;
+        self.cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(encryption_error)
+    }
+}
+
+/// Master keys from config. New data keys are wrapped by `current`, while `previous` keys
+/// still unwrap the data keys `rotate_data_keys` has not re-wrapped yet.
+pub struct Keyring {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl Keyring {
+    pub fn new(current: MasterKey, previous: Vec<MasterKey>) -> Self {
+        Self { current, previous }
+    }
+
+    /// Reads `ENCRYPTION_MASTER_KEY` and the comma separated `ENCRYPTION_PREVIOUS_MASTER_KEYS`,
+    /// all base64 encoded. Without a master key encryption is unavailable.
+    pub fn from_env() -> Option<Self> {
+        let current = var("ENCRYPTION_MASTER_KEY").ok()?;
+        let current = MasterKey::from_base64(&current)
+            .expect("Env var `ENCRYPTION_MASTER_KEY` is not a base64 encoded 32 byte key");
+        let previous = var("ENCRYPTION_PREVIOUS_MASTER_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| {
+                MasterKey::from_base64(value).expect(
+                    "Env var `ENCRYPTION_PREVIOUS_MASTER_KEYS` has a key that is not base64 encoded 32 bytes",
+                )
+            })
+            .collect();
+        Some(Self::new(current, previous))
+    }
+
+    pub fn current_id(&self) -> &str {
+        &self.current.id
+    }
+
+    fn master_key(&self, id: &str) -> Result<&MasterKey, StorageError> {
+        std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == id)
+            .ok_or_else(|| encryption_error(format!("Master key {} is not configured", id)))
+    }
+
+    /// A fresh data key and nonce prefix for one blob.
+    pub fn new_encryption(&self) -> Result<BlobEncryption, StorageError> {
+        let data_key: [u8; KEY_SIZE] = rand::random();
+        let nonce: [u8; NONCE_PREFIX_SIZE] = rand::random();
+        Ok(BlobEncryption {
+            key_id: self.current.id.clone(),
+            data_key: self.current.wrap(&data_key)?,
+            nonce: nonce.to_vec(),
+        })
+    }
+
+    pub fn cipher(&self, encryption: &BlobEncryption) -> Result<ObjectCipher, StorageError> {
+        let data_key = self
+            .master_key(&encryption.key_id)?
+            .unwrap(&encryption.data_key)?;
+        Ok(ObjectCipher {
+            cipher: XChaCha20Poly1305::new_from_slice(&data_key).map_err(encryption_error)?,
+            nonce: encryption
+                .nonce
+                .as_slice()
+                .try_into()
+                .map_err(|_| encryption_error("Invalid nonce prefix"))?,
+        })
+    }
+
+    /// Wraps the data key with the current master key. The object body stays as it is.
+    pub fn rewrap(&self, encryption: &BlobEncryption) -> Result<BlobEncryption, StorageError> {
+        let data_key = self
+            .master_key(&encryption.key_id)?
+            .unwrap(&encryption.data_key)?;
+        Ok(BlobEncryption {
+            key_id: self.current.id.clone(),
+            data_key: self.current.wrap(&data_key)?,
+            nonce: encryption.nonce.clone(),
+        })
+    }
+}
+
+/// Seals an object as fixed size chunks, each with its own tag, so any chunk can be
+/// decrypted on its own for Range reads.
+pub struct ObjectCipher {
+    cipher: XChaCha20Poly1305,
+    nonce: [u8; NONCE_PREFIX_SIZE],
+}
+
+impl ObjectCipher {
+    fn chunk_nonce(&self, index: u32, is_last: bool) -> XNonce {
+        let mut nonce = [0; NONCE_SIZE];
+        nonce[..NONCE_PREFIX_SIZE].copy_from_slice(&self.nonce);
+        nonce[NONCE_PREFIX_SIZE..NONCE_SIZE - 1].copy_from_slice(&index.to_be_bytes());
+        nonce[NONCE_SIZE - 1] = is_last as u8;
+        XNonce::from(nonce)
+    }
+
+    pub fn encrypt_chunk(
+        &self,
+        index: u32,
+        is_last: bool,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        self.cipher
+            .encrypt(&self.chunk_nonce(index, is_last), plaintext)
+            .map_err(encryption_error)
+    }
+
+    pub fn decrypt_chunk(
+        &self,
+        index: u32,
+        is_last: bool,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        self.cipher
+            .decrypt(&self.chunk_nonce(index, is_last), ciphertext)
+            .map_err(encryption_error)
+    }
+
+    /// Seals the chunks of `plaintext` starting at `first_index`, for an object whose final
+    /// chunk is `last_index`. Only the end of the object may be a partial chunk.
+    pub fn encrypt_chunks(
+        &self,
+        first_index: u32,
+        last_index: u32,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        if plaintext.is_empty() && first_index == last_index {
+            return self.encrypt_chunk(first_index, true, plaintext);
+        }
+
+        let mut sealed = Vec::with_capacity(encrypted_size(plaintext.len() as u64) as usize);
+        for (offset, chunk) in plaintext.chunks(ENCRYPTION_CHUNK_SIZE).enumerate() {
+            let index = first_index + offset as u32;
+            sealed.extend(self.encrypt_chunk(index, index == last_index, chunk)?);
+        }
+        Ok(sealed)
+    }
+
+    /// Seals a short lived value, like pending tus bytes, under a random nonce kept in front.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Bytes, StorageError> {
+        seal_with_random_nonce(&self.cipher, plaintext).map(Bytes::from)
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let (nonce, ciphertext) = split_nonce(sealed)?;
+        self.cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(encryption_error)
+    }
+}
+
+/// Seals plaintext of unknown length as it streams in. The last full chunk is held back
+/// until `finish`, since only then is it known to be the final one.
+pub struct ChunkEncryptor {
+    cipher: ObjectCipher,
+    pending: BytesMut,
+    index: u32,
+}
+
+impl ChunkEncryptor {
+    pub fn new(cipher: ObjectCipher) -> Self {
+        Self {
+            cipher,
+            pending: BytesMut::new(),
+            index: 0,
+        }
+    }
+
+    pub fn update(&mut self, plaintext: &[u8], sealed: &mut BytesMut) -> Result<(), StorageError> {
+        self.pending.extend_from_slice(plaintext);
+        while self.pending.len() > ENCRYPTION_CHUNK_SIZE {
+            let chunk = self.pending.split_to(ENCRYPTION_CHUNK_SIZE);
+            sealed.extend_from_slice(&self.cipher.encrypt_chunk(self.index, false, &chunk)?);
+            self.index += 1;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self, sealed: &mut BytesMut) -> Result<(), StorageError> {
+        let chunk = self.pending.split();
+        sealed.extend_from_slice(&self.cipher.encrypt_chunk(self.index, true, &chunk)?);
+        Ok(())
+    }
+}
+
+/// Decrypts a run of sealed chunks read from storage, dropping `skip` bytes from the first
+/// chunk and stopping after `limit` plaintext bytes.
+pub struct DecryptingReader {
+    inner: ObjectBody,
+    cipher: ObjectCipher,
+    index: u32,
+    last_index: u32,
+    remaining: u64,
+    chunk: Vec<u8>,
+    filled: usize,
+    plaintext: Bytes,
+    skip: usize,
+    limit: u64,
+}
+
+impl DecryptingReader {
+    fn new(
+        inner: ObjectBody,
+        cipher: ObjectCipher,
+        first_index: u32,
+        size: u64,
+        skip: usize,
+        limit: u64,
+    ) -> Self {
+        let chunk_start = first_index as u64 * ENCRYPTED_CHUNK_SIZE as u64;
+        Self {
+            inner,
+            cipher,
+            index: first_index,
+            last_index: chunk_count(size) as u32 - 1,
+            remaining: encrypted_size(size).saturating_sub(chunk_start),
+            chunk: vec![0; ENCRYPTED_CHUNK_SIZE],
+            filled: 0,
+            plaintext: Bytes::new(),
+            skip,
+            limit,
+        }
+    }
+}
+
+impl AsyncRead for DecryptingReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.limit == 0 || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            if !this.plaintext.is_empty() {
+                let len = buf
+                    .remaining()
+                    .min(this.plaintext.len())
+                    .min(this.limit as usize);
+                buf.put_slice(&this.plaintext.split_to(len));
+                this.limit -= len as u64;
+                return Poll::Ready(Ok(()));
+            }
+            if this.remaining == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let target = this.remaining.min(ENCRYPTED_CHUNK_SIZE as u64) as usize;
+            let mut read_buf = ReadBuf::new(&mut this.chunk[this.filled..target]);
+            ready!(this.inner.as_mut().poll_read(cx, &mut read_buf))?;
+            let read = read_buf.filled().len();
+            if read == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Encrypted object ended before its last chunk",
+                )));
+            }
+            this.filled += read;
+            if this.filled < target {
+                continue;
+            }
+
+            let mut plaintext = Bytes::from(
+                this.cipher
+                    .decrypt_chunk(
+                        this.index,
+                        this.index == this.last_index,
+                        &this.chunk[..target],
+                    )
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            );
+            plaintext.advance(this.skip.min(plaintext.len()));
+            this.skip = 0;
+            this.plaintext = plaintext;
+            this.remaining -= target as u64;
+            this.index += 1;
+            this.filled = 0;
+        }
+    }
+}
+
+/// The configured master keys, an error when none are.
+pub fn keyring(state: &AppState) -> Result<&Keyring, StorageError> {
+    state
+        .keyring
+        .as_deref()
+        .ok_or_else(|| encryption_error("No master key is configured"))
+}
+
+/// The keyring to encrypt `owner_id`'s uploads with, or `None` when they are stored as sent.
+pub async fn owner_keyring<'a>(
+    state: &'a AppState,
+    owner_id: &Uuid,
+) -> Result<Option<&'a Keyring>, AppErrorResponse> {
+    let conn = state.get_db_conn().await?;
+    let row = conn
+        .query_one(
+            "SELECT encrypt_files FROM users WHERE id = $1;",
+            &[owner_id],
+        )
+        .await
+        .map_err(|err| AppError::db_error(err))?;
+
+    match row.get::<_, bool>("encrypt_files") {
+        true => keyring(state)
+            .map(Some)
+            .map_err(|err| AppError::storage_error(err)),
+        false => Ok(None),
+    }
+}
+
+pub fn object_cipher(
+    state: &AppState,
+    encryption: &BlobEncryption,
+) -> Result<ObjectCipher, StorageError> {
+    keyring(state)?.cipher(encryption)
+}
+
+/// Reads a stored object back as plaintext.
+pub async fn open_object(
+    state: &AppState,
+    object: &StoredObject,
+) -> Result<ObjectBody, StorageError> {
+    let stored = state.storage.get_object(&object.key).await?;
+    match &object.encryption {
+        Some(encryption) => {
+            let size = object.size as u64;
+            Ok(Box::pin(DecryptingReader::new(
+                stored.body,
+                object_cipher(state, encryption)?,
+                0,
+                size,
+                0,
+                size,
+            )))
+        }
+        None => Ok(stored.body),
+    }
+}
+
+/// Reads plaintext bytes `start..=end`. Encrypted objects are fetched from the first to the
+/// last chunk the range touches.
+pub async fn open_object_range(
+    state: &AppState,
+    object: &StoredObject,
+    start: u64,
+    end: u64,
+) -> Result<ObjectBody, StorageError> {
+    let Some(encryption) = &object.encryption else {
+        let stored = state
+            .storage
+            .get_object_range(&object.key, start, end)
+            .await?;
+        return Ok(stored.body);
+    };
+
+    let size = object.size as u64;
+    let chunk_size = ENCRYPTION_CHUNK_SIZE as u64;
+    let first_index = start / chunk_size;
+    let stored_start = first_index * ENCRYPTED_CHUNK_SIZE as u64;
+    let stored_end =
+        ((end / chunk_size + 1) * ENCRYPTED_CHUNK_SIZE as u64).min(encrypted_size(size)) - 1;
+    let stored = state
+        .storage
+        .get_object_range(&object.key, stored_start, stored_end)
+        .await?;
+
+    Ok(Box::pin(DecryptingReader::new(
+        stored.body,
+        object_cipher(state, encryption)?,
+        first_index as u32,
+        size,
+        (start - first_index * chunk_size) as usize,
+        end - start + 1,
+    )))
+}
+
+/// Re-wraps every data key that is not wrapped by the current master key, without touching
+/// object bodies. Returns how many blobs and renderings were updated.
+pub async fn rotate_data_keys(state: &AppState) -> Result<usize, AppErrorResponse> {
+    let keyring = keyring(state).map_err(|err| AppError::storage_error(err))?;
+    let conn = state.get_db_conn().await?;
+    let mut rotated = 0;
+
+    loop {
+        let rows = conn
+            .query(
+                "SELECT hash, key_id, data_key, nonce FROM blobs WHERE key_id <> $1 LIMIT $2;",
+                &[&keyring.current_id(), &KEY_ROTATION_BATCH],
+            )
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in rows {
+            let hash: String = row.get("hash");
+            let encryption = BlobEncryption {
+                key_id: row.get("key_id"),
+                data_key: row.get("data_key"),
+                nonce: row.get("nonce"),
+            };
+            let rewrapped = keyring
+                .rewrap(&encryption)
+                .map_err(|err| AppError::storage_error(err))?;
+            // * Matching on the old key id leaves a blob alone if another run got there first
+            rotated += conn
+                .execute(
+                    "UPDATE blobs SET key_id = $1, data_key = $2 WHERE hash = $3 AND key_id = $4;",
+                    &[
+                        &rewrapped.key_id,
+                        &rewrapped.data_key,
+                        &hash,
+                        &encryption.key_id,
+                    ],
+                )
+                .await
+                .map_err(|err| AppError::db_error(err))? as usize;
+        }
+    }
+
+    // * Renderings of encrypted files carry envelopes of their own
+    for renderings in Renderings::iter() {
+        loop {
+            let rows = conn
+                .query(
+                    &format!(
+                        "SELECT file_id, {} AS name, key_id, data_key, nonce FROM {}
+                        WHERE key_id <> $1 LIMIT $2;",
+                        renderings.name_column(),
+                        renderings.table()
+                    ),
+                    &[&keyring.current_id(), &KEY_ROTATION_BATCH],
+                )
+                .await
+                .map_err(|err| AppError::db_error(err))?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let encryption = BlobEncryption {
+                    key_id: row.get("key_id"),
+                    data_key: row.get("data_key"),
+                    nonce: row.get("nonce"),
+                };
+                let rewrapped = keyring
+                    .rewrap(&encryption)
+                    .map_err(|err| AppError::storage_error(err))?;
+                rotated += conn
+                    .execute(
+                        &format!(
+                            "UPDATE {} SET key_id = $1, data_key = $2
+                            WHERE file_id = $3 AND {} = $4 AND key_id = $5;",
+                            renderings.table(),
+                            renderings.name_column()
+                        ),
+                        &[
+                            &rewrapped.key_id,
+                            &rewrapped.data_key,
+                            &row.get::<_, Uuid>("file_id"),
+                            &row.get::<_, String>("name"),
+                            &encryption.key_id,
+                        ],
+                    )
+                    .await
+                    .map_err(|err| AppError::db_error(err))? as usize;
+            }
+        }
+    }
+
+    Ok(rotated)
+}
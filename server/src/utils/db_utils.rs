@@ -145,6 +145,7 @@ impl<'a> WhereBuilder<'a> {
     }
 }
 
+#[allow(dead_code)]
 pub fn get_select_string(model: &Models, fields: &HashSet<String>) -> String {
     fields
         .iter()
@@ -159,6 +160,7 @@ pub fn get_select_string(model: &Models, fields: &HashSet<String>) -> String {
         .join(",")
 }
 
+#[allow(dead_code)]
 pub fn format_hashset_select_string(fields: &HashSet<String>) -> String {
     fields.iter().join(", ")
 }
@@ -0,0 +1,296 @@
+use std::io::{self, Cursor};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use deadpool_postgres::GenericClient;
+use exif::{DateTime, Exif, In, Reader, Tag, Value};
+use image::ImageFormat;
+use img_parts::{ImageEXIF, jpeg::Jpeg, png::Png, webp::WebP};
+use jiff::{
+    Timestamp, civil,
+    tz::{Offset, TimeZone},
+};
+use tokio::task::spawn_blocking;
+use tokio_postgres::{Error as DBError, Row};
+use uuid::Uuid;
+
+use crate::{
+    enums::{
+        errors::{RenderError, StorageError},
+        file_enums::FileTypes,
+    },
+    models::{
+        file::{PhotoMetadata, StoredObject},
+        state::AppState,
+    },
+    utils::{
+        blob_utils::blob_encryption,
+        image_utils::{Renderings, STRIPPED_VARIANT, decode_image, load_image},
+    },
+};
+
+// * JPEG segments and PNG chunks that carry EXIF, XMP, IPTC or free text
+const JPEG_METADATA_MARKERS: [u8; 2] = [0xe1, 0xed];
+const PNG_METADATA_CHUNKS: [[u8; 4]; 4] = [*b"eXIf", *b"iTXt", *b"tEXt", *b"zTXt"];
+const WEBP_METADATA_CHUNKS: [[u8; 4]; 2] = [*b"EXIF", *b"XMP "];
+
+/// Reads what the EXIF data in `bytes` says about the photo. Images without it give an empty
+/// `PhotoMetadata`.
+pub fn read_photo_metadata(bytes: &[u8]) -> PhotoMetadata {
+    match Reader::new().read_from_container(&mut Cursor::new(bytes)) {
+        Ok(exif) => photo_metadata(&exif),
+        Err(_) => PhotoMetadata::default(),
+    }
+}
+
+fn photo_metadata(exif: &Exif) -> PhotoMetadata {
+    let text = |tag: Tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    };
+    let number = |tag: Tag| {
+        exif.get_field(tag, In::PRIMARY)?
+            .value
+            .get_uint(0)
+            .and_then(|value| i32::try_from(value).ok())
+    };
+    let coordinate = |tag: Tag, reference: Tag, negative: &str| {
+        let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        if parts.len() < 3 {
+            return None;
+        }
+        // * Degrees, minutes and seconds
+        let degrees = parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0;
+        let sign = match text(reference) {
+            Some(reference) if reference.eq_ignore_ascii_case(negative) => -1.0,
+            _ => 1.0,
+        };
+        degrees.is_finite().then_some(sign * degrees)
+    };
+
+    PhotoMetadata {
+        camera_make: text(Tag::Make),
+        camera_model: text(Tag::Model),
+        taken_at: taken_at(exif, Tag::DateTimeOriginal, Tag::OffsetTimeOriginal)
+            .or_else(|| taken_at(exif, Tag::DateTime, Tag::OffsetTime)),
+        image_width: number(Tag::PixelXDimension).or_else(|| number(Tag::ImageWidth)),
+        image_height: number(Tag::PixelYDimension).or_else(|| number(Tag::ImageLength)),
+        orientation: number(Tag::Orientation).filter(|orientation| (1..=8).contains(orientation)),
+        gps_latitude: coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        gps_longitude: coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    }
+}
+
+fn taken_at(exif: &Exif, tag: Tag, offset_tag: Tag) -> Option<Timestamp> {
+    let Value::Ascii(values) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let mut taken = DateTime::from_ascii(values.first()?).ok()?;
+    if let Some(Value::Ascii(offsets)) = exif
+        .get_field(offset_tag, In::PRIMARY)
+        .map(|field| &field.value)
+        && let Some(offset) = offsets.first()
+    {
+        let _ = taken.parse_offset(offset);
+    }
+
+    let offset = Offset::from_seconds(taken.offset.unwrap_or_default() as i32 * 60).ok()?;
+    civil::DateTime::new(
+        taken.year as i16,
+        taken.month as i8,
+        taken.day as i8,
+        taken.hour as i8,
+        taken.minute as i8,
+        taken.second as i8,
+        taken.nanosecond.unwrap_or_default() as i32,
+    )
+    .ok()?
+    .to_zoned(TimeZone::fixed(offset))
+    .ok()
+    .map(|zoned| zoned.timestamp())
+}
+
+/// Reads the photo metadata of the file in `source`, a row selected by `IMAGE_SOURCE_QUERY`,
+/// into its columns. Content replaced in the meantime keeps the columns it was cleared to.
+pub async fn extract_photo_metadata(
+    state: &AppState,
+    client: &impl GenericClient,
+    source: &Row,
+) -> Result<(), RenderError> {
+    let bytes = load_image(state, source).await?;
+    let metadata = read_photo_metadata(&bytes);
+    client
+        .execute(
+            "UPDATE files
+            SET camera_make = $3, camera_model = $4, taken_at = $5, image_width = $6,
+                image_height = $7, orientation = $8, gps_latitude = $9, gps_longitude = $10
+            WHERE id = $1 AND version = $2;",
+            &[
+                &source.get::<_, Uuid>("id"),
+                &source.get::<_, i32>("version"),
+                &metadata.camera_make,
+                &metadata.camera_model,
+                &metadata.taken_at,
+                &metadata.image_width,
+                &metadata.image_height,
+                &metadata.orientation,
+                &metadata.gps_latitude,
+                &metadata.gps_longitude,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The photo metadata of a file, as read by `extract_photo_metadata`.
+pub async fn photo_metadata_of(
+    client: &impl GenericClient,
+    file_id: &Uuid,
+    owner_id: &Uuid,
+) -> Result<Option<PhotoMetadata>, DBError> {
+    let row = client
+        .query_opt(
+            "SELECT camera_make, camera_model, taken_at, image_width, image_height,
+                orientation, gps_latitude, gps_longitude
+            FROM files
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL AND type <> 'folder';",
+            &[file_id, owner_id],
+        )
+        .await?;
+
+    Ok(row.map(|row| PhotoMetadata {
+        camera_make: row.get("camera_make"),
+        camera_model: row.get("camera_model"),
+        taken_at: row.get("taken_at"),
+        image_width: row.get("image_width"),
+        image_height: row.get("image_height"),
+        orientation: row.get("orientation"),
+        gps_latitude: row.get("gps_latitude"),
+        gps_longitude: row.get("gps_longitude"),
+    }))
+}
+
+/// Whether a file is served without its metadata to anyone but its owner: when its bucket or,
+/// failing that, its owner asks for it.
+pub async fn strips_metadata(client: &impl GenericClient, file_id: &Uuid) -> Result<bool, DBError> {
+    let row = client
+        .query_opt(
+            "SELECT users.strip_metadata OR COALESCE(buckets.strip_metadata, FALSE) AS strip
+            FROM files
+                JOIN users ON users.id = files.owner_id
+                LEFT JOIN buckets ON buckets.id = files.bucket_id
+            WHERE files.id = $1;",
+            &[file_id],
+        )
+        .await?;
+    Ok(row.is_some_and(|row| row.get("strip")))
+}
+
+/// Removes EXIF, XMP and text metadata from an image of `file_type`. JPEG and PNG images keep
+/// their orientation; TIFF images are turned upright and encoded again. Types without
+/// metadata the server knows come back as they are.
+pub fn strip_metadata(bytes: Vec<u8>, file_type: &FileTypes) -> Result<Vec<u8>, RenderError> {
+    let orientation = || {
+        Reader::new()
+            .read_from_container(&mut Cursor::new(&bytes))
+            .ok()
+            .and_then(|exif| photo_metadata(&exif).orientation)
+            .filter(|orientation| *orientation != 1)
+            .map(orientation_exif)
+    };
+
+    let stripped = match file_type {
+        FileTypes::Jpg | FileTypes::Jpeg => {
+            let orientation = orientation();
+            let mut jpeg = Jpeg::from_bytes(Bytes::from(bytes))?;
+            for marker in JPEG_METADATA_MARKERS {
+                jpeg.remove_segments_by_marker(marker);
+            }
+            jpeg.set_exif(orientation);
+            jpeg.encoder().bytes()
+        }
+        FileTypes::Png => {
+            let orientation = orientation();
+            let mut png = Png::from_bytes(Bytes::from(bytes))?;
+            for chunk in PNG_METADATA_CHUNKS {
+                png.remove_chunks_by_type(chunk);
+            }
+            png.set_exif(orientation);
+            png.encoder().bytes()
+        }
+        FileTypes::Webp => {
+            let mut webp = WebP::from_bytes(Bytes::from(bytes))?;
+            for chunk in WEBP_METADATA_CHUNKS {
+                webp.remove_chunks_by_id(chunk);
+            }
+            // * Keeps the header flags in line with the chunks left
+            webp.set_exif(None);
+            webp.encoder().bytes()
+        }
+        FileTypes::Tiff => {
+            let mut body = vec![];
+            decode_image(bytes)?.write_to(&mut Cursor::new(&mut body), ImageFormat::Tiff)?;
+            return Ok(body);
+        }
+        _ => return Ok(bytes),
+    };
+    Ok(stripped.to_vec())
+}
+
+/// Big-endian TIFF data holding nothing but an orientation tag.
+fn orientation_exif(orientation: i32) -> Bytes {
+    let mut tiff = BytesMut::with_capacity(26);
+    tiff.put_slice(b"MM\0*");
+    tiff.put_u32(8);
+    tiff.put_u16(1);
+    tiff.put_u16(0x0112);
+    // * One SHORT, padded to the four bytes of the value field
+    tiff.put_u16(3);
+    tiff.put_u32(1);
+    tiff.put_u16(orientation as u16);
+    tiff.put_u16(0);
+    tiff.put_u32(0);
+    tiff.freeze()
+}
+
+/// The copy of the file in `source`, a row selected by `IMAGE_SOURCE_QUERY`, stripped of its
+/// metadata. Copies are kept as the `STRIPPED_VARIANT` of the file and made on first use; a
+/// replaced copy is queued for `flush_object_deletions`.
+pub async fn stripped_copy(
+    state: &AppState,
+    client: &impl GenericClient,
+    source: &Row,
+) -> Result<StoredObject, RenderError> {
+    let file_id: Uuid = source.get("id");
+    let version: i32 = source.get("version");
+    let current = || Renderings::Variants.current(client, &file_id, version, STRIPPED_VARIANT);
+
+    let copy = match current().await? {
+        Some(copy) => copy,
+        None => {
+            let file_type: FileTypes = source.get("type");
+            let bytes = load_image(state, source).await?;
+            let strip_type = file_type.clone();
+            let body = spawn_blocking(move || strip_metadata(bytes, &strip_type))
+                .await
+                .map_err(|err| StorageError::Io(io::Error::other(err)))??;
+            Renderings::Variants
+                .store(state, client, source, STRIPPED_VARIANT, &body, &file_type)
+                .await?;
+            // * A newer version was stored first, so this one has no copy to serve
+            current()
+                .await?
+                .ok_or_else(|| StorageError::NotFound(file_id.to_string()))?
+        }
+    };
+
+    Ok(StoredObject {
+        key: copy.get("key"),
+        size: copy.get("byte_size"),
+        encryption: blob_encryption(&copy),
+    })
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::enums::file_enums::{FileCategory, FileTypes};
+
+/// A known type: its name in the `type` column, the extensions and MIME types it goes by and
+/// its category. The first MIME type is the one it is served with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTypeEntry {
+    pub name: String,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    pub category: Option<FileCategory>,
+}
+
+impl FileTypeEntry {
+    pub fn file_type(&self) -> FileTypes {
+        self.name
+            .parse()
+            .unwrap_or_else(|_| FileTypes::Custom(self.name.clone()))
+    }
+
+    pub fn content_type(&self) -> &str {
+        self.mime_types
+            .first()
+            .map_or("application/octet-stream", |mime| mime.as_str())
+    }
+}
+
+// * name, extensions, MIME types, category
+const BUILTIN_TYPES: &[(&str, &[&str], &[&str], FileCategory)] = &[
+    ("png", &["png"], &["image/png"], FileCategory::Image),
+    ("jpg", &["jpg"], &["image/jpeg"], FileCategory::Image),
+    ("jpeg", &["jpeg"], &["image/jpeg"], FileCategory::Image),
+    ("webp", &["webp"], &["image/webp"], FileCategory::Image),
+    ("gif", &["gif"], &["image/gif"], FileCategory::Image),
+    ("svg", &["svg"], &["image/svg+xml"], FileCategory::Image),
+    ("bmp", &["bmp"], &["image/bmp"], FileCategory::Image),
+    ("heic", &["heic"], &["image/heic"], FileCategory::Image),
+    ("raw", &["raw"], &["image/x-raw"], FileCategory::Image),
+    (
+        "tiff",
+        &["tiff", "tif"],
+        &["image/tiff"],
+        FileCategory::Image,
+    ),
+    (
+        "psd",
+        &["psd"],
+        &["image/vnd.adobe.photoshop"],
+        FileCategory::Image,
+    ),
+    (
+        "pdf",
+        &["pdf"],
+        &["application/pdf"],
+        FileCategory::Document,
+    ),
+    (
+        "doc",
+        &["doc"],
+        &["application/msword"],
+        FileCategory::Document,
+    ),
+    (
+        "docx",
+        &["docx"],
+        &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"],
+        FileCategory::Document,
+    ),
+    ("txt", &["txt"], &["text/plain"], FileCategory::Document),
+    (
+        "xls",
+        &["xls"],
+        &["application/vnd.ms-excel"],
+        FileCategory::Document,
+    ),
+    (
+        "xlsx",
+        &["xlsx"],
+        &["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"],
+        FileCategory::Document,
+    ),
+    ("csv", &["csv"], &["text/csv"], FileCategory::Document),
+    ("md", &["md"], &["text/markdown"], FileCategory::Document),
+    (
+        "mdx",
+        &["mdx"],
+        &["text/markdown", "text/mdx"],
+        FileCategory::Document,
+    ),
+    ("mp3", &["mp3"], &["audio/mpeg"], FileCategory::Audio),
+    (
+        "wav",
+        &["wav"],
+        &["audio/wav", "audio/x-wav"],
+        FileCategory::Audio,
+    ),
+    ("ogg", &["ogg"], &["audio/ogg"], FileCategory::Audio),
+    ("flac", &["flac"], &["audio/flac"], FileCategory::Audio),
+    ("opus", &["opus"], &["audio/opus"], FileCategory::Audio),
+    ("aac", &["aac"], &["audio/aac"], FileCategory::Audio),
+    ("m4p", &["m4p"], &["audio/mp4"], FileCategory::Audio),
+    ("mp4", &["mp4"], &["video/mp4"], FileCategory::Video),
+    ("mov", &["mov"], &["video/quicktime"], FileCategory::Video),
+    ("avi", &["avi"], &["video/x-msvideo"], FileCategory::Video),
+    ("webm", &["webm"], &["video/webm"], FileCategory::Video),
+    ("zip", &["zip"], &["application/zip"], FileCategory::Archive),
+    (
+        "rar",
+        &["rar"],
+        &["application/vnd.rar"],
+        FileCategory::Archive,
+    ),
+    ("json", &["json"], &["application/json"], FileCategory::Code),
+    (
+        "xml",
+        &["xml"],
+        &["application/xml", "text/xml"],
+        FileCategory::Code,
+    ),
+    (
+        "yml",
+        &["yml", "yaml"],
+        &["application/x-yaml", "application/yaml"],
+        FileCategory::Code,
+    ),
+    ("sql", &["sql"], &["application/sql"], FileCategory::Code),
+    (
+        "js",
+        &["js"],
+        &["text/javascript", "application/javascript"],
+        FileCategory::Code,
+    ),
+    ("ts", &["ts"], &["text/typescript"], FileCategory::Code),
+    ("jsx", &["jsx"], &["text/jsx"], FileCategory::Code),
+    ("tsx", &["tsx"], &["text/tsx"], FileCategory::Code),
+    ("css", &["css"], &["text/css"], FileCategory::Code),
+    ("scss", &["scss"], &["text/x-scss"], FileCategory::Code),
+    ("sass", &["sass"], &["text/x-sass"], FileCategory::Code),
+    ("py", &["py"], &["text/x-python"], FileCategory::Code),
+    ("rb", &["rb"], &["text/x-ruby"], FileCategory::Code),
+    (
+        "php",
+        &["php"],
+        &["application/x-httpd-php"],
+        FileCategory::Code,
+    ),
+    ("sh", &["sh"], &["application/x-sh"], FileCategory::Code),
+    (
+        "java",
+        &["java"],
+        &["text/x-java-source"],
+        FileCategory::Code,
+    ),
+    ("cs", &["cs"], &["text/x-csharp"], FileCategory::Code),
+    ("html", &["html"], &["text/html"], FileCategory::Code),
+    ("ttf", &["ttf"], &["font/ttf"], FileCategory::Font),
+    ("otf", &["otf"], &["font/otf"], FileCategory::Font),
+    ("woff", &["woff"], &["font/woff"], FileCategory::Font),
+    (
+        "exe",
+        &["exe"],
+        &["application/vnd.microsoft.portable-executable"],
+        FileCategory::Executable,
+    ),
+];
+
+/// Every type the server knows, looked up by name, extension or MIME type.
+#[derive(Debug, Default)]
+pub struct FileTypeRegistry {
+    entries: Vec<FileTypeEntry>,
+    by_name: HashMap<String, usize>,
+    by_extension: HashMap<String, usize>,
+    by_mime: HashMap<String, usize>,
+}
+
+impl FileTypeRegistry {
+    /// The built-in types followed by `configured` ones. Built-in extensions and MIME types
+    /// shared by several types belong to the first; configured ones take them over. A
+    /// configured entry named like a known type adds to it and may change its category.
+    pub fn new(configured: Vec<FileTypeEntry>) -> Self {
+        let mut registry = Self::default();
+        for (name, extensions, mime_types, category) in BUILTIN_TYPES {
+            registry.add(
+                FileTypeEntry {
+                    name: name.to_string(),
+                    extensions: extensions.iter().map(|value| value.to_string()).collect(),
+                    mime_types: mime_types.iter().map(|value| value.to_string()).collect(),
+                    category: Some(*category),
+                },
+                false,
+            );
+        }
+        for entry in configured {
+            registry.add(entry, true);
+        }
+        registry
+    }
+
+    fn add(&mut self, entry: FileTypeEntry, is_override: bool) {
+        let name = entry.name.to_lowercase();
+        let index = match self.by_name.get(&name) {
+            Some(&index) => {
+                let existing = &mut self.entries[index];
+                existing.extensions.extend(entry.extensions.iter().cloned());
+                existing.mime_types.extend(entry.mime_types.iter().cloned());
+                existing.category = entry.category.or(existing.category);
+                index
+            }
+            None => {
+                self.entries.push(FileTypeEntry {
+                    name: name.clone(),
+                    ..entry.clone()
+                });
+                self.by_name.insert(name, self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+
+        for extension in &entry.extensions {
+            claim(&mut self.by_extension, extension, index, is_override);
+        }
+        for mime in &entry.mime_types {
+            claim(&mut self.by_mime, mime, index, is_override);
+        }
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&FileTypeEntry> {
+        self.by_name.get(name).map(|&index| &self.entries[index])
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<&FileTypeEntry> {
+        self.by_extension
+            .get(&extension.to_lowercase())
+            .map(|&index| &self.entries[index])
+    }
+
+    pub fn by_mime(&self, mime: &str) -> Option<&FileTypeEntry> {
+        self.by_mime
+            .get(&mime.to_lowercase())
+            .map(|&index| &self.entries[index])
+    }
+
+    /// Names of the types in `category`, as stored in the `type` column.
+    pub fn names_in(&self, category: FileCategory) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.category == Some(category))
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+}
+
+fn claim(claims: &mut HashMap<String, usize>, key: &str, index: usize, is_override: bool) {
+    let key = key.to_lowercase();
+    match is_override {
+        true => {
+            claims.insert(key, index);
+        }
+        false => {
+            claims.entry(key).or_insert(index);
+        }
+    }
+}
+
+/// The built-in types, extended by the JSON list of `FileTypeEntry` at `FILE_TYPES_CONFIG`.
+pub static FILE_TYPES: Lazy<FileTypeRegistry> = Lazy::new(|| {
+    let configured = std::env::var("FILE_TYPES_CONFIG")
+        .map(|path| {
+            let config = std::fs::read_to_string(&path)
+                .expect("COULD NOT READ THE FILE AT FILE_TYPES_CONFIG");
+            serde_json::from_str::<Vec<FileTypeEntry>>(&config)
+                .expect("COULD NOT PARSE FILE_TYPES_CONFIG AS A LIST OF FILE TYPES")
+        })
+        .unwrap_or_default();
+    FileTypeRegistry::new(configured)
+});
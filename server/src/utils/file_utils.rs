@@ -1,83 +1,612 @@
-use std::io::{BufReader, Read};
+use std::{collections::HashSet, io::Cursor};
 
-use aws_sdk_s3::primitives::ByteStream;
 use axum::extract::multipart::Field;
 use blake3::{Hash, Hasher};
+use bytes::{Bytes, BytesMut};
+use deadpool_postgres::GenericClient;
+use tokio::io::AsyncReadExt;
+use tokio_postgres::Error as DBError;
+use uuid::Uuid;
 
 use crate::{
-    enums::{errors::AppError, file_enums::FileTypes},
-    models::state::AppState,
+    consts::{ENCRYPTION_CHUNK_SIZE, SNIFF_SIZE, TYPE_MISMATCH_POLICY, UPLOAD_PART_SIZE},
+    enums::{
+        errors::{AppError, ArchiveError, StorageError},
+        file_enums::{FileTypes, MismatchPolicy, UploadErrorReason},
+        request_enums::ConflictPolicy,
+    },
+    models::{
+        file::{BlobEncryption, InsertedFile, NewFile, StoredObject, UploadedFile},
+        state::AppState,
+        storage::{ObjectBody, ObjectMeta, PutOptions, UploadedPart},
+    },
+    routes::file_routes::FileQuery,
+    traits::storage_traits::StorageBackend,
+    utils::{
+        blob_utils::{delete_objects, insert_file, new_blob_key},
+        crypto_utils::{
+            ChunkEncryptor, Keyring, ObjectCipher, object_cipher, open_object, open_object_range,
+        },
+        format_utils::title_stem,
+        sniff_utils::{VerifiedType, verify_type},
+        tree_utils::{create_parent_folders, split_relative_path},
+        zip_utils::{check_extraction_limits, open_zip_member, read_zip_directory},
+    },
 };
 
-pub async fn upload_file(
+/// Writes a stream of chunks to storage while hashing it. Holds at most one part in
+/// memory; objects smaller than a part are sent with a single put, larger ones switch
+/// to a multipart upload once the first part fills up. With `encrypted` the stored bytes
+/// are sealed chunks, while the hash and size still describe the plaintext.
+pub struct StreamingUpload<'a> {
+    storage: &'a dyn StorageBackend,
+    key: String,
+    options: PutOptions,
+    encryptor: Option<ChunkEncryptor>,
+    buffer: BytesMut,
+    upload_id: Option<String>,
+    parts: Vec<UploadedPart>,
+    hasher: Hasher,
+    size: i64,
+}
+
+impl<'a> StreamingUpload<'a> {
+    pub fn new(storage: &'a dyn StorageBackend, key: &str, options: PutOptions) -> Self {
+        Self {
+            storage,
+            key: key.to_string(),
+            options,
+            encryptor: None,
+            buffer: BytesMut::new(),
+            upload_id: None,
+            parts: Vec::new(),
+            hasher: Hasher::new(),
+            size: 0,
+        }
+    }
+
+    pub fn encrypted(mut self, cipher: ObjectCipher) -> Self {
+        self.encryptor = Some(ChunkEncryptor::new(cipher));
+        self
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), StorageError> {
+        self.hasher.update(chunk);
+        self.size += chunk.len() as i64;
+        match &mut self.encryptor {
+            Some(encryptor) => encryptor.update(chunk, &mut self.buffer)?,
+            None => self.buffer.extend_from_slice(chunk),
+        }
+
+        while self.buffer.len() >= UPLOAD_PART_SIZE {
+            self.flush_part(UPLOAD_PART_SIZE).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_part(&mut self, len: usize) -> Result<(), StorageError> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let upload_id = self
+                    .storage
+                    .create_multipart_upload(&self.key, &self.options)
+                    .await?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+
+        let body = self.buffer.split_to(len).freeze();
+        let part = self
+            .storage
+            .upload_part(&self.key, &upload_id, self.parts.len() as i32 + 1, body)
+            .await?;
+        self.parts.push(part);
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<(ObjectMeta, Hash, i64), StorageError> {
+        let result = self.complete().await;
+        if result.is_err() {
+            self.abort().await;
+        }
+        result.map(|meta| (meta, self.hasher.finalize(), self.size))
+    }
+
+    async fn complete(&mut self) -> Result<ObjectMeta, StorageError> {
+        if let Some(mut encryptor) = self.encryptor.take() {
+            encryptor.finish(&mut self.buffer)?;
+        }
+        if self.upload_id.is_none() {
+            let body = self.buffer.split().freeze();
+            return self
+                .storage
+                .put_object(&self.key, body, &self.options)
+                .await;
+        }
+
+        if !self.buffer.is_empty() {
+            self.flush_part(self.buffer.len()).await?;
+        }
+        let upload_id = self.upload_id.clone().unwrap_or_default();
+        self.storage
+            .complete_multipart_upload(&self.key, &upload_id, &self.parts)
+            .await
+    }
+
+    /// Drops any parts already sent. Safe to call when no multipart upload was started.
+    pub async fn abort(&mut self) {
+        if let Some(upload_id) = self.upload_id.take() {
+            let aborted = self
+                .storage
+                .abort_multipart_upload(&self.key, &upload_id)
+                .await;
+            if let Err(err) = aborted {
+                tracing::error!("ERROR ABORTING MULTIPART UPLOAD - {}", err);
+            }
+        }
+        self.buffer.clear();
+    }
+}
+
+/// Hashes the plaintext of a stored object by streaming it back from storage.
+pub async fn hash_object(state: &AppState, object: &StoredObject) -> Result<Hash, StorageError> {
+    let mut body = open_object(state, object).await?;
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = body.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Reads the leading bytes of `body` that `verify_type` looks at.
+async fn read_head(body: &mut ObjectBody) -> Result<Vec<u8>, StorageError> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    body.take(SNIFF_SIZE as u64).read_to_end(&mut head).await?;
+    Ok(head)
+}
+
+/// Checks the first `SNIFF_SIZE` bytes of a stored object against the extension of `title`
+/// and the `declared` content type, for uploads that reach storage before the server sees them.
+pub async fn verify_object(
     state: &AppState,
-    field: Field<'_>,
-    title: &String,
-    file_path: &String,
-    is_public: &bool,
-) -> Result<(FileTypes, i64, Hash), bool> {
-    let mut size: i64 = 0;
-    let mut data = Vec::new();
-    let mut stream = field;
-    while let Some(chunk) = stream.chunk().await.map_err(|err| {
+    object: &StoredObject,
+    title: &str,
+    declared: Option<&str>,
+) -> Result<VerifiedType, StorageError> {
+    let head = match object.size.min(SNIFF_SIZE as i64) {
+        0 => vec![],
+        length => {
+            read_head(&mut open_object_range(state, object, 0, length as u64 - 1).await?).await?
+        }
+    };
+    Ok(verify_type(&head, title, declared))
+}
+
+/// Logs a claim `verify_type` found contradicted and tells whether `TYPE_MISMATCH_POLICY`
+/// refuses the file for it.
+pub fn refuses_type(title: &str, verified: &VerifiedType) -> bool {
+    let Some(claimed) = &verified.mismatch else {
+        return false;
+    };
+    tracing::warn!(
+        "CONTENT OF {} IS {} NOT {}",
+        title,
+        verified.file_type,
+        claimed
+    );
+    *TYPE_MISMATCH_POLICY == MismatchPolicy::Reject
+}
+
+// * Logs why a part of the request body could not be read
+async fn read_chunk(field: &mut Field<'_>) -> Result<Option<Bytes>, UploadErrorReason> {
+    field.chunk().await.map_err(|err| {
         AppError::critical_error(format!(
             "CANNOT READ FILE CHUNK | STATUS:{} | TEXT:{}",
             err.status(),
             err.body_text()
         ));
-        false
-    })? {
-        size += chunk.len() as i64;
-        data.extend_from_slice(&chunk);
-    }
+        UploadErrorReason::ReadFailed
+    })
+}
 
-    let content_type = stream.content_type();
+/// Streams a multipart field to storage at `file_path`. Its first `SNIFF_SIZE` bytes are
+/// checked against the extension of `title` and the declared content type before anything is
+/// stored; contradicted claims are rejected or reported, as `TYPE_MISMATCH_POLICY` says.
+pub async fn upload_file(
+    state: &AppState,
+    field: Field<'_>,
+    title: &String,
+    file_path: &String,
+    is_public: &bool,
+    encryption: Option<&BlobEncryption>,
+) -> Result<(VerifiedType, i64, Hash, Option<String>), UploadErrorReason> {
+    let mut stream = field;
+    let declared = stream
+        .content_type()
+        .map(|content_type| content_type.to_string());
 
-    tracing::debug!("UPLOADING FILE TYPE ========> {:?}", content_type);
+    tracing::debug!("UPLOADING FILE TYPE ========> {:?}", declared);
 
     if title == "unnamed" {
         tracing::error!("Unnamed file SKIPPING - {}", file_path);
-        return Err(false);
+        return Err(UploadErrorReason::InvalidName);
     }
 
-    let content_type = content_type.unwrap().to_string();
-    let body = ByteStream::from(data);
-    let bytes = body.bytes().unwrap_or_default();
-    let mut reader = BufReader::new(bytes);
-    let mut hasher = Hasher::new();
-    let mut buffer = [0u8; 128 * 1024];
+    let mut head = BytesMut::new();
+    let mut is_read = false;
+    while head.len() < SNIFF_SIZE {
+        match read_chunk(&mut stream).await? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => {
+                is_read = true;
+                break;
+            }
+        }
+    }
+    let verified = verify_type(
+        &head[..head.len().min(SNIFF_SIZE)],
+        title,
+        declared.as_deref(),
+    );
+    if refuses_type(title, &verified) {
+        return Err(UploadErrorReason::TypeMismatch);
+    }
 
-    while let Ok(read) = reader.read(&mut buffer) {
-        if read == 0 {
-            break;
+    // * Types the server has no name for keep the declared content type
+    let content_type = match (&verified.file_type, &declared) {
+        (FileTypes::Other(_), Some(declared)) if verified.mismatch.is_none() => declared.clone(),
+        (file_type, _) => file_type.content_type().to_string(),
+    };
+    let options = PutOptions {
+        content_type: Some(content_type),
+        is_public: *is_public,
+    };
+    let mut upload = StreamingUpload::new(state.storage.as_ref(), file_path, options);
+    if let Some(encryption) = encryption {
+        match object_cipher(state, encryption) {
+            Ok(cipher) => upload = upload.encrypted(cipher),
+            Err(err) => {
+                AppError::storage_error(err);
+                return Err(UploadErrorReason::EncryptionFailed);
+            }
+        }
+    }
+
+    let mut chunk = Some(head.freeze());
+    while let Some(bytes) = chunk {
+        if let Err(err) = upload.write(&bytes).await {
+            tracing::error!("ERROR UPLOADING FILE - {}", err);
+            upload.abort().await;
+            return Err(UploadErrorReason::StorageFailed);
+        }
+        chunk = match is_read {
+            true => None,
+            false => match read_chunk(&mut stream).await {
+                Ok(chunk) => chunk,
+                Err(reason) => {
+                    upload.abort().await;
+                    return Err(reason);
+                }
+            },
+        };
+    }
+
+    match upload.finish().await {
+        Ok((meta, final_hash, size)) => Ok((verified, size, final_hash, meta.etag)),
+        Err(err) => {
+            tracing::error!("ERROR UPLOADING FILE - {}", err);
+            Err(UploadErrorReason::StorageFailed)
+        }
+    }
+}
+
+/// Writes a plaintext stream to storage at `key`, sealed under `encryption` when given.
+/// Returns the size, hash and etag of what was stored.
+pub async fn upload_reader(
+    state: &AppState,
+    mut body: ObjectBody,
+    key: &str,
+    options: PutOptions,
+    encryption: Option<&BlobEncryption>,
+) -> Result<(i64, Hash, Option<String>), UploadErrorReason> {
+    let mut upload = StreamingUpload::new(state.storage.as_ref(), key, options);
+    if let Some(encryption) = encryption {
+        match object_cipher(state, encryption) {
+            Ok(cipher) => upload = upload.encrypted(cipher),
+            Err(err) => {
+                AppError::storage_error(err);
+                return Err(UploadErrorReason::EncryptionFailed);
+            }
+        }
+    }
+
+    let mut buffer = vec![0; ENCRYPTION_CHUNK_SIZE];
+    loop {
+        let read = match body.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) => {
+                tracing::error!("ERROR READING UPLOAD - {}", err);
+                upload.abort().await;
+                return Err(UploadErrorReason::ReadFailed);
+            }
+        };
+        if let Err(err) = upload.write(&buffer[..read]).await {
+            tracing::error!("ERROR UPLOADING FILE - {}", err);
+            upload.abort().await;
+            return Err(UploadErrorReason::StorageFailed);
+        }
+    }
+
+    match upload.finish().await {
+        Ok((meta, hash, size)) => Ok((size, hash, meta.etag)),
+        Err(err) => {
+            tracing::error!("ERROR UPLOADING FILE - {}", err);
+            Err(UploadErrorReason::StorageFailed)
+        }
+    }
+}
+
+/// Files of one upload request on their way into `files`, inserted in one transaction.
+/// Objects that became blobs are only safe once it commits, the rest are duplicates of
+/// stored content or leftovers, deleted either way.
+pub struct UploadBatch {
+    pub owner_id: Uuid,
+    pub is_public: bool,
+    pub conflict: ConflictPolicy,
+    /// `path` of top level rows.
+    root_path: String,
+    /// Paths whose folders exist, so each folder is only created once per request.
+    created_folders: HashSet<String>,
+    stored_keys: Vec<String>,
+    discarded_keys: Vec<String>,
+}
+
+impl UploadBatch {
+    pub fn new(
+        owner_id: Uuid,
+        root_path: String,
+        is_public: bool,
+        conflict: ConflictPolicy,
+    ) -> Self {
+        Self {
+            owner_id,
+            is_public,
+            conflict,
+            root_path,
+            created_folders: HashSet::new(),
+            stored_keys: vec![],
+            discarded_keys: vec![],
         }
-        hasher.update(&buffer[..read]);
     }
 
-    let final_hash = hasher.finalize();
-
-    let upload = state
-        .s3_client
-        .put_object()
-        .bucket(&state.s3_name)
-        .key(file_path)
-        .body(body)
-        .acl(match is_public {
-            true => aws_sdk_s3::types::ObjectCannedAcl::PublicRead,
-            false => aws_sdk_s3::types::ObjectCannedAcl::Private,
-        })
-        .content_type(&content_type)
-        .send()
-        .await;
-
-    if upload.is_ok() {
-        Ok((
-            FileTypes::from_mime(content_type.as_str()),
+    /// Creates the folders leading to `path`. Returns false when a file holds the name of one.
+    pub async fn create_folders(
+        &mut self,
+        client: &impl GenericClient,
+        path: &str,
+    ) -> Result<bool, DBError> {
+        if self.created_folders.contains(path) {
+            return Ok(true);
+        }
+        let created = create_parent_folders(
+            client,
+            &self.owner_id,
+            &self.root_path,
+            path,
+            self.is_public,
+        )
+        .await?;
+        if created {
+            self.created_folders.insert(path.to_string());
+        }
+        Ok(created)
+    }
+
+    /// Inserts `file` below its folders. `None` when a file holds the name of a folder on
+    /// the way, in which case the upload is dropped.
+    pub async fn place(
+        &mut self,
+        client: &impl GenericClient,
+        file: &NewFile,
+    ) -> Result<Option<InsertedFile>, DBError> {
+        let inserted = match self.create_folders(client, &file.path).await {
+            Ok(true) => insert_file(client, file, self.conflict).await.map(Some),
+            Ok(false) => Ok(None),
+            Err(err) => Err(err),
+        };
+        match &inserted {
+            Ok(Some(inserted)) if inserted.is_stored => self.stored_keys.push(file.key.clone()),
+            _ => self.discarded_keys.push(file.key.clone()),
+        }
+        inserted
+    }
+
+    /// Drops an object that will not become a blob.
+    pub fn discard(&mut self, key: String) {
+        self.discarded_keys.push(key);
+    }
+
+    /// Deletes what is left over once the transaction committed.
+    pub async fn committed(self, state: &AppState) {
+        delete_objects(state, &self.discarded_keys).await;
+    }
+
+    /// Deletes every object of the batch after the transaction failed.
+    pub async fn rolled_back(mut self, state: &AppState) {
+        self.discarded_keys.append(&mut self.stored_keys);
+        delete_objects(state, &self.discarded_keys).await;
+    }
+}
+
+/// Expands the stored `archive` into a folder named after its `title`, inside `folder` given
+/// in the same form as the `path` query parameter. Every file entry becomes its own blob and
+/// row, typed by its extension. Entries fail on their own, while an archive that cannot be
+/// read or exceeds the extraction limits gives a single failed result. The archive object
+/// itself is discarded.
+#[allow(clippy::too_many_arguments)]
+pub async fn extract_archive(
+    state: &AppState,
+    client: &impl GenericClient,
+    batch: &mut UploadBatch,
+    keyring: Option<&Keyring>,
+    archive: StoredObject,
+    folder: &str,
+    title: &str,
+    field: &str,
+) -> Result<Vec<UploadedFile>, DBError> {
+    batch.discard(archive.key.clone());
+    let failed = |file_name: &str, reason| {
+        UploadedFile::failed(field.to_string(), Some(file_name.to_string()), reason)
+    };
+
+    let members = match read_zip_directory(state, &archive).await {
+        Ok(members) => members,
+        Err(err) => {
+            tracing::error!("ERROR READING ARCHIVE - {}", err);
+            return Ok(vec![failed(title, archive_failure(&err))]);
+        }
+    };
+    if let Err(err) = check_extraction_limits(&members) {
+        tracing::warn!("REFUSED ARCHIVE - {}", err);
+        return Ok(vec![failed(title, archive_failure(&err))]);
+    }
+
+    let stem = match title_stem(title) {
+        "" => title,
+        stem => stem,
+    };
+    let join = |paths: &[&str]| {
+        paths
+            .iter()
+            .map(|path| path.trim_matches('/'))
+            .filter(|path| !path.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    let archive_folder = FileQuery {
+        path: join(&[folder, stem]),
+        is_public: batch.is_public,
+        is_folder: true,
+    };
+    // * The folder is there even for an archive without entries
+    if !batch
+        .create_folders(client, &archive_folder.format_path(&state.root))
+        .await?
+    {
+        return Ok(vec![failed(title, UploadErrorReason::PathConflict)]);
+    }
+
+    let mut uploaded = vec![];
+    for member in members {
+        let Some((member_folder, member_title)) = split_relative_path(&member.name) else {
+            uploaded.push(failed(&member.name, UploadErrorReason::InvalidName));
+            continue;
+        };
+        let member_folder = FileQuery {
+            path: match member.is_folder {
+                true => join(&[&archive_folder.path, &member_folder, &member_title]),
+                false => join(&[&archive_folder.path, &member_folder]),
+            },
+            is_public: batch.is_public,
+            is_folder: true,
+        };
+        let path = member_folder.format_path(&state.root);
+        if member.is_folder {
+            if !batch.create_folders(client, &path).await? {
+                uploaded.push(failed(&member.name, UploadErrorReason::PathConflict));
+            }
+            continue;
+        }
+
+        let id = Uuid::new_v4();
+        let key = new_blob_key(&state.root, &id);
+        let encryption = match keyring.map(|keyring| keyring.new_encryption()).transpose() {
+            Ok(encryption) => encryption,
+            Err(err) => {
+                AppError::storage_error(err);
+                uploaded.push(failed(&member.name, UploadErrorReason::EncryptionFailed));
+                continue;
+            }
+        };
+        let mut body = match open_zip_member(state, &archive, &member).await {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::error!("ERROR OPENING ARCHIVE ENTRY - {}", err);
+                uploaded.push(failed(&member.name, archive_failure(&err)));
+                continue;
+            }
+        };
+        // * Entries carry no content type, so their names are checked against their content
+        let Ok(head) = read_head(&mut body).await else {
+            uploaded.push(failed(&member.name, UploadErrorReason::InvalidArchive));
+            continue;
+        };
+        let verified = verify_type(&head, &member_title, None);
+        if refuses_type(&member.name, &verified) {
+            uploaded.push(failed(&member.name, UploadErrorReason::TypeMismatch));
+            continue;
+        }
+        let body: ObjectBody = Box::pin(Cursor::new(head).chain(body));
+        let options = PutOptions {
+            content_type: Some(verified.file_type.content_type().to_string()),
+            is_public: batch.is_public,
+        };
+        let (size, hash, etag) =
+            match upload_reader(state, body, &key, options, encryption.as_ref()).await {
+                Ok(stored) => stored,
+                // * Reading an entry fails when it does not match the central directory
+                Err(UploadErrorReason::ReadFailed) => {
+                    uploaded.push(failed(&member.name, UploadErrorReason::InvalidArchive));
+                    continue;
+                }
+                Err(reason) => {
+                    uploaded.push(failed(&member.name, reason));
+                    continue;
+                }
+            };
+
+        let file = NewFile {
+            id,
+            title: member_title,
+            owner_id: batch.owner_id,
             size,
-            final_hash,
-        ))
-    } else {
-        tracing::error!("ERROR UPLOADING FILE - {}", upload.err().unwrap());
-        Err(false)
+            file_type: verified.file_type,
+            path,
+            is_public: batch.is_public,
+            hash,
+            key,
+            etag,
+            encryption,
+        };
+        match batch.place(client, &file).await? {
+            Some(inserted) => uploaded.push(
+                UploadedFile::stored(
+                    field.to_string(),
+                    member.name,
+                    &member_folder.format_path(""),
+                    inserted,
+                    &file,
+                )
+                .flagged(verified.mismatch),
+            ),
+            None => uploaded.push(failed(&member.name, UploadErrorReason::PathConflict)),
+        }
+    }
+    Ok(uploaded)
+}
+
+fn archive_failure(err: &ArchiveError) -> UploadErrorReason {
+    match err {
+        ArchiveError::LimitExceeded(_) => UploadErrorReason::ArchiveTooLarge,
+        ArchiveError::Storage(_) => UploadErrorReason::StorageFailed,
+        _ => UploadErrorReason::InvalidArchive,
     }
 }
@@ -14,3 +14,17 @@ pub fn camel_case_keys(value: Value) -> Value {
         other => other,
     }
 }
+
+/// `title` without its extension. A leading dot is part of the name, so `.env` is kept whole.
+pub fn title_stem(title: &str) -> &str {
+    match title.rfind('.').filter(|idx| *idx > 0) {
+        Some(idx) => &title[..idx],
+        None => title,
+    }
+}
+
+/// `title` with a copy number before its extension, like `report (1).pdf`.
+pub fn numbered_title(title: &str, number: usize) -> String {
+    let stem = title_stem(title);
+    format!("{} ({}){}", stem, number, &title[stem.len()..])
+}
@@ -0,0 +1,366 @@
+use std::io::{self, Cursor};
+
+use deadpool_postgres::{GenericClient, Object};
+use image::{
+    DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits,
+    codecs::jpeg::JpegEncoder, imageops::FilterType,
+};
+use once_cell::sync::Lazy;
+use strum::EnumIter;
+use tokio::{io::AsyncReadExt, sync::Semaphore, task::spawn_blocking};
+use tokio_postgres::{Error as DBError, Row};
+use uuid::Uuid;
+
+use crate::{
+    consts::{MAX_RENDER_ALLOC, MAX_RENDER_SOURCE_SIZE, RENDER_WORKERS},
+    enums::{
+        errors::{RenderError, StorageError},
+        file_enums::{FileTypes, ImageFit, ImageOutput},
+    },
+    models::{
+        file::{ImagePreset, ImageTransform, StoredObject},
+        state::AppState,
+        storage::PutOptions,
+    },
+    routes::file_routes::FileQuery,
+    utils::{
+        blob_utils::{blob_encryption, flush_object_deletions, legacy_key, queue_object_deletions},
+        crypto_utils::{keyring, open_object},
+        file_utils::StreamingUpload,
+    },
+};
+
+/// What images are rendered from: the current content of a file. Callers append `AND ...`.
+pub const IMAGE_SOURCE_QUERY: &str = "SELECT files.id, files.path, files.title, files.type,
+        files.size, files.version, files.is_public, blobs.key AS blob_key, blobs.key_id,
+        blobs.data_key, blobs.nonce
+    FROM files LEFT JOIN blobs ON blobs.hash = files.blob_hash
+    WHERE files.deleted_at IS NULL AND files.type <> 'folder'";
+
+/// The `image_variants` name of the copy of a file served without its metadata.
+pub const STRIPPED_VARIANT: &str = "stripped";
+
+// * Decoding is CPU bound, so only a few images are rendered at once
+static RENDER_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(RENDER_WORKERS));
+
+/// Tables of images rendered from a file. Rows are named per file, carry the file version
+/// they were rendered from and go with the file through the foreign key.
+#[derive(Debug, Clone, Copy, EnumIter)]
+pub enum Renderings {
+    /// `thumbnails`, named by `ThumbnailSize`.
+    Thumbnails,
+    /// `image_variants`, named by `ImageTransform::variant` or `STRIPPED_VARIANT`.
+    Variants,
+}
+
+impl Renderings {
+    pub fn table(&self) -> &'static str {
+        match self {
+            Renderings::Thumbnails => "thumbnails",
+            Renderings::Variants => "image_variants",
+        }
+    }
+
+    pub fn name_column(&self) -> &'static str {
+        match self {
+            Renderings::Thumbnails => "size",
+            Renderings::Variants => "variant",
+        }
+    }
+
+    // * Objects of each table live under their own folder of the storage root
+    fn folder(&self) -> &'static str {
+        match self {
+            Renderings::Thumbnails => "thumbnails",
+            Renderings::Variants => "variants",
+        }
+    }
+
+    /// Key of a new rendering. Every rendering gets its own key, so two renderings racing for
+    /// the same row never leave it pointing at the other's object or envelope.
+    fn key(&self, root: &str, file_id: &Uuid, version: i32, name: &str) -> String {
+        let folder = FileQuery {
+            path: String::from(self.folder()),
+            is_public: false,
+            is_folder: true,
+        };
+        format!(
+            "{}{}/{}/{}-{}",
+            folder.format_path(root),
+            file_id,
+            version,
+            name,
+            Uuid::new_v4().simple()
+        )
+    }
+
+    /// The rendering `name` of `file_id` made from its current `version`, selecting its
+    /// `key, type, byte_size` and the `key_id, data_key, nonce` of its envelope.
+    pub async fn current(
+        &self,
+        client: &impl GenericClient,
+        file_id: &Uuid,
+        version: i32,
+        name: &str,
+    ) -> Result<Option<Row>, RenderError> {
+        Ok(client
+            .query_opt(
+                &format!(
+                    "SELECT key, type, byte_size, key_id, data_key, nonce FROM {}
+                    WHERE file_id = $1 AND {} = $2 AND version = $3;",
+                    self.table(),
+                    self.name_column()
+                ),
+                &[file_id, &name, &version],
+            )
+            .await?)
+    }
+
+    /// Stores `body` as the rendering `name` of the file in `source`, a row selected by
+    /// `IMAGE_SOURCE_QUERY`, encrypted under its own data key when the source is and public
+    /// when it is. A rendering
+    /// of an older version never replaces a newer one; the object that lost is queued for
+    /// `flush_object_deletions`.
+    pub async fn store(
+        &self,
+        state: &AppState,
+        client: &impl GenericClient,
+        source: &Row,
+        name: &str,
+        body: &[u8],
+        file_type: &FileTypes,
+    ) -> Result<(), RenderError> {
+        let file_id: Uuid = source.get("id");
+        let version: i32 = source.get("version");
+        let key = self.key(&state.root, &file_id, version, name);
+        let encryption = match blob_encryption(source) {
+            Some(_) => Some(keyring(state)?.new_encryption()?),
+            None => None,
+        };
+        let options = PutOptions {
+            content_type: Some(file_type.content_type().to_string()),
+            is_public: source.get("is_public"),
+        };
+        let mut upload = StreamingUpload::new(state.storage.as_ref(), &key, options);
+        if let Some(encryption) = &encryption {
+            upload = upload.encrypted(keyring(state)?.cipher(encryption)?);
+        }
+        upload.write(body).await?;
+        upload.finish().await?;
+
+        let rows = client
+            .query(
+                &format!(
+                    "WITH previous AS (
+                        SELECT key FROM {table} WHERE file_id = $1 AND {name} = $2 FOR UPDATE
+                    )
+                    INSERT INTO {table}
+                        (file_id, {name}, version, key, type, byte_size, key_id, data_key, nonce)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                    ON CONFLICT (file_id, {name}) DO UPDATE SET
+                        version = EXCLUDED.version,
+                        key = EXCLUDED.key,
+                        type = EXCLUDED.type,
+                        byte_size = EXCLUDED.byte_size,
+                        key_id = EXCLUDED.key_id,
+                        data_key = EXCLUDED.data_key,
+                        nonce = EXCLUDED.nonce,
+                        created_at = CURRENT_TIMESTAMP
+                    WHERE {table}.version <= EXCLUDED.version
+                    RETURNING (SELECT key FROM previous) AS previous_key;",
+                    table = self.table(),
+                    name = self.name_column()
+                ),
+                &[
+                    &file_id,
+                    &name,
+                    &version,
+                    &key,
+                    &file_type.to_string(),
+                    &(body.len() as i64),
+                    &encryption.as_ref().map(|encryption| &encryption.key_id),
+                    &encryption.as_ref().map(|encryption| &encryption.data_key),
+                    &encryption.as_ref().map(|encryption| &encryption.nonce),
+                ],
+            )
+            .await?;
+        let replaced = match rows.first() {
+            Some(row) => row
+                .get::<_, Option<String>>("previous_key")
+                .filter(|previous| *previous != key),
+            None => Some(key),
+        };
+        if let Some(replaced) = replaced {
+            queue_object_deletions(client, &[replaced]).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the image of the file in `source`, a row selected by `IMAGE_SOURCE_QUERY`, turns it
+/// upright by its orientation tag and hands it to `render` off the async runtime.
+pub async fn render_image<T: Send + 'static>(
+    state: &AppState,
+    source: &Row,
+    render: impl FnOnce(DynamicImage) -> Result<T, RenderError> + Send + 'static,
+) -> Result<T, RenderError> {
+    let bytes = load_image(state, source).await?;
+    let _permit = RENDER_PERMITS
+        .acquire()
+        .await
+        .map_err(|err| StorageError::Io(io::Error::other(err)))?;
+    spawn_blocking(move || render(decode_image(bytes)?))
+        .await
+        .map_err(|err| StorageError::Io(io::Error::other(err)))?
+}
+
+/// Reads the content of the file in `source`, a row selected by `IMAGE_SOURCE_QUERY`, unless
+/// it is too large to render.
+pub async fn load_image(state: &AppState, source: &Row) -> Result<Vec<u8>, RenderError> {
+    let object = StoredObject {
+        key: source
+            .get::<_, Option<String>>("blob_key")
+            .unwrap_or_else(|| legacy_key(source)),
+        size: source.get("size"),
+        encryption: blob_encryption(source),
+    };
+    if object.size > MAX_RENDER_SOURCE_SIZE {
+        return Err(RenderError::TooLarge(object.size));
+    }
+
+    let mut bytes = Vec::with_capacity(object.size as usize);
+    open_object(state, &object)
+        .await?
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(StorageError::from)?;
+    Ok(bytes)
+}
+
+/// Decodes an image within the render limits and turns it upright by its orientation tag.
+pub fn decode_image(bytes: Vec<u8>) -> Result<DynamicImage, RenderError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(ImageError::IoError)?;
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_RENDER_ALLOC);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Encodes `image` as `format`, with `quality` for JPEG.
+pub fn encode_image(
+    image: &DynamicImage,
+    format: &ImageOutput,
+    quality: u8,
+) -> Result<Vec<u8>, RenderError> {
+    let mut body = vec![];
+    match format {
+        // * The WebP encoder only takes 8-bit RGB(A)
+        ImageOutput::Webp => match image.color().has_alpha() {
+            true => DynamicImage::from(image.to_rgba8())
+                .write_to(&mut Cursor::new(&mut body), ImageFormat::WebP)?,
+            false => DynamicImage::from(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut body), ImageFormat::WebP)?,
+        },
+        ImageOutput::Png => image.write_to(&mut Cursor::new(&mut body), ImageFormat::Png)?,
+        ImageOutput::Jpeg => {
+            JpegEncoder::new_with_quality(&mut body, quality).encode_image(&image.to_rgb8())?
+        }
+    }
+    Ok(body)
+}
+
+/// Resizes `image` as `transform` says and encodes it in its format.
+pub fn transform_image(
+    image: DynamicImage,
+    transform: &ImageTransform,
+) -> Result<Vec<u8>, RenderError> {
+    // * A missing edge follows the aspect ratio of the source
+    let scaled = |edge: u32, to: u32, from: u32| {
+        ((edge as u64 * to as u64 + from as u64 / 2) / from as u64).max(1) as u32
+    };
+    let (width, height) = match (transform.width, transform.height) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, scaled(image.height(), width, image.width())),
+        (None, Some(height)) => (scaled(image.width(), height, image.height()), height),
+        (None, None) => (image.width(), image.height()),
+    };
+
+    let image = match (width, height) == (image.width(), image.height()) {
+        true => image,
+        false => match transform.fit {
+            ImageFit::Contain => image.resize(width, height, FilterType::Lanczos3),
+            ImageFit::Cover => image.resize_to_fill(width, height, FilterType::Lanczos3),
+            ImageFit::Fill => image.resize_exact(width, height, FilterType::Lanczos3),
+        },
+    };
+    encode_image(&image, &transform.format, transform.quality)
+}
+
+/// The owner's transform presets, by name.
+pub async fn image_presets(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+) -> Result<Vec<ImagePreset>, DBError> {
+    let rows = client
+        .query(
+            "SELECT name, width, height, fit, format, quality FROM image_presets
+            WHERE owner_id = $1 ORDER BY name;",
+            &[owner_id],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ImagePreset {
+            name: row.get("name"),
+            transform: ImageTransform {
+                width: row.get::<_, Option<i32>>("width").map(|width| width as u32),
+                height: row
+                    .get::<_, Option<i32>>("height")
+                    .map(|height| height as u32),
+                fit: row.get::<_, &str>("fit").parse().unwrap_or_default(),
+                format: row.get::<_, &str>("format").parse().unwrap_or_default(),
+                quality: row.get::<_, i32>("quality") as u8,
+            },
+        })
+        .collect())
+}
+
+/// Deletes the owner's cached transforms that no preset allows anymore, once presets changed.
+/// Stripped copies are no transforms and stay.
+pub async fn prune_image_variants(
+    state: &AppState,
+    conn: &mut Object,
+    owner_id: &Uuid,
+) -> Result<(), DBError> {
+    let tx = conn.transaction().await?;
+    let variants = image_presets(&tx, owner_id)
+        .await?
+        .iter()
+        .map(|preset| preset.transform.variant())
+        .chain([String::from(STRIPPED_VARIANT)])
+        .collect::<Vec<_>>();
+    let keys = tx
+        .query(
+            "DELETE FROM image_variants USING files
+            WHERE files.id = image_variants.file_id AND files.owner_id = $1
+                AND image_variants.variant <> ALL($2)
+            RETURNING image_variants.key;",
+            &[owner_id, &variants],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("key"))
+        .collect::<Vec<String>>();
+    queue_object_deletions(&tx, &keys).await?;
+    tx.commit().await?;
+
+    let _ = flush_object_deletions(state, conn).await;
+    Ok(())
+}
@@ -1,3 +1,15 @@
+pub mod blob_utils;
+pub mod crypto_utils;
 pub mod db_utils;
+pub mod exif_utils;
+pub mod file_type_utils;
 pub mod file_utils;
 pub mod format_utils;
+pub mod image_utils;
+pub mod range_utils;
+pub mod sniff_utils;
+pub mod thumbnail_utils;
+pub mod trash_utils;
+pub mod tree_utils;
+pub mod tus_utils;
+pub mod zip_utils;
@@ -0,0 +1,46 @@
+use std::ops::Bound;
+
+use headers::Range;
+
+/// Resolves a `Range` header against the object size into inclusive `(start, end)` pairs,
+/// clamped to the object. Ranges that cannot be satisfied are dropped.
+pub fn resolve_ranges(range: &Range, size: u64) -> Vec<(u64, u64)> {
+    let last = match size {
+        0 => return vec![],
+        _ => size - 1,
+    };
+
+    range
+        .satisfiable_ranges(size)
+        .filter_map(|(start, end)| {
+            let start = match start {
+                Bound::Included(start) => start,
+                Bound::Excluded(start) => start + 1,
+                Bound::Unbounded => 0,
+            };
+            let end = match end {
+                Bound::Included(end) => end.min(last),
+                Bound::Excluded(end) => end.checked_sub(1)?.min(last),
+                Bound::Unbounded => last,
+            };
+            (start <= end).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Header that opens one part of a `multipart/byteranges` body.
+pub fn byteranges_part_header(
+    boundary: &str,
+    content_type: &str,
+    (start, end): (u64, u64),
+    size: u64,
+) -> String {
+    format!(
+        "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+        boundary, content_type, start, end, size
+    )
+}
+
+pub fn byteranges_end(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
@@ -0,0 +1,245 @@
+use crate::enums::file_enums::FileTypes;
+
+/// What the first bytes of a file say it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sniffed {
+    /// A format with a signature.
+    Type(FileTypes),
+    /// UTF-8 without control characters, which any text format may be.
+    Text,
+    /// Binary without a known signature.
+    Binary,
+    /// Nothing to look at.
+    Empty,
+}
+
+/// The type a file is stored with, and what it claimed to be when its content disagreed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedType {
+    pub file_type: FileTypes,
+    /// The extension or declared content type the content contradicts, as a type name or the
+    /// MIME type when the server has no name for it.
+    pub mismatch: Option<String>,
+}
+
+/// Recognizes a format by its signature in `head`, the first `SNIFF_SIZE` bytes of a file or
+/// all of a shorter one.
+pub fn sniff(head: &[u8]) -> Sniffed {
+    if head.is_empty() {
+        return Sniffed::Empty;
+    }
+    let starts = |signature: &[u8]| head.starts_with(signature);
+    let at =
+        |offset: usize, signature: &[u8]| head[offset.min(head.len())..].starts_with(signature);
+
+    let file_type = if starts(b"\x89PNG\r\n\x1a\n") {
+        FileTypes::Png
+    } else if starts(b"\xff\xd8\xff") {
+        FileTypes::Jpeg
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        FileTypes::Gif
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        FileTypes::Webp
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        FileTypes::Wav
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        FileTypes::Avi
+    } else if starts(b"BM") && head.len() >= 14 && at(6, b"\0\0\0\0") {
+        FileTypes::Bmp
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        FileTypes::Tiff
+    } else if starts(b"8BPS") {
+        FileTypes::Psd
+    } else if starts(b"%PDF-") {
+        FileTypes::Pdf
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        FileTypes::Zip
+    } else if starts(b"Rar!\x1a\x07") {
+        FileTypes::Rar
+    } else if starts(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+        FileTypes::Doc
+    } else if starts(b"OggS") {
+        FileTypes::Ogg
+    } else if starts(b"fLaC") {
+        FileTypes::Flac
+    } else if starts(b"ID3") || (head.len() >= 2 && head[0] == 0xff && head[1] & 0xf6 == 0xf2) {
+        FileTypes::Mp3
+    } else if head.len() >= 2 && head[0] == 0xff && head[1] & 0xf6 == 0xf0 {
+        FileTypes::Aac
+    } else if at(4, b"ftyp") {
+        // * ISO media names its flavour in the major brand
+        match head.get(8..12).unwrap_or_default() {
+            b"heic" | b"heix" | b"hevc" | b"heim" | b"heis" | b"mif1" | b"msf1" => FileTypes::Heic,
+            b"qt  " => FileTypes::Mov,
+            b"M4A " | b"M4B " | b"M4P " => FileTypes::M4p,
+            _ => FileTypes::Mp4,
+        }
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        FileTypes::Webm
+    } else if starts(b"\0\x01\0\0") || starts(b"true") {
+        FileTypes::Ttf
+    } else if starts(b"OTTO") {
+        FileTypes::Otf
+    } else if starts(b"wOFF") {
+        FileTypes::Woff
+    } else if starts(b"MZ") {
+        FileTypes::Exe
+    } else if is_text(head) {
+        return Sniffed::Text;
+    } else {
+        return Sniffed::Binary;
+    };
+    Sniffed::Type(file_type)
+}
+
+// * A character cut off by the end of `head` still counts as text
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    !valid
+        .chars()
+        .any(|char| char.is_control() && !matches!(char, '\n' | '\r' | '\t' | '\x0c'))
+}
+
+/// Types with a signature `sniff` knows, which content without it cannot be.
+fn has_signature(file_type: &FileTypes) -> bool {
+    matches!(
+        file_type,
+        FileTypes::Png
+            | FileTypes::Jpg
+            | FileTypes::Jpeg
+            | FileTypes::Gif
+            | FileTypes::Webp
+            | FileTypes::Wav
+            | FileTypes::Avi
+            | FileTypes::Bmp
+            | FileTypes::Tiff
+            | FileTypes::Psd
+            | FileTypes::Pdf
+            | FileTypes::Zip
+            | FileTypes::Docx
+            | FileTypes::Xlsx
+            | FileTypes::Rar
+            | FileTypes::Doc
+            | FileTypes::Xls
+            | FileTypes::Ogg
+            | FileTypes::Opus
+            | FileTypes::Flac
+            | FileTypes::Mp4
+            | FileTypes::Mov
+            | FileTypes::M4p
+            | FileTypes::Heic
+            | FileTypes::Webm
+            | FileTypes::Ttf
+            | FileTypes::Otf
+            | FileTypes::Woff
+            | FileTypes::Exe
+    )
+}
+
+/// Types stored as plain text.
+fn is_text_type(file_type: &FileTypes) -> bool {
+    matches!(
+        file_type,
+        FileTypes::Txt
+            | FileTypes::Svg
+            | FileTypes::Json
+            | FileTypes::Csv
+            | FileTypes::Md
+            | FileTypes::Mdx
+            | FileTypes::Xml
+            | FileTypes::Yml
+            | FileTypes::Sql
+            | FileTypes::Js
+            | FileTypes::Ts
+            | FileTypes::Jsx
+            | FileTypes::Tsx
+            | FileTypes::Css
+            | FileTypes::Scss
+            | FileTypes::Sass
+            | FileTypes::Py
+            | FileTypes::Rb
+            | FileTypes::Php
+            | FileTypes::Sh
+            | FileTypes::Java
+            | FileTypes::Cs
+            | FileTypes::Html
+    )
+}
+
+/// Whether a file claimed to be `claimed` may hold content sniffed as `sniffed`. Containers
+/// shared by several formats match all of them.
+fn is_consistent(sniffed: &Sniffed, claimed: &FileTypes) -> bool {
+    match sniffed {
+        Sniffed::Empty => true,
+        Sniffed::Text => !has_signature(claimed),
+        Sniffed::Binary => !has_signature(claimed) && !is_text_type(claimed),
+        Sniffed::Type(file_type) => {
+            file_type == claimed
+                || matches!(
+                    (file_type, claimed),
+                    (FileTypes::Jpeg, FileTypes::Jpg)
+                        | (FileTypes::Zip, FileTypes::Docx | FileTypes::Xlsx)
+                        | (FileTypes::Doc, FileTypes::Xls)
+                        | (FileTypes::Ogg, FileTypes::Opus)
+                        | (FileTypes::Tiff, FileTypes::Raw)
+                        | (FileTypes::Mp3, FileTypes::Aac)
+                        | (
+                            FileTypes::Mp4 | FileTypes::Mov | FileTypes::M4p,
+                            FileTypes::Mp4 | FileTypes::Mov | FileTypes::M4p
+                        )
+                )
+                // * Formats the server has no name for cannot be told apart
+                || matches!(claimed, FileTypes::Other(_))
+        }
+    }
+}
+
+/// Checks what `head` holds against the extension of `title` and the `declared` content type.
+/// Claims the content agrees with decide the stored type, the extension first; otherwise the
+/// content does, and the first contradicted claim is reported.
+pub fn verify_type(head: &[u8], title: &str, declared: Option<&str>) -> VerifiedType {
+    let sniffed = sniff(head);
+    let from_extension = title
+        .rsplit_once('.')
+        .map(|(_, extension)| FileTypes::from_extension(&extension.to_lowercase()))
+        .filter(|file_type| !matches!(file_type, FileTypes::Other(_)));
+    let from_mime = declared.map(FileTypes::from_mime);
+    // * Declared types the server has no name for are kept, unless the content has one
+    let claims = from_extension
+        .into_iter()
+        .chain(from_mime)
+        .collect::<Vec<_>>();
+
+    let mismatch = claims
+        .iter()
+        .find(|claimed| !is_consistent(&sniffed, claimed))
+        .map(|claimed| match claimed {
+            FileTypes::Other(mime) => mime.clone(),
+            claimed => claimed.to_string(),
+        });
+    let agreed = claims
+        .into_iter()
+        .filter(|claimed| is_consistent(&sniffed, claimed))
+        .find(|claimed| {
+            !matches!(claimed, FileTypes::Other(_)) || !matches!(sniffed, Sniffed::Type(_))
+        });
+
+    let file_type = match (agreed, sniffed) {
+        (Some(file_type), _) => file_type,
+        (None, Sniffed::Type(file_type)) => file_type,
+        (None, Sniffed::Text) => FileTypes::Txt,
+        (None, Sniffed::Binary | Sniffed::Empty) => {
+            FileTypes::from_mime("application/octet-stream")
+        }
+    };
+    VerifiedType {
+        file_type,
+        mismatch,
+    }
+}
@@ -0,0 +1,115 @@
+use deadpool_postgres::GenericClient;
+use image::DynamicImage;
+use strum::IntoEnumIterator;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+use crate::{
+    consts::THUMBNAIL_QUALITY,
+    enums::{
+        errors::RenderError,
+        file_enums::{FileTypes, ImageOutput, ThumbnailSize},
+    },
+    models::state::AppState,
+    utils::{
+        blob_utils::flush_object_deletions,
+        exif_utils::extract_photo_metadata,
+        image_utils::{IMAGE_SOURCE_QUERY, Renderings, encode_image, render_image},
+    },
+};
+
+/// Renders every thumbnail size of the file in `source`, a row selected by
+/// `IMAGE_SOURCE_QUERY`, and records them. Replaced thumbnail objects are queued for
+/// `flush_object_deletions`.
+pub async fn generate_thumbnails(
+    state: &AppState,
+    client: &impl GenericClient,
+    source: &Row,
+) -> Result<(), RenderError> {
+    let rendered = render_image(state, source, render_thumbnails).await?;
+    for (size, body, file_type) in rendered {
+        Renderings::Thumbnails
+            .store(state, client, source, &size.to_string(), &body, &file_type)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Renders thumbnails of the images among `ids` and reads their photo metadata in the
+/// background, so uploads return without waiting for them. Failures are logged; a missing
+/// thumbnail is rendered again on request.
+pub fn spawn_image_processing(state: &AppState, ids: Vec<Uuid>) {
+    if ids.is_empty() {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        let mut conn = match state.get_db_conn().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                tracing::error!("NO CONNECTION FOR THUMBNAILS - {}", err.message);
+                return;
+            }
+        };
+        let sources = conn
+            .query(
+                &format!("{} AND files.id = ANY($1);", IMAGE_SOURCE_QUERY),
+                &[&ids],
+            )
+            .await;
+        let sources = match sources {
+            Ok(sources) => sources,
+            Err(err) => {
+                tracing::error!("ERROR LOADING THUMBNAIL SOURCES - {}", err);
+                return;
+            }
+        };
+
+        for source in sources {
+            let file_type: FileTypes = source.get("type");
+            if file_type.has_exif()
+                && let Err(err) = extract_photo_metadata(&state, &conn, &source).await
+            {
+                tracing::error!(
+                    "ERROR READING METADATA OF {} - {}",
+                    source.get::<_, Uuid>("id"),
+                    err
+                );
+            }
+            if !file_type.is_renderable() {
+                continue;
+            }
+            if let Err(err) = generate_thumbnails(&state, &conn, &source).await {
+                tracing::error!(
+                    "ERROR RENDERING THUMBNAILS OF {} - {}",
+                    source.get::<_, Uuid>("id"),
+                    err
+                );
+            }
+        }
+        let _ = flush_object_deletions(&state, &mut conn).await;
+    });
+}
+
+/// Encodes `image` in every `ThumbnailSize`. Images with transparency become PNGs, the rest
+/// JPEGs. Images are never scaled up.
+fn render_thumbnails(
+    image: DynamicImage,
+) -> Result<Vec<(ThumbnailSize, Vec<u8>, FileTypes)>, RenderError> {
+    ThumbnailSize::iter()
+        .map(|size| {
+            let edge = size.pixels();
+            let thumbnail = match image.width() <= edge && image.height() <= edge {
+                true => image.clone(),
+                false => image.thumbnail(edge, edge),
+            };
+
+            let format = match thumbnail.color().has_alpha() {
+                true => ImageOutput::Png,
+                false => ImageOutput::Jpeg,
+            };
+            let body = encode_image(&thumbnail, &format, THUMBNAIL_QUALITY)?;
+            Ok((size, body, format.file_type()))
+        })
+        .collect()
+}
@@ -0,0 +1,93 @@
+use deadpool_postgres::{GenericClient, Object};
+use jiff::{SignedDuration, Timestamp};
+use tokio_postgres::Error as DBError;
+use uuid::Uuid;
+
+use crate::{
+    consts::{TRASH_PURGE_BATCH, TRASH_RETENTION_DAYS},
+    enums::errors::AppError,
+    models::response::AppErrorResponse,
+    utils::blob_utils::{DELETED_FILE_COLUMNS, release_deleted_files},
+};
+
+/// Moves a row and, for a folder with `prefix`, every live row below it to the trash. The
+/// whole tree gets the same `deleted_at`, which is how `restore_tree` tells it apart from
+/// rows that were trashed on their own. Returns the number of rows trashed.
+pub async fn trash_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+) -> Result<u64, DBError> {
+    client
+        .execute(
+            "UPDATE files SET deleted_at = CURRENT_TIMESTAMP
+            WHERE owner_id = $1 AND deleted_at IS NULL AND (id = $2 OR starts_with(path, $3));",
+            &[owner_id, id, &prefix],
+        )
+        .await
+}
+
+/// Takes a trashed row and what was trashed along with it out of the trash. Meant to run in a
+/// transaction: a name taken in the meantime fails with a unique violation and nothing is
+/// restored. Returns the number of rows restored.
+pub async fn restore_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+    deleted_at: &Timestamp,
+) -> Result<u64, DBError> {
+    client
+        .execute(
+            "UPDATE files SET deleted_at = NULL
+            WHERE owner_id = $1
+                AND deleted_at = $4
+                AND (id = $2 OR starts_with(path, $3));",
+            &[owner_id, id, &prefix, deleted_at],
+        )
+        .await
+}
+
+/// Permanently deletes files trashed more than `TRASH_RETENTION_DAYS` ago, `TRASH_PURGE_BATCH`
+/// rows at a time. Their objects are queued for `flush_object_deletions`. Returns the number
+/// of rows deleted.
+pub async fn purge_trash(conn: &mut Object) -> Result<usize, AppErrorResponse> {
+    let cutoff = Timestamp::now() - SignedDuration::from_hours(*TRASH_RETENTION_DAYS * 24);
+    let mut purged = 0;
+
+    loop {
+        let tx = conn
+            .transaction()
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        let rows = tx
+            .query(
+                &format!(
+                    "DELETE FROM files
+                    WHERE id IN (
+                        SELECT id FROM files
+                        WHERE deleted_at < $1
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING {};",
+                    DELETED_FILE_COLUMNS
+                ),
+                &[&cutoff, &TRASH_PURGE_BATCH],
+            )
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        if rows.is_empty() {
+            break;
+        }
+
+        release_deleted_files(&tx, &rows)
+            .await
+            .map_err(|err| AppError::db_error(err))?;
+        tx.commit().await.map_err(|err| AppError::db_error(err))?;
+        purged += rows.len();
+    }
+
+    Ok(purged)
+}
@@ -0,0 +1,224 @@
+use deadpool_postgres::GenericClient;
+use jiff::Timestamp;
+use tokio_postgres::{Error as DBError, Row};
+use uuid::Uuid;
+
+use crate::{
+    enums::file_enums::CopyStatus, models::file::CopiedFile,
+    utils::blob_utils::DELETED_FILE_COLUMNS,
+};
+
+/// Value of the `path` column for a folder's children, from the folder's own `path` and `title`.
+pub fn folder_prefix(path: &str, title: &str) -> String {
+    format!("{}{}/", path, title)
+}
+
+/// Files in a tree that are still stored under their path, as `id, path, title` rows. `prefix`
+/// is the folder prefix when the root of the tree is a folder. Trashed rows are left out.
+pub async fn legacy_files_in_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+) -> Result<Vec<Row>, DBError> {
+    client
+        .query(
+            "SELECT id, path, title, is_public FROM files
+            WHERE owner_id = $1
+                AND blob_hash IS NULL
+                AND type <> 'folder'
+                AND deleted_at IS NULL
+                AND (id = $2 OR starts_with(path, $3));",
+            &[owner_id, id, &prefix],
+        )
+        .await
+}
+
+/// Gives a row a new `path` and `title`. For a folder, `prefix` is its current folder prefix
+/// and every live row below it follows; trashed rows keep the path they are restored to.
+/// Meant to run in a transaction: a name that is already taken fails with a unique violation
+/// and nothing moves. Returns the number of rows moved.
+pub async fn move_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+    path: &str,
+    title: &str,
+) -> Result<u64, DBError> {
+    let mut moved = client
+        .execute(
+            "UPDATE files SET path = $1, title = $2 WHERE id = $3 AND owner_id = $4;",
+            &[&path, &title, id, owner_id],
+        )
+        .await?;
+
+    if let Some(prefix) = prefix {
+        moved += client
+            .execute(
+                "UPDATE files SET path = $1 || substr(path, char_length($2) + 1)
+                WHERE owner_id = $3 AND deleted_at IS NULL AND starts_with(path, $2);",
+                &[&folder_prefix(path, title), &prefix, owner_id],
+            )
+            .await?;
+    }
+    Ok(moved)
+}
+
+/// Deletes a trashed row and, for a folder with `prefix`, what was trashed along with it,
+/// the rows below it sharing its `deleted_at`. Returns the deleted rows with
+/// `DELETED_FILE_COLUMNS` for `release_deleted_files`.
+pub async fn delete_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+    deleted_at: &Timestamp,
+) -> Result<Vec<Row>, DBError> {
+    client
+        .query(
+            &format!(
+                "DELETE FROM files
+                WHERE owner_id = $1
+                    AND (id = $2 OR (starts_with(path, $3) AND deleted_at = $4))
+                RETURNING {};",
+                DELETED_FILE_COLUMNS
+            ),
+            &[owner_id, id, &prefix, deleted_at],
+        )
+        .await
+}
+
+/// Creates the folders leading to `path` that do not exist yet, starting below `root_path`,
+/// the `path` of top level rows. Returns false when a file already holds the name of one of
+/// them, so nothing can be put below it.
+pub async fn create_parent_folders(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    root_path: &str,
+    path: &str,
+    is_public: bool,
+) -> Result<bool, DBError> {
+    let Some(relative) = path.strip_prefix(root_path) else {
+        return Ok(true);
+    };
+
+    let mut parent = root_path.to_string();
+    for title in relative.split('/').filter(|title| !title.is_empty()) {
+        let created = client
+            .execute(
+                "INSERT INTO files (title, owner_id, size, type, path, is_public)
+                VALUES ($1, $2, 0, 'folder', $3, $4)
+                ON CONFLICT (path, title, owner_id) WHERE deleted_at IS NULL DO NOTHING;",
+                &[&title, owner_id, &parent, &is_public],
+            )
+            .await?
+            == 1;
+        if !created {
+            let existing = client
+                .query_opt(
+                    "SELECT type FROM files
+                    WHERE owner_id = $1 AND path = $2 AND title = $3 AND deleted_at IS NULL;",
+                    &[owner_id, &parent, &title],
+                )
+                .await?;
+            if existing.is_some_and(|row| row.get::<_, &str>("type") != "folder") {
+                return Ok(false);
+            }
+        }
+        parent = folder_prefix(&parent, title);
+    }
+    Ok(true)
+}
+
+/// Splits an uploaded file name carrying a relative path, like `photos/2024/a.jpg`, into its
+/// folder, in the same form as the `path` query parameter, and its title. Backslashes count as
+/// separators. Returns `None` for names with `.` or `..` segments or no title.
+pub fn split_relative_path(name: &str) -> Option<(String, String)> {
+    let name = name.replace('\\', "/");
+    let segments = name
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let (title, folders) = segments.split_last()?;
+    if segments
+        .iter()
+        .any(|segment| *segment == "." || *segment == "..")
+    {
+        return None;
+    }
+    Some((folders.join("/"), title.to_string()))
+}
+
+/// Copies a row and, for a folder with `prefix`, everything below it, to `path` and `title`.
+/// Copies point at the same blobs, so no content is duplicated. Each item is inserted on its
+/// own: a name already taken at the destination is reported and the rest carry on, so a
+/// folder copied onto an existing one merges into it.
+pub async fn copy_tree(
+    client: &impl GenericClient,
+    owner_id: &Uuid,
+    id: &Uuid,
+    prefix: Option<&str>,
+    path: &str,
+    title: &str,
+) -> Result<Vec<CopiedFile>, DBError> {
+    let rows = client
+        .query(
+            "SELECT id, path, title, type, blob_hash FROM files
+            WHERE owner_id = $1 AND deleted_at IS NULL AND (id = $2 OR starts_with(path, $3))
+            ORDER BY char_length(path), title;",
+            &[owner_id, id, &prefix],
+        )
+        .await?;
+    let copy_prefix = folder_prefix(path, title);
+
+    let mut copied = Vec::with_capacity(rows.len());
+    for row in rows {
+        let source_id: Uuid = row.get("id");
+        let (copy_path, copy_title) = match (source_id == *id, prefix) {
+            (false, Some(prefix)) => (
+                format!(
+                    "{}{}",
+                    copy_prefix,
+                    &row.get::<_, &str>("path")[prefix.len()..]
+                ),
+                row.get("title"),
+            ),
+            _ => (path.to_string(), title.to_string()),
+        };
+        let is_missing = row.get::<_, &str>("type") != "folder"
+            && row.get::<_, Option<&str>>("blob_hash").is_none();
+
+        let copy_id = match is_missing {
+            true => None,
+            false => client
+                .query_opt(
+                    "INSERT INTO files
+                        (title, owner_id, size, type, path, is_public, hash, bucket_id, etag, blob_hash,
+                        camera_make, camera_model, taken_at, image_width, image_height, orientation,
+                        gps_latitude, gps_longitude)
+                    SELECT $1, owner_id, size, type, $2, is_public, hash, bucket_id, etag, blob_hash,
+                        camera_make, camera_model, taken_at, image_width, image_height, orientation,
+                        gps_latitude, gps_longitude
+                    FROM files WHERE id = $3
+                    ON CONFLICT (path, title, owner_id) WHERE deleted_at IS NULL DO NOTHING
+                    RETURNING id;",
+                    &[&copy_title, &copy_path, &source_id],
+                )
+                .await?
+                .map(|row| row.get("id")),
+        };
+        copied.push(CopiedFile {
+            source_id,
+            id: copy_id,
+            path: copy_path,
+            title: copy_title,
+            status: match (is_missing, copy_id) {
+                (true, _) => CopyStatus::Missing,
+                (false, Some(_)) => CopyStatus::Copied,
+                (false, None) => CopyStatus::Exists,
+            },
+        });
+    }
+    Ok(copied)
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use axum::body::Body;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::{Bytes, BytesMut};
+use http_body_util::BodyExt;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use crate::{
+    consts::{ENCRYPTION_CHUNK_SIZE, TUS_LOCK_TTL, TUS_UPLOAD_TTL, UPLOAD_PART_SIZE},
+    enums::errors::AppError,
+    models::{
+        file::{NewFile, StoredObject},
+        response::AppErrorResponse,
+        state::AppState,
+        storage::PutOptions,
+        upload::TusUpload,
+    },
+    utils::{
+        blob_utils::record_file,
+        crypto_utils::{chunk_count, object_cipher},
+        file_utils::{hash_object, refuses_type, verify_object},
+    },
+};
+
+/// Parses `Upload-Metadata`: comma separated pairs of a key and an optional base64 value.
+pub fn parse_metadata(header: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let mut split = pair.splitn(2, ' ');
+        let key = split.next()?.to_string();
+        let value = match split.next() {
+            Some(value) => String::from_utf8(STANDARD.decode(value.trim()).ok()?).ok()?,
+            None => String::from(""),
+        };
+        metadata.insert(key, value);
+    }
+    Some(metadata)
+}
+
+pub async fn get_upload(
+    state: &AppState,
+    id: &Uuid,
+) -> Result<Option<TusUpload>, AppErrorResponse> {
+    let upload = state
+        .cache
+        .get(&TusUpload::cache_key(id))
+        .await
+        .map_err(|err| AppError::dfly_error(err))?;
+
+    match upload {
+        Some(upload) => serde_json::from_str(&upload)
+            .map(Some)
+            .map_err(|err| AppError::critical_error(err)),
+        None => Ok(None),
+    }
+}
+
+pub async fn save_upload(state: &AppState, upload: &TusUpload) -> Result<(), AppErrorResponse> {
+    let value = serde_json::to_string(upload).map_err(|err| AppError::critical_error(err))?;
+    state
+        .cache
+        .set_ex(&TusUpload::cache_key(&upload.id), &value, TUS_UPLOAD_TTL)
+        .await
+        .map_err(|err| AppError::dfly_error(err))
+}
+
+/// Guards an upload against concurrent PATCH requests. Returns false if another request holds it.
+pub async fn lock_upload(state: &AppState, id: &Uuid) -> Result<bool, AppErrorResponse> {
+    state
+        .cache
+        .set_nx_ex(&TusUpload::lock_key(id), "1", TUS_LOCK_TTL)
+        .await
+        .map_err(|err| AppError::dfly_error(err))
+}
+
+pub async fn unlock_upload(state: &AppState, id: &Uuid) {
+    if let Err(err) = state.cache.del(&TusUpload::lock_key(id)).await {
+        AppError::dfly_error(err);
+    }
+}
+
+/// Appends a PATCH body to the upload. Full parts go straight to storage, the remainder is
+/// kept as a pending object until the next request. If the client disconnects midway the
+/// bytes received so far are kept, so the client can resume from the new offset.
+pub async fn append_upload(
+    state: &AppState,
+    upload: &mut TusUpload,
+    mut body: Body,
+) -> Result<(), AppErrorResponse> {
+    let cipher = upload
+        .encryption
+        .as_ref()
+        .map(|encryption| object_cipher(state, encryption))
+        .transpose()
+        .map_err(|err| AppError::storage_error(err))?;
+    let previous_pending = (upload.pending_len() > 0).then(|| upload.pending_key());
+    let mut buffer = BytesMut::new();
+    if let Some(key) = &previous_pending {
+        let mut object = state
+            .storage
+            .get_object(key)
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+        let mut pending = Vec::with_capacity(object.meta.size as usize);
+        object
+            .body
+            .read_to_end(&mut pending)
+            .await
+            .map_err(|err| AppError::critical_error(err))?;
+        if let Some(cipher) = &cipher {
+            pending = cipher
+                .open(&pending)
+                .map_err(|err| AppError::storage_error(err))?;
+        }
+        buffer.extend_from_slice(&pending);
+    }
+
+    while let Some(frame) = body.frame().await {
+        let chunk = match frame {
+            Ok(frame) => match frame.into_data() {
+                Ok(chunk) => chunk,
+                Err(_) => continue,
+            },
+            Err(err) => {
+                tracing::warn!("TUS PATCH INTERRUPTED - {} - {}", upload.id, err);
+                break;
+            }
+        };
+
+        if upload.offset + chunk.len() as i64 > upload.length {
+            return Err(AppError::bad_request_response(format!(
+                "PATCH for upload {} exceeds Upload-Length",
+                upload.id
+            )));
+        }
+        upload.offset += chunk.len() as i64;
+        buffer.extend_from_slice(&chunk);
+
+        while buffer.len() >= UPLOAD_PART_SIZE {
+            upload_part(state, upload, buffer.split_to(UPLOAD_PART_SIZE).freeze()).await?;
+            state
+                .cache
+                .set_ex(&TusUpload::lock_key(&upload.id), "1", TUS_LOCK_TTL)
+                .await
+                .map_err(|err| AppError::dfly_error(err))?;
+        }
+    }
+
+    if upload.offset == upload.length {
+        return complete_upload(state, upload, buffer.freeze()).await;
+    }
+
+    // * The new pending object is written under a new key before the state points at it,
+    // * so a failure at any step leaves the previous state intact
+    if !buffer.is_empty() {
+        let pending = match &cipher {
+            Some(cipher) => cipher
+                .seal(&buffer)
+                .map_err(|err| AppError::storage_error(err))?,
+            None => buffer.freeze(),
+        };
+        state
+            .storage
+            .put_object(&upload.pending_key(), pending, &PutOptions::default())
+            .await
+            .map_err(|err| AppError::storage_error(err))?;
+    }
+    save_upload(state, upload).await?;
+
+    if let Some(key) = previous_pending.filter(|key| *key != upload.pending_key())
+        && let Err(err) = state.storage.delete_object(&key).await
+    {
+        AppError::storage_error(err);
+    }
+    Ok(())
+}
+
+/// Encrypts bytes that start at the next part boundary. Parts are a whole number of chunks,
+/// so every part seals its own run of chunk indices.
+fn seal_part(state: &AppState, upload: &TusUpload, body: Bytes) -> Result<Bytes, AppErrorResponse> {
+    let Some(encryption) = &upload.encryption else {
+        return Ok(body);
+    };
+    let first_index = upload.parts.len() * (UPLOAD_PART_SIZE / ENCRYPTION_CHUNK_SIZE);
+    let last_index = chunk_count(upload.length as u64) - 1;
+    object_cipher(state, encryption)
+        .and_then(|cipher| cipher.encrypt_chunks(first_index as u32, last_index as u32, &body))
+        .map(Bytes::from)
+        .map_err(|err| AppError::storage_error(err))
+}
+
+async fn upload_part(
+    state: &AppState,
+    upload: &mut TusUpload,
+    body: Bytes,
+) -> Result<(), AppErrorResponse> {
+    let body = seal_part(state, upload, body)?;
+    let upload_id = match &upload.upload_id {
+        Some(upload_id) => upload_id.clone(),
+        None => {
+            let options = PutOptions {
+                content_type: Some(upload.content_type.clone()),
+                is_public: upload.is_public,
+            };
+            let upload_id = state
+                .storage
+                .create_multipart_upload(&upload.key, &options)
+                .await
+                .map_err(|err| AppError::storage_error(err))?;
+            upload.upload_id = Some(upload_id.clone());
+            upload_id
+        }
+    };
+
+    let part = state
+        .storage
+        .upload_part(&upload.key, &upload_id, upload.parts.len() as i32 + 1, body)
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+    upload.parts.push(part);
+    Ok(())
+}
+
+/// Writes the final object and records it in the `files` table the same way
+/// `upload_file_route` does, dropping the object if its content is already stored.
+pub async fn complete_upload(
+    state: &AppState,
+    upload: &mut TusUpload,
+    remainder: Bytes,
+) -> Result<(), AppErrorResponse> {
+    let meta = match upload.upload_id.clone() {
+        Some(upload_id) => {
+            if !remainder.is_empty() {
+                upload_part(state, upload, remainder).await?;
+            }
+            state
+                .storage
+                .complete_multipart_upload(&upload.key, &upload_id, &upload.parts)
+                .await
+                .map_err(|err| AppError::storage_error(err))?
+        }
+        None => {
+            let options = PutOptions {
+                content_type: Some(upload.content_type.clone()),
+                is_public: upload.is_public,
+            };
+            state
+                .storage
+                .put_object(&upload.key, seal_part(state, upload, remainder)?, &options)
+                .await
+                .map_err(|err| AppError::storage_error(err))?
+        }
+    };
+
+    let object = StoredObject {
+        key: upload.key.clone(),
+        size: upload.length,
+        encryption: upload.encryption.clone(),
+    };
+    // * The declared type only arrives as metadata, so the stored content is what decides
+    let verified = verify_object(state, &object, &upload.title, Some(&upload.content_type))
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+    if refuses_type(&upload.title, &verified) {
+        if let Err(err) = state.storage.delete_object(&upload.key).await {
+            AppError::storage_error(err);
+        }
+        discard_pending(state, upload).await;
+        state
+            .cache
+            .del(&TusUpload::cache_key(&upload.id))
+            .await
+            .map_err(|err| AppError::dfly_error(err))?;
+        return Err(AppError::bad_request_response(format!(
+            "Content of upload {} is {}, not {}",
+            upload.id,
+            verified.file_type,
+            verified.mismatch.unwrap_or_default()
+        )));
+    }
+
+    // * The hasher cannot be carried between requests, so the stored object is read back once
+    let hash = hash_object(state, &object)
+        .await
+        .map_err(|err| AppError::storage_error(err))?;
+
+    let file = NewFile {
+        id: upload.id,
+        title: upload.title.clone(),
+        owner_id: upload.owner_id,
+        size: upload.length,
+        file_type: verified.file_type,
+        path: upload.path.clone(),
+        is_public: upload.is_public,
+        hash,
+        key: upload.key.clone(),
+        etag: meta.etag,
+        encryption: upload.encryption.clone(),
+    };
+    record_file(state, file).await?;
+
+    upload.upload_id = None;
+    upload.parts.clear();
+    upload.completed = true;
+    save_upload(state, upload).await?;
+    discard_pending(state, upload).await;
+    Ok(())
+}
+
+/// Removes pending objects, including any left behind by failed requests.
+pub async fn discard_pending(state: &AppState, upload: &TusUpload) {
+    let pending = match state
+        .storage
+        .list_objects(Some(&upload.pending_prefix))
+        .await
+    {
+        Ok(pending) => pending,
+        Err(err) => {
+            AppError::storage_error(err);
+            return;
+        }
+    };
+
+    for object in pending {
+        if let Err(err) = state.storage.delete_object(&object.key).await {
+            AppError::storage_error(err);
+        }
+    }
+}
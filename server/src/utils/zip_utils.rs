@@ -0,0 +1,680 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use async_compression::tokio::bufread::DeflateDecoder;
+use crc32fast::Hasher;
+use jiff::{Timestamp, civil::DateTime, tz::TimeZone};
+use serde::Serialize;
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf, duplex, empty,
+};
+
+use crate::{
+    consts::{
+        ARCHIVE_PIPE_SIZE, MAX_ARCHIVE_ENTRIES, MAX_COMPRESSION_RATIO, MAX_EXTRACTED_SIZE,
+        MAX_ZIP_DIRECTORY_SIZE,
+    },
+    enums::errors::{ArchiveError, StorageError},
+    models::{file::StoredObject, state::AppState, storage::ObjectBody},
+    utils::crypto_utils::{open_object, open_object_range},
+};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+const LOCAL_HEADER_SIZE: u64 = 30;
+const END_SIZE: u64 = 22;
+const ZIP64_END_SIZE: u64 = 56;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+// * Sizes and CRC follow the data, names are UTF-8
+const FILE_FLAGS: u16 = 1 << 3 | 1 << 11;
+const FOLDER_FLAGS: u16 = 1 << 11;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// * Made by Unix, so external attributes carry the permission bits
+const VERSION_MADE_BY: u16 = 3 << 8 | VERSION_ZIP64;
+const FILE_ATTRIBUTES: u32 = 0o100644 << 16;
+const FOLDER_ATTRIBUTES: u32 = 0o040755 << 16 | 0x10;
+const U16_LIMIT: u64 = 0xFFFF;
+const U32_LIMIT: u64 = 0xFFFF_FFFF;
+
+/// One entry of an archive. Folder names end in `/` and have no content.
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u64,
+    pub modified: Timestamp,
+    pub is_folder: bool,
+}
+
+impl ZipEntry {
+    fn flags(&self) -> u16 {
+        if self.is_folder {
+            FOLDER_FLAGS
+        } else {
+            FILE_FLAGS
+        }
+    }
+
+    fn needs_zip64(&self) -> bool {
+        self.size >= U32_LIMIT
+    }
+
+    /// MS-DOS `(time, date)` of the entry in UTC, clamped to the 1980 epoch of the format.
+    fn dos_time(&self) -> (u16, u16) {
+        let time = self.modified.to_zoned(TimeZone::UTC).datetime();
+        if time.year() < 1980 {
+            return (0, 1 << 5 | 1);
+        }
+        (
+            (time.hour() as u16) << 11 | (time.minute() as u16) << 5 | (time.second() as u16 / 2),
+            ((time.year() - 1980) as u16) << 9 | (time.month() as u16) << 5 | time.day() as u16,
+        )
+    }
+}
+
+/// Lays out a ZIP64 archive of stored (uncompressed) entries as it is written front to back.
+/// Entry content is not held: callers write `begin_entry`, then exactly `size` bytes of
+/// content, then `end_entry`, and `finish` once every entry is in.
+#[derive(Default)]
+pub struct ZipWriter {
+    offset: u64,
+    entries: u64,
+    central_directory: Vec<u8>,
+    entry_offset: u64,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Local header of `entry`. Files announce a data descriptor since their CRC is only known
+    /// once the content went through.
+    pub fn begin_entry(&mut self, entry: &ZipEntry) -> Vec<u8> {
+        let (time, date) = entry.dos_time();
+        let zip64 = entry.needs_zip64();
+        let version = if zip64 {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        };
+        let mut header = Vec::with_capacity(30 + entry.name.len() + 20);
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, version);
+        put_u16(&mut header, entry.flags());
+        put_u16(&mut header, 0); // * stored
+        put_u16(&mut header, time);
+        put_u16(&mut header, date);
+        put_u32(&mut header, 0);
+        // * With a data descriptor the sizes here are zero, or masked when ZIP64 carries them
+        let size = if zip64 { U32_LIMIT as u32 } else { 0 };
+        put_u32(&mut header, size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, entry.name.len() as u16);
+        put_u16(&mut header, if zip64 { 20 } else { 0 });
+        header.extend_from_slice(entry.name.as_bytes());
+        if zip64 {
+            put_u16(&mut header, ZIP64_EXTRA_ID);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, 0);
+            put_u64(&mut header, 0);
+        }
+
+        self.entry_offset = self.offset;
+        self.offset += header.len() as u64;
+        header
+    }
+
+    /// Data descriptor closing `entry` after its content, empty for folders. Also records the
+    /// entry for the central directory.
+    pub fn end_entry(&mut self, entry: &ZipEntry, crc: u32) -> Vec<u8> {
+        let zip64 = entry.needs_zip64();
+        let mut descriptor = Vec::new();
+        if !entry.is_folder {
+            put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+            put_u32(&mut descriptor, crc);
+            if zip64 {
+                put_u64(&mut descriptor, entry.size);
+                put_u64(&mut descriptor, entry.size);
+            } else {
+                put_u32(&mut descriptor, entry.size as u32);
+                put_u32(&mut descriptor, entry.size as u32);
+            }
+        }
+        self.offset += entry.size + descriptor.len() as u64;
+
+        // * The central directory only carries ZIP64 values for the fields that overflow
+        let mut extra = Vec::new();
+        if zip64 {
+            put_u64(&mut extra, entry.size);
+            put_u64(&mut extra, entry.size);
+        }
+        if self.entry_offset >= U32_LIMIT {
+            put_u64(&mut extra, self.entry_offset);
+        }
+
+        let (time, date) = entry.dos_time();
+        let (version, extra_size) = match extra.is_empty() {
+            true => (VERSION_DEFAULT, 0),
+            false => (VERSION_ZIP64, 4 + extra.len() as u16),
+        };
+        let attributes = if entry.is_folder {
+            FOLDER_ATTRIBUTES
+        } else {
+            FILE_ATTRIBUTES
+        };
+        let header = &mut self.central_directory;
+        put_u32(header, CENTRAL_HEADER_SIGNATURE);
+        put_u16(header, VERSION_MADE_BY);
+        put_u16(header, version);
+        put_u16(header, entry.flags());
+        put_u16(header, 0);
+        put_u16(header, time);
+        put_u16(header, date);
+        put_u32(header, crc);
+        put_u32(header, entry.size.min(U32_LIMIT) as u32);
+        put_u32(header, entry.size.min(U32_LIMIT) as u32);
+        put_u16(header, entry.name.len() as u16);
+        put_u16(header, extra_size);
+        put_u16(header, 0); // * comment
+        put_u16(header, 0); // * disk
+        put_u16(header, 0); // * internal attributes
+        put_u32(header, attributes);
+        put_u32(header, self.entry_offset.min(U32_LIMIT) as u32);
+        header.extend_from_slice(entry.name.as_bytes());
+        if !extra.is_empty() {
+            put_u16(header, ZIP64_EXTRA_ID);
+            put_u16(header, extra.len() as u16);
+            header.extend_from_slice(&extra);
+        }
+
+        self.entries += 1;
+        descriptor
+    }
+
+    /// Central directory and end records. The ZIP64 end records are only added when a count,
+    /// size or offset does not fit the classic ones.
+    pub fn finish(self) -> Vec<u8> {
+        let directory_offset = self.offset;
+        let directory_size = self.central_directory.len() as u64;
+        let mut tail = self.central_directory;
+
+        if self.entries >= U16_LIMIT || directory_size >= U32_LIMIT || directory_offset >= U32_LIMIT
+        {
+            let end_offset = directory_offset + directory_size;
+            put_u32(&mut tail, ZIP64_END_SIGNATURE);
+            put_u64(&mut tail, 44);
+            put_u16(&mut tail, VERSION_MADE_BY);
+            put_u16(&mut tail, VERSION_ZIP64);
+            put_u32(&mut tail, 0);
+            put_u32(&mut tail, 0);
+            put_u64(&mut tail, self.entries);
+            put_u64(&mut tail, self.entries);
+            put_u64(&mut tail, directory_size);
+            put_u64(&mut tail, directory_offset);
+
+            put_u32(&mut tail, ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut tail, 0);
+            put_u64(&mut tail, end_offset);
+            put_u32(&mut tail, 1);
+        }
+
+        put_u32(&mut tail, END_SIGNATURE);
+        put_u16(&mut tail, 0);
+        put_u16(&mut tail, 0);
+        put_u16(&mut tail, self.entries.min(U16_LIMIT) as u16);
+        put_u16(&mut tail, self.entries.min(U16_LIMIT) as u16);
+        put_u32(&mut tail, directory_size.min(U32_LIMIT) as u32);
+        put_u32(&mut tail, directory_offset.min(U32_LIMIT) as u32);
+        put_u16(&mut tail, 0);
+        tail
+    }
+}
+
+/// Exact byte size of the archive `ZipWriter` produces for `entries`.
+pub fn archive_size<'a>(entries: impl IntoIterator<Item = &'a ZipEntry>) -> u64 {
+    let mut writer = ZipWriter::new();
+    let mut size = 0;
+    for entry in entries {
+        size += writer.begin_entry(entry).len() as u64;
+        size += entry.size;
+        size += writer.end_entry(entry, 0).len() as u64;
+    }
+    size + writer.finish().len() as u64
+}
+
+/// An archive entry and, for files, where its content is stored.
+pub struct ArchiveEntry {
+    pub entry: ZipEntry,
+    pub object: Option<StoredObject>,
+}
+
+/// Streams a ZIP archive of `entries`, reading each object only when its turn comes. The
+/// archive goes through a bounded pipe, so at most `ARCHIVE_PIPE_SIZE` bytes of it are held.
+/// A failing read ends the body early, which clients see as a truncated download.
+pub fn stream_archive(state: AppState, entries: Vec<ArchiveEntry>) -> ObjectBody {
+    let (reader, mut writer) = duplex(ARCHIVE_PIPE_SIZE);
+    tokio::spawn(async move {
+        if let Err(err) = write_archive(&state, entries, &mut writer).await {
+            tracing::error!("ARCHIVE STREAM FAILED - {}", err);
+        }
+    });
+    Box::pin(reader)
+}
+
+async fn write_archive(
+    state: &AppState,
+    entries: Vec<ArchiveEntry>,
+    out: &mut (impl AsyncWrite + Unpin),
+) -> Result<(), StorageError> {
+    let mut writer = ZipWriter::new();
+    let mut buffer = vec![0; ARCHIVE_PIPE_SIZE];
+
+    for ArchiveEntry { entry, object } in entries {
+        out.write_all(&writer.begin_entry(&entry)).await?;
+
+        let mut hasher = Hasher::new();
+        if let Some(object) = object {
+            let mut body = open_object(state, &object).await?;
+            let mut written = 0;
+            loop {
+                let read = body.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                out.write_all(&buffer[..read]).await?;
+                written += read as u64;
+            }
+            // * The layout was computed from the recorded size, a mismatch would corrupt it
+            if written != entry.size {
+                return Err(StorageError::Backend(format!(
+                    "{} has {} bytes, expected {}",
+                    object.key, written, entry.size
+                )));
+            }
+        }
+
+        out.write_all(&writer.end_entry(&entry, hasher.finalize()))
+            .await?;
+    }
+
+    out.write_all(&writer.finish()).await?;
+    out.shutdown().await?;
+    Ok(())
+}
+
+/// An entry of a stored archive, as its central directory lists it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipMember {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    /// `None` when the archive holds no valid date.
+    pub modified: Option<Timestamp>,
+    pub is_folder: bool,
+    #[serde(skip)]
+    method: u16,
+    #[serde(skip)]
+    flags: u16,
+    #[serde(skip)]
+    crc: u32,
+    #[serde(skip)]
+    offset: u64,
+}
+
+/// Reads the central directory of a stored archive with ranged reads: the end records from
+/// the tail of the object, then the directory they point to. Nothing else is fetched.
+pub async fn read_zip_directory(
+    state: &AppState,
+    object: &StoredObject,
+) -> Result<Vec<ZipMember>, ArchiveError> {
+    let size = object.size as u64;
+    if size < END_SIZE {
+        return Err(invalid("too short for an end of central directory"));
+    }
+    // * The end record sits before a comment of at most 64 KiB
+    let tail_start = size.saturating_sub(END_SIZE + U16_LIMIT);
+    let tail = read_range(state, object, tail_start, size - 1).await?;
+    let end = (0..=tail.len() - END_SIZE as usize)
+        .rev()
+        .find(|&at| le(&tail, at, 4) == Some(END_SIGNATURE as u64))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let field = |at: usize, width: usize| le(&tail, end + at, width).ok_or_else(truncated);
+    let mut entries = field(10, 2)?;
+    let mut directory_size = field(12, 4)?;
+    let mut directory_offset = field(16, 4)?;
+
+    // * Masked values mean the ZIP64 end record, found through the locator, holds them
+    if entries == U16_LIMIT || directory_size == U32_LIMIT || directory_offset == U32_LIMIT {
+        let locator = end.checked_sub(20).ok_or_else(truncated)?;
+        if le(&tail, locator, 4) == Some(ZIP64_LOCATOR_SIGNATURE as u64) {
+            let record_offset = le(&tail, locator + 8, 8).ok_or_else(truncated)?;
+            let record_end = checked_end(record_offset, ZIP64_END_SIZE)?;
+            if record_end > size {
+                return Err(truncated());
+            }
+            let record = read_range(state, object, record_offset, record_end - 1).await?;
+            if le(&record, 0, 4) != Some(ZIP64_END_SIGNATURE as u64) {
+                return Err(invalid("broken ZIP64 end of central directory"));
+            }
+            let field = |at: usize| le(&record, at, 8).ok_or_else(truncated);
+            entries = field(32)?;
+            directory_size = field(40)?;
+            directory_offset = field(48)?;
+        }
+    }
+
+    if directory_size > MAX_ZIP_DIRECTORY_SIZE {
+        return Err(ArchiveError::LimitExceeded(format!(
+            "central directory of {} bytes",
+            directory_size
+        )));
+    }
+    let directory_end = checked_end(directory_offset, directory_size)?;
+    if directory_end > size {
+        return Err(truncated());
+    }
+    let directory = match directory_size {
+        0 => vec![],
+        _ => read_range(state, object, directory_offset, directory_end - 1).await?,
+    };
+
+    let mut members = vec![];
+    let mut at = 0;
+    // * Every header takes at least 46 bytes, so a lying count stops at the directory's end
+    while (members.len() as u64) < entries {
+        let member = parse_central_header(&directory, &mut at, directory_offset)?;
+        members.push(member);
+    }
+    Ok(members)
+}
+
+/// Parses the header at `at` and moves past it. Entries have to end before `directory_offset`,
+/// where their content would run into the directory.
+fn parse_central_header(
+    directory: &[u8],
+    at: &mut usize,
+    directory_offset: u64,
+) -> Result<ZipMember, ArchiveError> {
+    let start = *at;
+    let field =
+        |offset: usize, width: usize| le(directory, start + offset, width).ok_or_else(truncated);
+    if field(0, 4)? != CENTRAL_HEADER_SIGNATURE as u64 {
+        return Err(invalid("broken central directory header"));
+    }
+    let flags = field(8, 2)? as u16;
+    let method = field(10, 2)? as u16;
+    let (time, date) = (field(12, 2)? as u16, field(14, 2)? as u16);
+    let crc = field(16, 4)? as u32;
+    let mut compressed_size = field(20, 4)?;
+    let mut size = field(24, 4)?;
+    let name_size = field(28, 2)? as usize;
+    let extra_size = field(30, 2)? as usize;
+    let comment_size = field(32, 2)? as usize;
+    let mut offset = field(42, 4)?;
+
+    let name_start = start + 46;
+    let extra_start = name_start + name_size;
+    let end = extra_start + extra_size + comment_size;
+    if end > directory.len() {
+        return Err(truncated());
+    }
+    // * Names without the UTF-8 flag are CP437, which matches UTF-8 for the ASCII names
+    // * that make up nearly every archive
+    let name = String::from_utf8_lossy(&directory[name_start..extra_start]).into_owned();
+
+    // * The ZIP64 extra field holds, in order, only the values masked in the header
+    let extra = &directory[extra_start..extra_start + extra_size];
+    let mut block = 0;
+    while let (Some(id), Some(block_size)) = (le(extra, block, 2), le(extra, block + 2, 2)) {
+        let data_start = block + 4;
+        block = data_start + block_size as usize;
+        if id != ZIP64_EXTRA_ID as u64 {
+            continue;
+        }
+        let mut value = data_start;
+        for field in [&mut size, &mut compressed_size, &mut offset] {
+            if *field == U32_LIMIT {
+                *field = le(extra, value, 8)
+                    .filter(|_| value + 8 <= block)
+                    .ok_or_else(truncated)?;
+                value += 8;
+            }
+        }
+    }
+
+    // * Sizes and offsets are trusted by the extraction limits from here on
+    if checked_end(checked_end(offset, LOCAL_HEADER_SIZE)?, compressed_size)? > directory_offset {
+        return Err(invalid("entry outside the archive"));
+    }
+
+    *at = end;
+    Ok(ZipMember {
+        is_folder: name.ends_with('/'),
+        name,
+        size,
+        compressed_size,
+        modified: dos_timestamp(time, date),
+        method,
+        flags,
+        crc,
+        offset,
+    })
+}
+
+/// Opens the content of `member`, inflated when it is deflated. Only the member's bytes are
+/// read from storage. The reader fails if the content does not match the recorded size and
+/// CRC, so a directory that understates sizes cannot inflate past them.
+pub async fn open_zip_member(
+    state: &AppState,
+    object: &StoredObject,
+    member: &ZipMember,
+) -> Result<ObjectBody, ArchiveError> {
+    if member.flags & 1 != 0 {
+        return Err(ArchiveError::Unsupported(format!(
+            "{} is encrypted",
+            member.name
+        )));
+    }
+    if member.method == METHOD_STORED && member.compressed_size != member.size {
+        return Err(invalid("stored entry with differing sizes"));
+    }
+    if member.method != METHOD_STORED && member.method != METHOD_DEFLATED {
+        return Err(ArchiveError::Unsupported(format!(
+            "compression method {} of {}",
+            member.method, member.name
+        )));
+    }
+
+    let size = object.size as u64;
+    let header_end = checked_end(member.offset, LOCAL_HEADER_SIZE)?;
+    if header_end > size {
+        return Err(truncated());
+    }
+    let header = read_range(state, object, member.offset, header_end - 1).await?;
+    if le(&header, 0, 4) != Some(LOCAL_HEADER_SIGNATURE as u64) {
+        return Err(invalid("broken local header"));
+    }
+    let data_start = checked_end(
+        header_end,
+        le(&header, 26, 2).ok_or_else(truncated)? + le(&header, 28, 2).ok_or_else(truncated)?,
+    )?;
+    let data_end = checked_end(data_start, member.compressed_size)?;
+    if data_end > size {
+        return Err(truncated());
+    }
+
+    let body: ObjectBody = match member.compressed_size {
+        0 => Box::pin(empty()),
+        _ => open_object_range(state, object, data_start, data_end - 1).await?,
+    };
+    let body: ObjectBody = match member.method {
+        METHOD_DEFLATED => Box::pin(DeflateDecoder::new(BufReader::new(body))),
+        _ => body,
+    };
+    Ok(Box::pin(CheckedReader {
+        inner: body,
+        remaining: member.size,
+        hasher: Hasher::new(),
+        crc: member.crc,
+    }))
+}
+
+/// Refuses archives whose extraction would write too much: too many entries, too many bytes
+/// in total, or an entry inflating past `MAX_COMPRESSION_RATIO` times its compressed size.
+pub fn check_extraction_limits(members: &[ZipMember]) -> Result<(), ArchiveError> {
+    if members.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(ArchiveError::LimitExceeded(format!(
+            "{} entries",
+            members.len()
+        )));
+    }
+    let total = members
+        .iter()
+        .try_fold(0u64, |total, member| total.checked_add(member.size))
+        .unwrap_or(u64::MAX);
+    if total > MAX_EXTRACTED_SIZE {
+        return Err(ArchiveError::LimitExceeded(format!(
+            "{} bytes uncompressed",
+            total
+        )));
+    }
+    // * Tiny entries compress well by nature, so ratios count at least 1 KiB compressed
+    if let Some(member) = members
+        .iter()
+        .find(|member| member.size / MAX_COMPRESSION_RATIO > member.compressed_size.max(1024))
+    {
+        return Err(ArchiveError::LimitExceeded(format!(
+            "{} inflates from {} to {} bytes",
+            member.name, member.compressed_size, member.size
+        )));
+    }
+    Ok(())
+}
+
+/// Fails reads past `remaining` bytes, and an end of input that comes early or with a
+/// different CRC than recorded.
+struct CheckedReader {
+    inner: ObjectBody,
+    remaining: u64,
+    hasher: Hasher,
+    crc: u32,
+}
+
+impl AsyncRead for CheckedReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(this.inner.as_mut().poll_read(cx, buf))?;
+        let read = &buf.filled()[filled..];
+
+        if read.is_empty() {
+            if this.remaining != 0 {
+                return Poll::Ready(Err(invalid_data("entry is shorter than recorded")));
+            }
+            if this.hasher.clone().finalize() != this.crc {
+                return Poll::Ready(Err(invalid_data("entry does not match its CRC")));
+            }
+        } else {
+            if read.len() as u64 > this.remaining {
+                return Poll::Ready(Err(invalid_data("entry is longer than recorded")));
+            }
+            this.remaining -= read.len() as u64;
+            this.hasher.update(read);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+async fn read_range(
+    state: &AppState,
+    object: &StoredObject,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>, ArchiveError> {
+    let length = end
+        .checked_sub(start)
+        .ok_or_else(|| invalid("range ends before it starts"))?;
+    let mut body = open_object_range(state, object, start, end).await?;
+    // * The largest read is a central directory, which is capped before it is fetched
+    let mut bytes =
+        Vec::with_capacity(length.saturating_add(1).min(MAX_ZIP_DIRECTORY_SIZE) as usize);
+    body.read_to_end(&mut bytes)
+        .await
+        .map_err(StorageError::from)?;
+    Ok(bytes)
+}
+
+/// Little endian value of `width` bytes at `at`, `None` past the end of `bytes`.
+fn le(bytes: &[u8], at: usize, width: usize) -> Option<u64> {
+    let field = bytes.get(at..at.checked_add(width)?)?;
+    Some(
+        field
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | *byte as u64),
+    )
+}
+
+/// Timestamp of an MS-DOS `(time, date)` pair read as UTC, `None` when it is no valid date.
+fn dos_timestamp(time: u16, date: u16) -> Option<Timestamp> {
+    DateTime::new(
+        (date >> 9) as i16 + 1980,
+        (date >> 5 & 0xF) as i8,
+        (date & 0x1F) as i8,
+        (time >> 11) as i8,
+        (time >> 5 & 0x3F) as i8,
+        (time & 0x1F) as i8 * 2,
+        0,
+    )
+    .ok()?
+    .to_zoned(TimeZone::UTC)
+    .ok()
+    .map(|zoned| zoned.timestamp())
+}
+
+/// End of `length` bytes from `start`, failing for offsets or sizes no archive can have.
+fn checked_end(start: u64, length: u64) -> Result<u64, ArchiveError> {
+    start
+        .checked_add(length)
+        .ok_or_else(|| invalid("offset or size past the largest archive"))
+}
+
+fn invalid(reason: &str) -> ArchiveError {
+    ArchiveError::Invalid(reason.to_string())
+}
+
+fn truncated() -> ArchiveError {
+    invalid("truncated archive")
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}